@@ -1,173 +1,2799 @@
 use std::{
-  collections::BTreeMap,
+  collections::{BTreeMap, BTreeSet},
+  fmt::{self, Write as _},
   fs,
+  io::Write,
+  mem,
   path::{Path, PathBuf},
   process::Command,
+  sync::Mutex,
   time::Duration,
 };
 
 use anyhow::{Context, Result};
-use tempfile::TempDir;
+use tempfile::{NamedTempFile, TempDir};
 
 use crate::{
+  environment::Environment,
   ext::CommandExt,
-  run,
-  stats::{Program, Stats},
+  format,
+  jobs::{self, Jobs},
+  pick::{self, Candidate},
+  programs::{self, Invocation},
+  progress::Progress,
+  run::{self, Compiled, RunError},
+  stats::{self, Aggregate, Mode, Program, Runs, Stats, Timing, Warning},
 };
 
-const GIT_URL: &str = "https://github.com/HigherOrderCO/hvm.git";
-const PROGRAMS_DIR: &str = "./programs";
+pub const GIT_URL: &str = "https://github.com/HigherOrderCO/hvm.git";
+/// Default seconds a clone or checkout may take before it's considered hung.
+pub const GIT_TIMEOUT: u64 = 300;
+/// Default most runs of a program in a mode when running until stable.
+pub const MAX_RUNS: usize = 30;
+/// Fewest runs of a cell before it may stop early, see [`StopEarly`].
+const STOP_EARLY_MIN_RUNS: usize = 2;
+const MERGE_BASE_PREFIX: &str = "merge-base:";
+/// Shortest abbreviated hash git accepts.
+const MIN_ABBREV_LEN: usize = 4;
+/// Number of recent commits offered by the interactive picker.
+const PICKABLE_COMMITS: usize = 200;
+/// Load average per cpu above which the machine is considered busy.
+const HIGH_LOAD_PER_CPU: f64 = 0.5;
+/// Fraction of the timeout above which a run is considered close to it.
+const NEAR_TIMEOUT_RATIO: f64 = 0.8;
+/// Seconds, and fraction of the reported time, by which the wall-clock time of
+/// a run may differ from the reported time before it is considered suspicious.
+const WALL_CLOCK_TOLERANCE: f64 = 0.5;
+const WALL_CLOCK_TOLERANCE_RATIO: f64 = 0.5;
+/// Coefficient of variation of repeated runs above which their timings are
+/// considered unreliable.
+const HIGH_VARIANCE: f64 = 0.1;
+pub const PROGRAMS_DIR: &str = "./programs";
+/// Follows the commit of a local repo with uncommitted changes.
+pub const DIRTY_SUFFIX: &str = "-dirty";
+
+/// Where the benchmarked programs come from.
+pub enum ProgramsSource {
+  /// A directory shared by all revisions.
+  Dir(PathBuf),
+  /// This path inside each revision's own checkout, so that each revision runs
+  /// its contemporaneous programs.
+  EachRev(PathBuf),
+  /// This path inside a single revision's checkout, shared by all revisions.
+  FixedRev(String, PathBuf),
+}
+
+/// Which programs are benchmarked.
+pub struct Programs {
+  pub source: ProgramsSource,
+  /// Names of the programs to benchmark. If empty, all programs are.
+  pub only: Vec<String>,
+  /// Names of programs not to benchmark.
+  pub exclude: Vec<String>,
+  /// Extensions of the files that are programs, e.g. `hvm`.
+  pub extensions: Vec<String>,
+  /// Only benchmark this many of the selected programs, the smallest ones.
+  pub sample: Option<usize>,
+  /// Program files benchmarked besides the selected ones, e.g. a program
+  /// being written outside the programs directory, named after their file
+  /// name without extension. They aren't subject to `only`, `exclude` or
+  /// `sample`.
+  pub files: Vec<PathBuf>,
+  /// Only benchmark `files`, not the programs of the source.
+  pub files_only: bool,
+}
+
+impl Default for Programs {
+  fn default() -> Self {
+    Self {
+      source: ProgramsSource::Dir(PathBuf::from(PROGRAMS_DIR)),
+      only: Vec::new(),
+      exclude: Vec::new(),
+      extensions: programs::EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+      sample: None,
+      files: Vec::new(),
+      files_only: false,
+    }
+  }
+}
+
+impl Programs {
+  /// Returns the invocations of the selected programs in `dir` and of the
+  /// program files, failing if there are none, a program named by the
+  /// selection isn't there, or a program file is named like another program.
+  fn select(&self, dir: &Path) -> Result<Vec<Invocation>> {
+    let mut programs = match self.files_only {
+      true => BTreeMap::new(),
+      false => programs::by_name(dir, &self.extensions).context("programs")?,
+    };
+
+    if programs.is_empty() && self.files.is_empty() {
+      anyhow::bail!(
+        "no programs found in {dir:?}, i.e. no files ending in .{}; is it the right directory?",
+        self.extensions.join(" or .")
+      );
+    }
+
+    if let Some(missing) = self
+      .only
+      .iter()
+      .chain(&self.exclude)
+      .find(|name| !programs.contains_key(*name))
+    {
+      let available = programs.keys().cloned().collect::<Vec<_>>();
+      anyhow::bail!(
+        "no program {missing:?} in {dir:?}, available programs: {}",
+        available.join(", ")
+      );
+    }
+
+    programs.retain(|name, _| (self.only.is_empty() || self.only.contains(name)) && !self.exclude.contains(name));
+
+    if let Some(sample) = self.sample {
+      // by size, then by name, so that the sample is the same on every run
+      let mut by_size = Vec::new();
+      for (name, path) in &programs {
+        let size = fs::metadata(path).with_context(|| format!("metadata {path:?}"))?.len();
+        by_size.push((size, name.clone()));
+      }
+      by_size.sort();
+
+      let sampled = by_size
+        .into_iter()
+        .take(sample)
+        .map(|(_, name)| name)
+        .collect::<BTreeSet<_>>();
+      programs.retain(|name, _| sampled.contains(name));
+    }
+
+    for file in &self.files {
+      let name = file.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+      if let Some(other) = programs.insert(name.clone(), file.clone()) {
+        anyhow::bail!("program file {file:?} is named {name:?}, like {other:?}");
+      }
+    }
+
+    let mut invocations = Vec::new();
+    for (name, path) in programs {
+      let category = programs::category(dir, &path);
+      invocations
+        .extend(programs::invocations(&name, &category, &path).with_context(|| format!("invocations of {name}"))?);
+    }
+
+    Ok(invocations)
+  }
+}
+
+/// How remote revisions are built.
+pub struct BuildOptions {
+  /// Url of the repo the remote revisions are cloned from.
+  pub repo_url: String,
+  /// Number of revisions built at once.
+  pub jobs: usize,
+  /// Most commits a revision range or `since` may expand to.
+  pub max_revs: usize,
+  /// Also benchmark every commit on the default branch committed since this
+  /// date, e.g. `7d` or `2024-01-01`, in any format `git log --since` takes.
+  pub since: Option<String>,
+  /// Directory keeping built binaries across invocations, keyed by commit
+  /// hash.
+  pub cache_dir: Option<PathBuf>,
+  /// Build revisions even if they are cached, replacing the cached binaries.
+  pub rebuild: bool,
+  /// Directory keeping cargo's build artifacts across invocations, in a
+  /// subdirectory per revision, so that rebuilding a revision after it moved
+  /// to a nearby commit only recompiles what changed.
+  pub target_dir: Option<PathBuf>,
+  /// Benchmark the other revisions if one fails to build, marking its cells
+  /// as build errors, instead of failing.
+  pub keep_going: bool,
+  /// How long cloning the repo or checking out a revision may take, so that
+  /// a network hang fails instead of waiting forever.
+  pub git_timeout: Duration,
+  /// Clone the file contents of every commit, instead of only the history and
+  /// fetching the contents of the revisions that are checked out.
+  pub full_clone: bool,
+}
+
+impl Default for BuildOptions {
+  fn default() -> Self {
+    Self {
+      repo_url: GIT_URL.to_string(),
+      jobs: 1,
+      max_revs: 20,
+      since: None,
+      cache_dir: None,
+      rebuild: false,
+      target_dir: None,
+      keep_going: false,
+      git_timeout: Duration::from_secs(GIT_TIMEOUT),
+      full_clone: false,
+    }
+  }
+}
+
+/// How each program is measured in each mode.
+pub struct Sampling {
+  /// Timeout for a single run.
+  pub timeout: Duration,
+  /// Timeout for a single run of a compiled mode, if not `timeout`.
+  pub compiled_timeout: Option<Duration>,
+  /// Timeout for a single run of an interpreted mode, if not `timeout`.
+  pub interpreted_timeout: Option<Duration>,
+  /// Number of runs, whose timings are aggregated.
+  pub runs: usize,
+  /// Keep running past `runs` until the coefficient of variation of the
+  /// completed runs is below this, e.g. `0.02`, or `max_runs` is reached.
+  pub until_stable: Option<f64>,
+  /// Most runs done with `until_stable`.
+  pub max_runs: usize,
+  /// Number of runs before the sampled ones, whose timings are discarded.
+  pub warmup: usize,
+  /// Number of times a failed run is retried.
+  pub retries: usize,
+  /// Modes to run programs in.
+  pub modes: Vec<Mode>,
+  /// How many runs may happen at once.
+  pub jobs: Jobs,
+  /// Run the CUDA modes even if CUDA doesn't seem to be available.
+  pub force_cuda: bool,
+  /// Stop sampling a cell before `runs` once it clearly compares with the
+  /// same cell of a baseline revision.
+  pub stop_early: Option<StopEarly>,
+}
+
+/// Stops sampling a cell of a revision once the confidence interval of its
+/// mean time clearly separates from the baseline's, or clearly stays within
+/// `threshold` of it, e.g. when gating CI on regressions, where the verdict
+/// is often obvious after a couple of runs. The baseline is benchmarked
+/// first, and in full.
+#[derive(Clone, Debug)]
+pub struct StopEarly {
+  /// Revision the others are compared to.
+  pub baseline: String,
+  /// Percentage by which a revision may be slower than the baseline without
+  /// regressing.
+  pub threshold: f64,
+}
+
+impl Default for Sampling {
+  fn default() -> Self {
+    Self {
+      timeout: Duration::from_secs(60),
+      compiled_timeout: None,
+      interpreted_timeout: None,
+      runs: 1,
+      until_stable: None,
+      max_runs: MAX_RUNS,
+      warmup: 0,
+      retries: 0,
+      modes: Mode::ALL.to_vec(),
+      jobs: Jobs::default(),
+      force_cuda: false,
+      stop_early: None,
+    }
+  }
+}
+
+impl Sampling {
+  /// Returns whether `timings` are enough, either as many as asked for or,
+  /// with [`Self::until_stable`], stable enough. With [`Self::stop_early`],
+  /// they're also enough once they settle how they compare with the
+  /// `baseline` timings of the same program and mode.
+  fn enough(&self, timings: &[Timing], baseline: Option<&[Timing]>) -> bool {
+    if baseline.is_some_and(|baseline| self.settled(timings, baseline)) {
+      return true;
+    }
+    if timings.len() < self.runs {
+      return false;
+    }
+    let Some(target) = self.until_stable else {
+      return true;
+    };
+    if timings.len() >= self.max_runs {
+      return true;
+    }
+
+    // runs that didn't complete have no timing to settle
+    let seconds = timings.iter().filter_map(Timing::seconds).collect::<Vec<_>>();
+    seconds.len() < timings.len() || stats::variation(&seconds).is_some_and(|(_, coefficient)| coefficient < target)
+  }
+
+  /// Returns whether the confidence interval of `timings` is clearly apart
+  /// from the one of `baseline`, or clearly within the threshold of
+  /// [`Self::stop_early`] of it. Only runs that all completed are compared.
+  fn settled(&self, timings: &[Timing], baseline: &[Timing]) -> bool {
+    let Some(stop_early) = &self.stop_early else {
+      return false;
+    };
+    if timings.len() < STOP_EARLY_MIN_RUNS {
+      return false;
+    }
+
+    let interval = |timings: &[Timing]| {
+      let seconds = timings.iter().map(Timing::seconds).collect::<Option<Vec<_>>>()?;
+      stats::confidence_interval(&seconds)
+    };
+    let (Some((low, high)), Some((base_low, base_high))) = (interval(timings), interval(baseline)) else {
+      return false;
+    };
+
+    low > base_high || high < base_low * (1.0 + stop_early.threshold / 100.0)
+  }
+
+  /// Returns the timeout for a single run in `mode`.
+  fn timeout(&self, mode: Mode) -> Duration {
+    let timeout = if mode.is_compiled() {
+      self.compiled_timeout
+    } else {
+      self.interpreted_timeout
+    };
+
+    timeout.unwrap_or(self.timeout)
+  }
+}
+
+/// Where results are written as JSON whenever a program finishes in every
+/// mode, so that a crash doesn't lose the results so far.
+pub struct PartialOutput {
+  pub path: PathBuf,
+  /// Tags written with the results.
+  pub tags: Vec<(String, String)>,
+}
+
+/// What a [`Bench`] reports as it goes, for [`BenchBuilder::on_event`] to
+/// show.
+pub enum Event<'a> {
+  /// `program` finished in `mode` on `rev` with `runs`, or was given the
+  /// runs of an identical binary or a build error.
+  Finished {
+    rev: &'a str,
+    program: &'a str,
+    mode: Mode,
+    runs: &'a Runs,
+  },
+  /// Something failed without stopping the benchmark, e.g. a build with
+  /// `--keep-going` or writing the partial results.
+  Failed(String),
+}
+
+/// Called with each [`Event`] of a [`Bench`].
+type OnEvent = Box<dyn Fn(Event) + Send + Sync>;
+
+/// A profile of a program on a revision, written by [`Bench::profile`].
+pub struct Profile {
+  pub rev: String,
+  /// Where `perf record` wrote the profile.
+  pub output: PathBuf,
+  /// The top of `perf report`, i.e. the hottest symbols.
+  pub summary: String,
+}
 
 pub struct Bench {
-  /// Local hvm directory.
-  local_dir: PathBuf,
-  /// Remote revisions.
+  /// Label and directory of each local hvm repo.
+  locals: Vec<(String, PathBuf)>,
+  /// Remote revisions. Ranges such as `v1.0..v2.0` are expanded into their
+  /// commits after cloning.
   remote_revs: Vec<String>,
-  /// Timeout for runs.
-  timeout: Duration,
+  /// Label and path of each prebuilt hvm binary. When there are any, nothing
+  /// is cloned or built.
+  prebuilt: Vec<(String, PathBuf)>,
+  /// How programs are measured.
+  sampling: Sampling,
+  /// Which programs are benchmarked, and where they are read from.
+  programs: Programs,
+  /// Settings for building and running hvm.
+  run_options: run::Options,
+  /// Whether to let the user pick more remote revisions after cloning.
+  interactive: bool,
+  /// How remote revisions are built.
+  build_options: BuildOptions,
+  /// Whether to reuse the results of the previously benchmarked revision when
+  /// a binary is byte-identical to it.
+  skip_identical: bool,
+  /// Whether the CUDA modes are skipped because CUDA isn't available.
+  skip_cuda: bool,
+  /// Remote revisions that failed to build, and why.
+  failed_builds: Vec<(String, String)>,
+  /// Remote revisions that resolved to the same commit as an earlier one, and
+  /// the earlier one. Each commit is built and benchmarked once, and its
+  /// results shown under every label.
+  aliases: Vec<(String, String)>,
+  /// Commit hash each remote revision resolved to.
+  pub commits: BTreeMap<String, String>,
+  /// When each remote revision was committed, in seconds since the epoch.
+  commit_times: BTreeMap<String, u64>,
+  /// Version in the `Cargo.toml` of each built revision, that its binary
+  /// should report.
+  expected_versions: BTreeMap<String, String>,
   /// Statistics collected for each revision.
   pub stats: BTreeMap<String, Stats>,
+  /// Warnings collected while benchmarking.
+  pub warnings: Vec<Warning>,
+  /// The machine the benchmark ran on, gathered before building.
+  pub environment: Option<Environment>,
+  /// Where partial results are written, if anywhere.
+  partial_output: Option<PartialOutput>,
+  /// Called with each result as soon as it's known, and with failures that
+  /// don't stop the benchmark.
+  on_event: OnEvent,
   /// Temporary directory for binaries and remote repo.
   tempdir: TempDir,
 }
 
-impl Bench {
-  pub fn new(local_dir: PathBuf, remote_revs: Vec<String>, timeout: Duration) -> Result<Self> {
+/// Configures and validates a [`Bench`]. Everything is optional: by default
+/// nothing is benchmarked, in every mode, once, on the programs in
+/// [`PROGRAMS_DIR`].
+#[derive(Default)]
+pub struct BenchBuilder {
+  local_dirs: Vec<PathBuf>,
+  remote_revs: Vec<String>,
+  prebuilt: Vec<(String, PathBuf)>,
+  sampling: Sampling,
+  programs: Programs,
+  skip_identical: bool,
+  run_options: run::Options,
+  interactive: bool,
+  build_options: BuildOptions,
+  partial_output: Option<PartialOutput>,
+  on_event: Option<OnEvent>,
+}
+
+impl BenchBuilder {
+  /// Adds local hvm repos to benchmark, each labeled by its directory name,
+  /// or `local` if there's only one.
+  pub fn local_dirs<I: IntoIterator<Item = PathBuf>>(mut self, local_dirs: I) -> Self {
+    self.local_dirs.extend(local_dirs);
+    self
+  }
+
+  /// Adds revisions of the remote repo to benchmark. Ranges such as
+  /// `v1.0..v2.0` are expanded into their commits after cloning.
+  pub fn revs<I: IntoIterator<Item = String>>(mut self, revs: I) -> Self {
+    self.remote_revs.extend(revs);
+    self
+  }
+
+  /// Adds prebuilt hvm binaries to benchmark, each with its label. When there
+  /// are any, nothing is cloned or built.
+  pub fn binaries<I: IntoIterator<Item = (String, PathBuf)>>(mut self, binaries: I) -> Self {
+    self.prebuilt.extend(binaries);
+    self
+  }
+
+  /// Sets how programs are measured, replacing [`Self::timeout`],
+  /// [`Self::runs`] and [`Self::modes`].
+  pub fn sampling(mut self, sampling: Sampling) -> Self {
+    self.sampling = sampling;
+    self
+  }
+
+  /// Sets the timeout for a single run in every mode.
+  pub fn timeout(mut self, timeout: Duration) -> Self {
+    self.sampling.timeout = timeout;
+    self
+  }
+
+  /// Sets the number of runs of each program in each mode.
+  pub fn runs(mut self, runs: usize) -> Self {
+    self.sampling.runs = runs;
+    self
+  }
+
+  /// Sets the modes to run programs in.
+  pub fn modes(mut self, modes: Vec<Mode>) -> Self {
+    self.sampling.modes = modes;
+    self
+  }
+
+  /// Sets which programs are benchmarked, replacing [`Self::programs_dir`].
+  pub fn programs(mut self, programs: Programs) -> Self {
+    self.programs = programs;
+    self
+  }
+
+  /// Reads the programs from `dir`, shared by all revisions.
+  pub fn programs_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+    self.programs.source = ProgramsSource::Dir(dir.into());
+    self
+  }
+
+  /// Reuses the results of the previously benchmarked revision when a binary
+  /// is byte-identical to it.
+  pub fn skip_identical(mut self, skip_identical: bool) -> Self {
+    self.skip_identical = skip_identical;
+    self
+  }
+
+  pub fn run_options(mut self, run_options: run::Options) -> Self {
+    self.run_options = run_options;
+    self
+  }
+
+  /// Lets the user pick more remote revisions after cloning.
+  pub fn interactive(mut self, interactive: bool) -> Self {
+    self.interactive = interactive;
+    self
+  }
+
+  pub fn build_options(mut self, build_options: BuildOptions) -> Self {
+    self.build_options = build_options;
+    self
+  }
+
+  /// Writes the results so far to `partial_output`, if any, whenever a
+  /// program finishes in every mode.
+  pub fn partial_output(mut self, partial_output: Option<PartialOutput>) -> Self {
+    self.partial_output = partial_output;
+    self
+  }
+
+  /// Calls `on_event` with each result as soon as it's known, and with
+  /// failures that don't stop the benchmark. They're ignored by default.
+  pub fn on_event<F: Fn(Event) + Send + Sync + 'static>(mut self, on_event: F) -> Self {
+    self.on_event = Some(Box::new(on_event));
+    self
+  }
+
+  /// Checks the configuration, failing if a local repo, binary, or programs
+  /// directory doesn't exist, and creates the bench with its temporary
+  /// directory.
+  pub fn build(self) -> Result<Bench> {
+    for local_dir in &self.local_dirs {
+      if !local_dir.exists() {
+        anyhow::bail!("{local_dir:?} does not exist");
+      }
+    }
+    for (i, (name, binary)) in self.prebuilt.iter().enumerate() {
+      if !binary.is_file() {
+        anyhow::bail!("binary {binary:?} does not exist");
+      }
+      if self.prebuilt[..i].iter().any(|(other, _)| other == name) {
+        anyhow::bail!("several binaries are named {name:?}");
+      }
+    }
+    if let ProgramsSource::Dir(dir) = &self.programs.source {
+      if !dir.is_dir() && !self.programs.files_only {
+        anyhow::bail!("programs directory {dir:?} does not exist");
+      }
+    }
+    if let Some(file) = self.programs.files.iter().find(|file| !file.is_file()) {
+      anyhow::bail!("program file {file:?} does not exist");
+    }
+    if self.sampling.runs == 0 {
+      anyhow::bail!("programs must be run at least once");
+    }
+    if self.sampling.until_stable.is_some() && self.sampling.max_runs < self.sampling.runs {
+      anyhow::bail!(
+        "at most {} runs, fewer than the {} runs asked for",
+        self.sampling.max_runs,
+        self.sampling.runs
+      );
+    }
+    if self.sampling.modes.is_empty() {
+      anyhow::bail!("no modes to run programs in");
+    }
+
     let tempdir = TempDir::with_prefix("hvm-bench-").context("tempdir")?;
 
     fs::create_dir(tempdir.path().join("repo")).context("create_dir repo")?;
     fs::create_dir(tempdir.path().join("bin")).context("create_dir bin")?;
+    fs::create_dir(tempdir.path().join("programs")).context("create_dir programs")?;
 
-    Ok(Self {
-      local_dir,
-      remote_revs,
-      timeout,
+    let locals = local_labels(&self.local_dirs)?
+      .into_iter()
+      .zip(self.local_dirs)
+      .collect();
+
+    Ok(Bench {
+      locals,
+      remote_revs: self.remote_revs,
+      prebuilt: self.prebuilt,
+      sampling: self.sampling,
+      programs: self.programs,
+      skip_identical: self.skip_identical,
+      run_options: self.run_options,
+      interactive: self.interactive,
+      build_options: self.build_options,
+      skip_cuda: false,
+      failed_builds: Vec::new(),
+      aliases: Vec::new(),
+      commits: BTreeMap::new(),
+      commit_times: BTreeMap::new(),
+      expected_versions: BTreeMap::new(),
       stats: BTreeMap::new(),
+      warnings: Vec::new(),
+      environment: None,
+      partial_output: self.partial_output,
+      on_event: self.on_event.unwrap_or_else(|| Box::new(|_| {})),
       tempdir,
     })
   }
+}
+
+impl Bench {
+  pub fn builder() -> BenchBuilder {
+    BenchBuilder::default()
+  }
 
   pub fn bench(&mut self) -> Result<()> {
-    self.clone_remote().context("clone")?;
-    self.build_all().context("build all")?;
+    // fail before building if the selection can already be checked
+    if let ProgramsSource::Dir(dir) = &self.programs.source {
+      self.programs.select(dir)?;
+    }
+
+    self.environment = Some(Environment::detect(&self.run_options));
+    self.check_environment();
+    if self.prebuilt.is_empty() {
+      self.clone_and_build().context("clone and build")?;
+    }
     self.bench_all().context("bench all")?;
 
     Ok(())
   }
 
-  fn build_all(&self) -> Result<()> {
-    self.cargo_build(&self.local_dir).context("cargo build local")?;
-    fs::rename(
-      self.local_dir.join("target/release/hvm"),
-      self.bin_dir().join("local_hvm"),
-    )
-    .context("rename local")?;
-
+  /// Returns the revisions that would be built, and the commands that would be
+  /// run for each program and mode, without cloning, building or running
+  /// anything.
+  pub fn plan(&self) -> Result<String> {
+    let mut plan = String::new();
+    writeln!(plan, "revisions:")?;
     for rev in &self.remote_revs {
-      let bin_rev_dir = self.bin_dir().join(rev);
-      fs::create_dir(&bin_rev_dir).context("create dir")?;
+      if rev.contains("..") {
+        writeln!(plan, "  {rev} (expanded into its commits after cloning)")?;
+      } else {
+        writeln!(plan, "  {rev}")?;
+      }
+    }
+    if let Some(since) = &self.build_options.since {
+      writeln!(
+        plan,
+        "  the commits on the default branch since {since} (listed after cloning)"
+      )?;
+    }
+    if self.interactive {
+      writeln!(plan, "  and the ones picked after cloning")?;
+    }
+    for (label, local_dir) in &self.locals {
+      writeln!(plan, "  {label} {local_dir:?}")?;
+    }
+    for (label, binary) in &self.prebuilt {
+      writeln!(plan, "  {label} {binary:?} (prebuilt)")?;
+    }
 
-      let binary = bin_rev_dir.join("hvm");
+    let (programs, read_from) = match &self.programs.source {
+      ProgramsSource::Dir(dir) => (self.programs.select(dir)?, format!("{dir:?}")),
+      ProgramsSource::EachRev(path) => (Vec::new(), format!("{path:?} in each revision")),
+      ProgramsSource::FixedRev(rev, path) => (Vec::new(), format!("{path:?} in {rev}")),
+    };
+    writeln!(plan, "programs, from {read_from}:")?;
+    if programs.is_empty() {
+      writeln!(plan, "  listed after cloning")?;
+    }
+    for program in &programs {
+      writeln!(plan, "  {}", program.name)?;
+    }
 
-      self.checkout_remote(rev).with_context(|| format!("checkout {rev}"))?;
-      self
-        .cargo_build(self.remote_repo_dir())
-        .with_context(|| format!("cargo build remote {rev}"))?;
+    match self.sampling.until_stable {
+      Some(target) => writeln!(
+        plan,
+        "runs: {} to {} per program and mode, until they vary by less than {}%, after {} warmup runs",
+        self.sampling.runs,
+        self.sampling.max_runs,
+        target * 100.0,
+        self.sampling.warmup
+      )?,
+      None => writeln!(
+        plan,
+        "runs: {} per program and mode, after {} warmup runs",
+        self.sampling.runs, self.sampling.warmup
+      )?,
+    }
+    if let Some(stop_early) = &self.sampling.stop_early {
+      writeln!(
+        plan,
+        "stopping early once clearly apart from {} or within {}% of it",
+        stop_early.baseline, stop_early.threshold
+      )?;
+    }
+
+    let hvm = Path::new("hvm");
+    for program in &programs {
+      for &mode in &self.sampling.modes {
+        writeln!(
+          plan,
+          "{} in {mode}, timeout {:?}:",
+          program.name,
+          self.sampling.timeout(mode)
+        )?;
+        for command in run::commands(hvm, mode, &program.path, &program.args, &self.run_options) {
+          writeln!(plan, "  {command}")?;
+        }
+      }
+    }
 
-      fs::rename(self.remote_repo_dir().join("target/release/hvm"), &binary).context("rename remote")?;
+    Ok(plan)
+  }
+
+  /// Instead of benchmarking, runs `program` under `perf record` for every
+  /// revision, writing the profiles to `out_dir`, and returns them with a
+  /// summary of the hottest symbols.
+  pub fn profile(&mut self, program: &Path, mode: Mode, out_dir: &Path) -> Result<Vec<Profile>> {
+    anyhow::ensure!(self.is_available("perf"), "perf is not available");
+
+    self.clone_and_build().context("clone and build")?;
+    fs::create_dir_all(out_dir).context("create dir")?;
+
+    let mut profiles = Vec::new();
+    for (rev, bin, _) in self.binaries() {
+      let output = out_dir.join(format!("{}.perf.data", file_name(&rev)));
+
+      self.info(format_args!("profiling {rev:?} into {output:?}"));
+      let summary = run::profile(
+        &bin,
+        mode,
+        program,
+        &output,
+        self.sampling.timeout(mode),
+        &self.run_options,
+      )
+      .with_context(|| format!("profile {rev}"))?;
+
+      profiles.push(Profile { rev, output, summary });
+    }
+
+    Ok(profiles)
+  }
+
+  fn clone_and_build(&mut self) -> Result<()> {
+    self.clone_remote().context("clone")?;
+
+    if self.interactive {
+      let picked = pick::pick(&self.recent_commits().context("recent commits")?).context("pick")?;
+
+      // abbreviated, so that they fit in the table
+      let picked = picked.into_iter().map(|hash| hash.chars().take(12).collect::<String>());
+      self.remote_revs.extend(picked);
     }
 
+    self.validate_revs()?;
+    self.expand_since().context("expand since")?;
+    self.expand_ranges().context("expand ranges")?;
+    self.build_all().context("build all")?;
+
     Ok(())
   }
 
-  fn bench_all(&mut self) -> Result<()> {
-    for rev in self.remote_revs.clone() {
-      self
-        .bench_bin(&rev, self.bin_dir().join(&rev).join("hvm"))
-        .with_context(|| format!("bench {rev}"))?;
+  /// Checks that every remote revision, both ends of every range, and the
+  /// revision the programs are taken from exist, so that a typo fails before
+  /// anything is built. All the invalid ones are reported together.
+  fn validate_revs(&self) -> Result<()> {
+    let mut revs = Vec::new();
+    for rev in &self.remote_revs {
+      match rev.split_once("..") {
+        Some((from, to)) => revs.extend([from, to]),
+        None => revs.push(rev.as_str()),
+      }
+    }
+    if let ProgramsSource::FixedRev(rev, _) = &self.programs.source {
+      revs.push(rev);
     }
 
-    self
-      .bench_bin("(local)", self.bin_dir().join("local_hvm"))
-      .context("bench local")?;
+    let invalid = revs
+      .into_iter()
+      .filter_map(|rev| self.resolve_rev(rev).err().map(|err| format!("{rev}: {err:#}")))
+      .collect::<Vec<_>>();
+    if !invalid.is_empty() {
+      anyhow::bail!("invalid revisions:\n  {}", invalid.join("\n  "));
+    }
 
     Ok(())
   }
 
-  fn bench_bin<P: AsRef<Path>>(&mut self, rev: &str, bin: P) -> Result<()> {
-    eprintln!("benchmarking {rev:?}");
-    for program in self.programs().context("programs")? {
-      eprintln!("  running {program:?}");
+  /// Adds the commits on the default branch since `since` of the build
+  /// options to `remote_revs`, oldest first.
+  fn expand_since(&mut self) -> Result<()> {
+    let Some(since) = &self.build_options.since else {
+      return Ok(());
+    };
 
-      let program_name = program.file_stem().context("file stem")?.to_string_lossy().into_owned();
+    let commits = self
+      .git()
+      .args(["rev-list", "--reverse", "--date-order"])
+      .arg(format!("--since={}", git_date(since)))
+      .arg("origin/HEAD")
+      .status_stdout()
+      .context("status stdout")?;
 
-      self.stats.entry(rev.to_string()).or_default().programs.insert(
-        program_name,
-        Program {
-          interpreted_c: run::interpreted_c(&bin, &program, self.timeout),
-          interpreted_cuda: run::interpreted_cuda(&bin, &program, self.timeout),
-          interpreted_rust: run::interpreted_rust(&bin, &program, self.timeout),
-          compiled_c: Ok(run::compiled_c(&bin, &program, self.timeout).unwrap()),
-          compiled_cuda: run::compiled_cuda(&bin, &program, self.timeout),
-        },
+    let commits = commits.lines().collect::<Vec<_>>();
+    if commits.is_empty() {
+      anyhow::bail!("no commits on the default branch since {since:?}");
+    }
+    if commits.len() > self.build_options.max_revs {
+      anyhow::bail!(
+        "{} commits on the default branch since {since:?}, more than --max-revs {}",
+        commits.len(),
+        self.build_options.max_revs
       );
     }
 
+    // abbreviated, so that they fit in the table
+    self.remote_revs.extend(
+      commits
+        .into_iter()
+        .map(|hash| hash.chars().take(12).collect::<String>()),
+    );
+
     Ok(())
   }
 
-  fn programs(&self) -> Result<Vec<PathBuf>> {
-    fs::read_dir(PROGRAMS_DIR)
-      .context("read dir")?
-      .map(|entry| Ok(entry?.path()))
-      .collect()
-  }
+  /// Replaces every revision range in `remote_revs` with its commits, oldest
+  /// first.
+  fn expand_ranges(&mut self) -> Result<()> {
+    let mut revs = Vec::new();
+    for rev in mem::take(&mut self.remote_revs) {
+      let Some((from, to)) = rev.split_once("..") else {
+        revs.push(rev);
+        continue;
+      };
 
-  fn remote_repo_dir(&self) -> PathBuf {
-    self.tempdir.path().join("repo")
-  }
+      let from = self.resolve_rev(from).with_context(|| format!("resolve {from}"))?;
+      let to = self.resolve_rev(to).with_context(|| format!("resolve {to}"))?;
+      let commits = self
+        .git()
+        .args(["rev-list", "--reverse", "--topo-order"])
+        .arg(format!("{from}..{to}"))
+        .status_stdout()
+        .context("status stdout")?;
 
-  fn bin_dir(&self) -> PathBuf {
-    self.tempdir.path().join("bin")
-  }
+      let commits = commits.lines().collect::<Vec<_>>();
+      if commits.is_empty() {
+        anyhow::bail!("revision range {rev:?} has no commits");
+      }
+      if commits.len() > self.build_options.max_revs {
+        anyhow::bail!(
+          "revision range {rev:?} has {} commits, more than --max-revs {}",
+          commits.len(),
+          self.build_options.max_revs
+        );
+      }
 
-  fn cargo_build<P: AsRef<Path>>(&self, dir: P) -> Result<()> {
-    eprintln!("building {dir:?}", dir = dir.as_ref());
+      // abbreviated, so that they fit in the table
+      revs.extend(
+        commits
+          .into_iter()
+          .map(|hash| hash.chars().take(12).collect::<String>()),
+      );
+    }
 
-    Command::new("cargo")
-      .current_dir(dir)
-      .args(["build", "--release"])
-      .status_stdout()
-      .context("status stdout")?;
+    // a revision given twice, e.g. also within a range, is built once
+    let mut seen = BTreeSet::new();
+    revs.retain(|rev| seen.insert(rev.clone()));
+    self.remote_revs = revs;
 
     Ok(())
   }
 
-  fn clone_remote(&self) -> Result<()> {
-    self
-      .git()
-      .args(["clone", GIT_URL])
-      .arg(".")
-      .status_stdout()
-      .context("status stdout")?;
+  /// Builds every local repo and remote revision. A remote revision that
+  /// fails to build fails the whole build, unless the build options say to
+  /// keep going, in which case it's reported as a warning and not benchmarked.
+  fn build_all(&mut self) -> Result<()> {
+    // first, so that a revision without programs fails before anything is
+    // built
+    if let ProgramsSource::FixedRev(rev, path) = &self.programs.source {
+      let commit = self.resolve_rev(rev).with_context(|| format!("resolve {rev}"))?;
+      self
+        .checkout_remote(&commit)
+        .with_context(|| format!("checkout {rev}"))?;
+      copy_programs(self.remote_repo_dir().join(path), self.shared_programs_dir()).context("copy programs")?;
+      self.programs.select(&self.shared_programs_dir())?;
+    }
+
+    for (i, (label, local_dir)) in self.locals.iter().enumerate() {
+      let built = self
+        .cargo_build(local_dir, None)
+        .with_context(|| format!("cargo build {label}"))?;
+      fs::rename(built, self.local_bin(i)).with_context(|| format!("rename {label}"))?;
+      if let Some(version) = cargo_version(local_dir) {
+        self.expected_versions.insert(label.clone(), version);
+      }
+      if let Some(commit) = local_commit(local_dir) {
+        self.commits.insert(label.clone(), commit);
+      }
+    }
+
+    // worktrees share the clone's objects, so that revisions can be built
+    // concurrently
+    let mut worktrees: Vec<(String, String, PathBuf)> = Vec::new();
+    let mut aliases = Vec::new();
+    let mut failed = Vec::new();
+    for (i, rev) in self.remote_revs.iter().enumerate() {
+      let worktree = self.tempdir.path().join("worktrees").join(i.to_string());
+      let added = self
+        .resolve_rev(rev)
+        .with_context(|| format!("resolve {rev}"))
+        .and_then(|commit| {
+          if let Some((original, ..)) = worktrees.iter().find(|(_, built, _)| *built == commit) {
+            self.info(format_args!(
+              "{rev:?} is the same commit as {original:?}, building it once"
+            ));
+            aliases.push((rev.clone(), original.clone()));
+            return Ok(None);
+          }
+
+          self
+            .add_worktree(&worktree, &commit)
+            .with_context(|| format!("add worktree for {rev}"))?;
+          Ok(Some(commit))
+        });
+
+      match added {
+        Ok(Some(commit)) => worktrees.push((rev.clone(), commit, worktree)),
+        Ok(None) => {}
+        Err(err) if self.build_options.keep_going => failed.push((rev.clone(), err)),
+        Err(err) => return Err(err),
+      }
+    }
+
+    for (rev, commit, worktree) in &worktrees {
+      let time = self
+        .commit_time(commit)
+        .with_context(|| format!("commit time of {rev}"))?;
+      self.commits.insert(rev.clone(), commit.clone());
+      self.commit_times.insert(rev.clone(), time);
+      if let Some(version) = cargo_version(worktree) {
+        self.expected_versions.insert(rev.clone(), version);
+      }
+    }
+    for (alias, original) in &aliases {
+      self.commits.insert(alias.clone(), self.commits[original].clone());
+      self.commit_times.insert(alias.clone(), self.commit_times[original]);
+    }
+
+    let results = jobs::map(&worktrees, self.build_options.jobs, |(rev, commit, worktree)| {
+      self.build_rev(rev, commit, worktree)
+    });
+    for ((rev, ..), result) in worktrees.into_iter().zip(results) {
+      if let Err(err) = result {
+        failed.push((rev, err));
+      }
+    }
+
+    // in the order given, so that the reported failure doesn't depend on the
+    // order builds finished in
+    failed.sort_by_key(|(rev, _)| self.remote_revs.iter().position(|r| r == rev));
+    for (rev, err) in failed {
+      if !self.build_options.keep_going {
+        return Err(err.context(format!(
+          "build {rev}, pass --keep-going to benchmark the other revisions"
+        )));
+      }
+
+      (self.on_event)(Event::Failed(format!("failed to build {rev:?}: {err:#}")));
+      self.warnings.push(Warning::BuildFailed {
+        rev: rev.clone(),
+        error: format!("{err:#}"),
+      });
+      for (alias, _) in aliases.iter().filter(|(_, original)| *original == rev) {
+        self.failed_builds.push((alias.clone(), format!("{err:#}")));
+      }
+      self.failed_builds.push((rev, format!("{err:#}")));
+    }
+    self.aliases = aliases;
 
     Ok(())
   }
 
-  fn checkout_remote(&self, rev: &str) -> Result<()> {
-    self
-      .git()
-      .args(["checkout", rev])
-      .status_stdout()
-      .context("status stdout")?;
+  /// Builds `rev`, checked out at `worktree`, into its own `bin` directory,
+  /// reusing the cached binary of `commit` if there is one.
+  fn build_rev(&self, rev: &str, commit: &str, worktree: &Path) -> Result<()> {
+    let bin_rev_dir = self.rev_bin_dir(rev);
+    fs::create_dir(&bin_rev_dir).context("create dir")?;
+    let binary = bin_rev_dir.join("hvm");
+
+    let cached = (self.build_options.cache_dir.as_ref()).map(|cache_dir| cache_dir.join(commit).join("hvm"));
+    match &cached {
+      Some(cached) if cached.exists() && !self.build_options.rebuild => {
+        self.info(format_args!("using cached build of {rev:?}"));
+        fs::copy(cached, &binary).context("copy from cache")?;
+      }
+      _ => {
+        let target_dir = (self.build_options.target_dir.as_ref()).map(|target_dir| target_dir.join(file_name(rev)));
+        let built = self
+          .cargo_build(worktree, target_dir.as_deref())
+          .context("cargo build")?;
+        // copied, as cargo rebuilds a binary that's missing from its target dir
+        fs::copy(built, &binary).context("copy")?;
+
+        if let Some(cached) = &cached {
+          cache_binary(&binary, cached).context("cache")?;
+        }
+      }
+    }
+
+    if let ProgramsSource::EachRev(path) = &self.programs.source {
+      copy_programs(worktree.join(path), bin_rev_dir.join("programs")).context("copy programs")?;
+    }
 
     Ok(())
   }
 
-  fn git(&self) -> Command {
-    let mut git = Command::new("git");
-    git.current_dir(self.remote_repo_dir());
+  /// Returns the label of every revision that was built, in benchmarking
+  /// order.
+  pub fn revisions(&self) -> Vec<String> {
+    let built = self.binaries().into_iter().map(|(rev, ..)| rev).collect::<Vec<_>>();
 
-    git
+    // the failed builds in their place among the remote revisions
+    let mut revisions = (self.remote_revs.iter())
+      .filter(|rev| {
+        built.contains(rev)
+          || self.aliases.iter().any(|(alias, _)| alias == *rev)
+          || self.failed_builds.iter().any(|(failed, _)| failed == *rev)
+      })
+      .cloned()
+      .collect::<Vec<_>>();
+    revisions.extend(built.into_iter().filter(|rev| !self.remote_revs.contains(rev)));
+
+    revisions
+  }
+
+  /// Returns the label, binary, and programs directory of every revision that
+  /// was built or prebuilt, in benchmarking order, which starts with the
+  /// baseline of [`StopEarly`] so that the others can be compared with it.
+  fn binaries(&self) -> Vec<(String, PathBuf, PathBuf)> {
+    let mut binaries = (self.prebuilt.iter())
+      .map(|(label, binary)| (label.clone(), binary.clone(), self.shared_programs_dir()))
+      .collect::<Vec<_>>();
+    for rev in &self.remote_revs {
+      if !self.rev_bin_dir(rev).join("hvm").exists() {
+        continue;
+      }
+
+      let programs_dir = match &self.programs.source {
+        ProgramsSource::EachRev(_) => self.rev_bin_dir(rev).join("programs"),
+        _ => self.shared_programs_dir(),
+      };
+
+      binaries.push((rev.clone(), self.rev_bin_dir(rev).join("hvm"), programs_dir));
+    }
+
+    for (i, (label, local_dir)) in self.locals.iter().enumerate() {
+      let programs_dir = match &self.programs.source {
+        ProgramsSource::EachRev(path) => local_dir.join(path),
+        _ => self.shared_programs_dir(),
+      };
+
+      binaries.push((label.clone(), self.local_bin(i), programs_dir));
+    }
+
+    if let Some(stop_early) = &self.sampling.stop_early {
+      binaries.sort_by_key(|(label, ..)| *label != stop_early.baseline);
+    }
+
+    binaries
+  }
+
+  /// Returns the directory the binary of the remote revision `rev` is built
+  /// into, named so that revisions like `origin/main` stay one directory.
+  fn rev_bin_dir(&self, rev: &str) -> PathBuf {
+    self.bin_dir().join(file_name(rev))
+  }
+
+  /// Returns the binary of the `i`th local repo.
+  fn local_bin(&self, i: usize) -> PathBuf {
+    self.bin_dir().join(format!("local_hvm_{i}"))
+  }
+
+  fn bench_all(&mut self) -> Result<()> {
+    let binaries = self.binaries();
+
+    // a revision whose programs can't be selected fails when it's benchmarked
+    let cells = |programs_dir: &Path| self.programs.select(programs_dir).map_or(0, |programs| programs.len());
+    let cells = (binaries.iter())
+      .map(|(_, _, programs_dir)| cells(programs_dir) * self.sampling.modes.len())
+      .collect::<Vec<_>>();
+    let progress = Progress::new(cells.iter().sum(), self.run_options.verbosity);
+
+    let mut previous: Option<(String, PathBuf, PathBuf)> = None;
+    for ((rev, bin, programs_dir), cells) in binaries.into_iter().zip(cells) {
+      if let Some((previous_rev, previous_bin, previous_programs_dir)) = &previous {
+        if self.skip_identical
+          && *previous_programs_dir == programs_dir
+          && fs::read(previous_bin).context("read")? == fs::read(&bin).context("read")?
+        {
+          self.info(format_args!(
+            "skipping {rev:?}, its binary is identical to {previous_rev:?}"
+          ));
+
+          self.reuse_stats(previous_rev, rev);
+          progress.skip(cells);
+
+          continue;
+        }
+      }
+
+      self
+        .bench_bin(&rev, &bin, programs_dir.clone(), &progress)
+        .with_context(|| format!("bench {rev}"))?;
+
+      previous = Some((rev, bin, programs_dir));
+    }
+
+    for (alias, original) in self.aliases.clone() {
+      if self.stats.contains_key(&original) {
+        self.reuse_stats(&original, alias);
+      }
+    }
+
+    self.record_failed_builds();
+    self.check_outputs();
+
+    // revisions that weren't benchmarked, e.g. a saved baseline, keep theirs
+    for (rev, stats) in &mut self.stats {
+      if let Some(&time) = self.commit_times.get(rev) {
+        stats.committed_at = Some(time);
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Records the results of `from` as those of `to` too, as they ran the same
+  /// binary.
+  fn reuse_stats(&mut self, from: &str, to: String) {
+    let stats = self.stats.get(from).cloned().unwrap_or_default();
+    for (program_name, program) in &stats.programs {
+      for &mode in &self.sampling.modes {
+        if let Some(runs) = program.mode(mode) {
+          self.finished(&to, program_name, mode, runs);
+        }
+      }
+    }
+    self.stats.insert(to, stats);
+  }
+
+  /// Marks every cell of the revisions that failed to build as a build error,
+  /// for each program benchmarked in any other revision.
+  fn record_failed_builds(&mut self) {
+    let program_names = (self.stats.values())
+      .flat_map(|stats| stats.programs.keys().cloned())
+      .collect::<BTreeSet<_>>();
+
+    for (rev, error) in &self.failed_builds {
+      for program_name in &program_names {
+        for &mode in &self.sampling.modes {
+          let runs = Err(RunError::Build(anyhow::anyhow!(error.clone())));
+          self.finished(rev, program_name, mode, &runs);
+
+          let stats = self.stats.entry(rev.clone()).or_default();
+          *stats.programs.entry(program_name.clone()).or_default().mode_mut(mode) = Some(runs);
+        }
+      }
+    }
+  }
+
+  fn bench_bin<P: AsRef<Path>>(&mut self, rev: &str, bin: P, programs_dir: PathBuf, progress: &Progress) -> Result<()> {
+    self.info(format_args!("benchmarking {rev:?}"));
+    let bin = bin.as_ref();
+    let programs = self.programs.select(&programs_dir)?;
+
+    let version = self.binary_version(bin);
+    if let (Some(expected), Some(actual)) = (self.expected_versions.get(rev), &version) {
+      if expected != actual {
+        self.warnings.push(Warning::VersionMismatch {
+          rev: rev.to_string(),
+          expected: expected.clone(),
+          actual: actual.clone(),
+        });
+      }
+    }
+    let stats = self.stats.entry(rev.to_string()).or_default();
+    stats.version = version;
+    stats.categories.extend(
+      (programs.iter())
+        .filter(|program| !program.category.is_empty())
+        .map(|program| (program.name.clone(), program.category.clone())),
+    );
+
+    let cells = programs
+      .iter()
+      .flat_map(|program| self.sampling.modes.iter().map(move |&mode| (mode, program)))
+      .collect::<Vec<_>>();
+    let finished: Mutex<BTreeMap<String, Program>> = Mutex::default();
+    jobs::run_all(&cells, self.sampling.jobs, |mode, program| {
+      progress.start(rev, &program.name, mode);
+      let baseline = self.baseline_runs(rev, &program.name, mode);
+      let result = self.sample(mode, bin, program, baseline);
+      if let Err(err) = &result {
+        (self.on_event)(Event::Failed(format!("  {:?} failed in {mode}: {err}", program.name)));
+      }
+      progress.finish();
+      self.finished(rev, &program.name, mode, &result);
+
+      let mut finished = finished.lock().unwrap();
+      let stats = finished.entry(program.name.clone()).or_default();
+      *stats.mode_mut(mode) = Some(result);
+      if self.sampling.modes.iter().all(|&mode| stats.mode(mode).is_some()) {
+        if let Err(err) = self.write_partial(rev, &finished) {
+          (self.on_event)(Event::Failed(format!("failed to write partial results: {err:#}")));
+        }
+      }
+    });
+
+    let stats = finished.into_inner().unwrap();
+
+    for (program_name, stats) in stats {
+      self.check_timings(rev, &program_name, &stats);
+      self
+        .stats
+        .entry(rev.to_string())
+        .or_default()
+        .programs
+        .insert(program_name, stats);
+    }
+
+    Ok(())
+  }
+
+  /// Reports the result of `program` in `mode` as soon as it's known.
+  fn finished(&self, rev: &str, program: &str, mode: Mode, runs: &Runs) {
+    (self.on_event)(Event::Finished {
+      rev,
+      program,
+      mode,
+      runs,
+    });
+  }
+
+  /// Writes the results so far, with `finished` the programs of `rev` that
+  /// finished, to the partial output if there is one. The file is replaced
+  /// atomically, so that it always holds complete JSON.
+  fn write_partial(&self, rev: &str, finished: &BTreeMap<String, Program>) -> Result<()> {
+    let Some(output) = &self.partial_output else {
+      return Ok(());
+    };
+
+    let mut stats = self.stats.clone();
+    let programs = finished.iter().map(|(name, program)| (name.clone(), program.clone()));
+    stats.entry(rev.to_string()).or_default().programs.extend(programs);
+    let json = format::format_json(
+      &stats,
+      &self.commits,
+      &output.tags,
+      self.environment.as_ref(),
+      &self.warnings,
+    )
+    .context("format json")?;
+
+    let dir = match output.path.parent() {
+      Some(dir) if !dir.as_os_str().is_empty() => dir,
+      _ => Path::new("."),
+    };
+    let mut temp = NamedTempFile::new_in(dir).context("tempfile")?;
+    temp.write_all(json.as_bytes()).context("write")?;
+    temp.persist(&output.path).context("persist")?;
+
+    Ok(())
+  }
+
+  /// Generates and compiles `program` for the compiled `mode`, see
+  /// [`run::compile_c`].
+  fn compile(&self, mode: Mode, bin: &Path, program: &Invocation) -> Result<Result<Compiled, Timing>, RunError> {
+    let (timeout, options) = (self.sampling.timeout(mode), &self.run_options);
+
+    self.retrying(mode, program, || match mode {
+      Mode::CompiledCuda => run::compile_cuda(bin, &program.path, timeout, options),
+      _ => run::compile_c(bin, &program.path, timeout, options),
+    })
+  }
+
+  /// Runs `program` once in `mode`, or the binary it was compiled into in a
+  /// compiled mode.
+  fn run(&self, mode: Mode, bin: &Path, program: &Invocation, compiled: Option<&Compiled>) -> Result<Timing, RunError> {
+    let (timeout, options) = (self.sampling.timeout(mode), &self.run_options);
+    let (path, args) = (&program.path, &program.args);
+
+    match (mode, compiled) {
+      (_, Some(compiled)) => run::run_binary(compiled, args, timeout, options),
+      (Mode::InterpretedC, None) => run::interpreted_c(bin, path, args, timeout, options),
+      (Mode::InterpretedCuda, None) => run::interpreted_cuda(bin, path, args, timeout, options),
+      (Mode::InterpretedRust, None) => run::interpreted_rust(bin, path, args, timeout, options),
+      (Mode::CompiledC | Mode::CompiledCuda, None) => unreachable!("compiled modes run a compiled binary"),
+    }
+  }
+
+  /// Calls `run`, retrying it up to [`Sampling::retries`] times if it fails.
+  fn retrying<T, F>(&self, mode: Mode, program: &Invocation, run: F) -> Result<T, RunError>
+  where
+    F: FnMut() -> Result<T, RunError>,
+  {
+    retry(self.sampling.retries, run, |attempt, err| {
+      self.info(format_args!(
+        "  {:?} failed in {mode}, retrying ({attempt}/{}): {err}",
+        program.name, self.sampling.retries
+      ));
+    })
+  }
+
+  /// Samples `program` in `mode` after the warmup runs, unless `mode` can't
+  /// run on this machine, comparing it with the `baseline` runs of the same
+  /// cell, if any. In a compiled mode, the program is compiled once, and every
+  /// run runs that binary, with the compile time added to its timing.
+  fn sample(&self, mode: Mode, bin: &Path, program: &Invocation, baseline: Option<&[Timing]>) -> Runs {
+    if self.skip_cuda && mode.is_cuda() {
+      return Ok(vec![Timing::Skipped]);
+    }
+
+    let compiled = if mode.is_compiled() {
+      match self.compile(mode, bin, program)? {
+        Ok(compiled) => Some(compiled),
+        Err(timing) => return Ok(vec![timing]),
+      }
+    } else {
+      None
+    };
+    let run = || self.retrying(mode, program, || self.run(mode, bin, program, compiled.as_ref()));
+
+    for _ in 0..self.sampling.warmup {
+      // failures show up in the sampled runs
+      let _ = run();
+    }
+
+    self.sample_runs(baseline, run)
+  }
+
+  /// Returns the runs of `program` in `mode` of the [`StopEarly::baseline`]
+  /// that `rev` is compared with, unless `rev` is the baseline or the
+  /// baseline has no runs of it.
+  fn baseline_runs(&self, rev: &str, program: &str, mode: Mode) -> Option<&[Timing]> {
+    let baseline = &self.sampling.stop_early.as_ref()?.baseline;
+    if baseline == rev {
+      return None;
+    }
+
+    match self.stats.get(baseline)?.programs.get(program)?.mode(mode)? {
+      Ok(timings) => Some(timings),
+      Err(_) => None,
+    }
+  }
+
+  /// Calls `run` until there are enough sampled runs, see
+  /// [`Sampling::enough`], stopping at the first error, if the mode turns out
+  /// to be unsupported, or if compiling timed out, which would likely happen
+  /// again.
+  fn sample_runs<F: FnMut() -> Result<Timing, RunError>>(&self, baseline: Option<&[Timing]>, mut run: F) -> Runs {
+    let mut timings = Vec::with_capacity(self.sampling.runs);
+    while !self.sampling.enough(&timings, baseline) {
+      let timing = run()?;
+      let last = matches!(timing, Timing::Unsupported | Timing::CompileTimeout);
+      timings.push(timing);
+
+      if last {
+        break;
+      }
+    }
+
+    Ok(timings)
+  }
+
+  /// Warns about conditions of the machine that make results unreliable, and
+  /// stops counting runs with perf if it can't count.
+  fn check_environment(&mut self) {
+    let cpus = std::thread::available_parallelism().map_or(1, usize::from);
+    let load = fs::read_to_string("/proc/loadavg")
+      .ok()
+      .and_then(|loadavg| loadavg.split_whitespace().next()?.parse::<f64>().ok());
+
+    if let Some(load) = load {
+      if load > cpus as f64 * HIGH_LOAD_PER_CPU {
+        self.warnings.push(Warning::HighLoad { load, cpus });
+      }
+    }
+
+    let c_compiler = self.run_options.c_compiler.program.clone();
+    if self.sampling.modes.contains(&Mode::CompiledC) && !self.is_available(&c_compiler) {
+      self.warnings.push(Warning::MissingCompiler { compiler: c_compiler });
+    }
+
+    if self.run_options.perf && !self.run_options.perf_available() {
+      self.run_options.perf = false;
+      self.warnings.push(Warning::PerfUnavailable);
+    }
+
+    if self.sampling.modes.iter().any(|mode| mode.is_cuda()) {
+      let cuda_compiler = self.run_options.cuda_compiler.program.clone();
+      let has_compiler = self.is_available(&cuda_compiler);
+
+      if self.sampling.force_cuda {
+        if self.sampling.modes.contains(&Mode::CompiledCuda) && !has_compiler {
+          self.warnings.push(Warning::MissingCompiler {
+            compiler: cuda_compiler,
+          });
+        }
+      } else if !has_compiler || !self.is_available("nvidia-smi") {
+        self.skip_cuda = true;
+        self.warnings.push(Warning::CudaUnavailable);
+      }
+    }
+  }
+
+  /// Warns about programs that printed a different result in some revisions
+  /// than in the first one, in benchmarking order, to print one.
+  fn check_outputs(&mut self) {
+    let revisions = self.revisions();
+    let programs = (self.stats.values())
+      .flat_map(|stats| stats.programs.keys())
+      .collect::<BTreeSet<_>>();
+
+    let mut warnings = Vec::new();
+    for program in programs {
+      for mode in Mode::ALL {
+        let outputs = revisions.iter().filter_map(|rev| {
+          let output = self.stats.get(rev)?.programs.get(program)?.output(mode)?;
+          Some((rev.as_str(), output))
+        });
+
+        if let Some((reference, revs)) = stats::output_mismatch(outputs) {
+          warnings.push(Warning::OutputMismatch {
+            program: program.clone(),
+            mode,
+            reference: reference.to_string(),
+            revs: revs.into_iter().map(str::to_string).collect(),
+          });
+        }
+      }
+    }
+
+    self.warnings.extend(warnings);
+  }
+
+  /// Warns about runs that finished close to the timeout, whose wall-clock
+  /// time differs a lot from the time hvm reported, or that the GPU was
+  /// throttled around.
+  fn check_timings(&mut self, rev: &str, program: &str, stats: &Program) {
+    for mode in Mode::ALL {
+      if let Some(Ok(Timing::Completed { reported, .. })) = stats.timing(mode, Aggregate::Max) {
+        if reported.seconds > self.sampling.timeout(mode).as_secs_f64() * NEAR_TIMEOUT_RATIO {
+          self.warnings.push(Warning::NearTimeout {
+            rev: rev.to_string(),
+            program: program.to_string(),
+            mode,
+            seconds: reported.seconds,
+          });
+        }
+      }
+
+      if let Some(Ok(timings)) = stats.mode(mode) {
+        let runs = timings.iter().filter(|timing| timing.is_throttled()).count();
+        if runs > 0 {
+          self.warnings.push(Warning::Throttled {
+            rev: rev.to_string(),
+            program: program.to_string(),
+            mode,
+            runs,
+          });
+        }
+
+        let seconds = timings.iter().filter_map(Timing::seconds).collect::<Vec<_>>();
+        if let Some((_, coefficient)) =
+          stats::variation(&seconds).filter(|(_, coefficient)| *coefficient > HIGH_VARIANCE)
+        {
+          self.warnings.push(Warning::HighVariance {
+            rev: rev.to_string(),
+            program: program.to_string(),
+            mode,
+            coefficient,
+          });
+        }
+      }
+
+      let Some(Ok(Timing::Completed { reported, wall, .. })) = stats.timing(mode, Aggregate::Median) else {
+        continue;
+      };
+      let (reported, wall) = (reported.seconds, wall.as_secs_f64());
+
+      if (wall - reported).abs() > WALL_CLOCK_TOLERANCE.max(reported * WALL_CLOCK_TOLERANCE_RATIO) {
+        self.warnings.push(Warning::WallClockMismatch {
+          rev: rev.to_string(),
+          program: program.to_string(),
+          mode,
+          reported,
+          wall,
+        });
+      }
+    }
+  }
+
+  /// The programs directory used by revisions that don't have their own.
+  fn shared_programs_dir(&self) -> PathBuf {
+    match &self.programs.source {
+      ProgramsSource::Dir(dir) => dir.clone(),
+      _ => self.tempdir.path().join("programs"),
+    }
+  }
+
+  fn remote_repo_dir(&self) -> PathBuf {
+    self.tempdir.path().join("repo")
+  }
+
+  fn bin_dir(&self) -> PathBuf {
+    self.tempdir.path().join("bin")
+  }
+
+  /// Reports what's going on, unless quiet.
+  fn info(&self, message: fmt::Arguments) {
+    self.run_options.verbosity.info(message);
+  }
+
+  /// Builds the hvm binary of the repo at `dir`, in `target_dir` if given or
+  /// else in the repo's own, returning where it was built.
+  fn cargo_build<P: AsRef<Path>>(&self, dir: P, target_dir: Option<&Path>) -> Result<PathBuf> {
+    let dir = dir.as_ref();
+    self.info(format_args!("building {dir:?}"));
+
+    let mut command = self.run_options.command_in(dir, "cargo");
+    command.args(["build", "--release"]);
+    if let Some(target_dir) = target_dir {
+      command.arg("--target-dir").arg(target_dir);
+    }
+    command.status_stdout().context("status stdout")?;
+
+    Ok(target_dir.unwrap_or(&dir.join("target")).join("release/hvm"))
+  }
+
+  /// Returns the version `bin --version` reports, e.g. `2.0.22` for
+  /// `hvm 2.0.22`, or `None` if it doesn't report one.
+  fn binary_version(&self, bin: &Path) -> Option<String> {
+    let output = self.run_options.command(bin).arg("--version").status_stdout().ok()?;
+
+    output.split_whitespace().last().map(str::to_string)
+  }
+
+  /// Returns whether `program --version` runs successfully.
+  fn is_available(&self, program: &str) -> bool {
+    self
+      .run_options
+      .command(program)
+      .arg("--version")
+      .output()
+      .is_ok_and(|output| output.status.success())
+  }
+
+  /// Clones the remote repo. Unless it's a full clone, only the history is
+  /// cloned, i.e. commits and trees, which is all resolving revisions needs,
+  /// and the contents of each revision are fetched when it's checked out.
+  /// Repos given by path are always cloned fully, as git links their objects
+  /// instead of copying them.
+  fn clone_remote(&self) -> Result<()> {
+    let mut git = self.git();
+    git.arg("clone");
+    if !self.build_options.full_clone && !Path::new(&self.build_options.repo_url).exists() {
+      git.arg("--filter=blob:none");
+    }
+
+    git
+      .arg(&self.build_options.repo_url)
+      .arg(".")
+      .status_stdout_timeout(self.build_options.git_timeout)
+      .context("status stdout")?
+      .with_context(|| self.git_timed_out(&format!("cloning {:?}", self.build_options.repo_url)))?;
+
+    Ok(())
+  }
+
+  /// Checks out `commit` into a new worktree at `path`.
+  fn add_worktree(&self, path: &Path, commit: &str) -> Result<()> {
+    self
+      .git()
+      .args(["worktree", "add", "--detach"])
+      .arg(path)
+      .arg(commit)
+      .status_stdout_timeout(self.build_options.git_timeout)
+      .context("status stdout")?
+      .with_context(|| self.git_timed_out(&format!("checking out {commit}")))?;
+
+    Ok(())
+  }
+
+  fn checkout_remote(&self, rev: &str) -> Result<()> {
+    self
+      .git()
+      .args(["checkout", rev])
+      .status_stdout_timeout(self.build_options.git_timeout)
+      .context("status stdout")?
+      .with_context(|| self.git_timed_out(&format!("checking out {rev}")))?;
+
+    Ok(())
+  }
+
+  /// Returns the error of a git operation, described by `doing`, that took
+  /// longer than the git timeout.
+  fn git_timed_out(&self, doing: &str) -> String {
+    format!(
+      "{doing} timed out after {}s, the network may be unreachable; raise --git-timeout if the repo is just slow",
+      self.build_options.git_timeout.as_secs()
+    )
+  }
+
+  /// Resolves a revision spec into a full commit hash.
+  ///
+  /// `merge-base:<base>[,<head>]` resolves to the merge base of `<base>` and
+  /// `<head>`, where `<head>` defaults to the `HEAD` of the local repo. Any
+  /// other spec is resolved with [`Self::rev_parse`].
+  fn resolve_rev(&self, rev: &str) -> Result<String> {
+    let Some(spec) = rev.strip_prefix(MERGE_BASE_PREFIX) else {
+      return self.rev_parse(rev);
+    };
+
+    let (base, head) = match spec.split_once(',') {
+      Some((base, head)) => (base, self.rev_parse(head)?),
+      None if self.locals.is_empty() => {
+        anyhow::bail!("{MERGE_BASE_PREFIX}{spec} needs a local repo or an explicit ,<head>")
+      }
+      None => (spec, self.fetch_local_head().context("fetch local head")?),
+    };
+    let base = self.rev_parse(base)?;
+
+    let merge_base = self
+      .git()
+      .args(["merge-base", &base, &head])
+      .status_stdout()
+      .context("status stdout")?;
+
+    Ok(merge_base.trim().to_string())
+  }
+
+  /// Returns the commit hash of `rev` in the remote repo, falling back to the
+  /// remote-tracking branch `origin/<rev>`. Abbreviated hashes are accepted,
+  /// and ambiguous ones are reported with all the commits they could mean.
+  fn rev_parse(&self, rev: &str) -> Result<String> {
+    for candidate in [rev.to_string(), format!("origin/{rev}")] {
+      let commit = self
+        .git()
+        .args(["rev-parse", "--verify", "--quiet"])
+        .arg(format!("{candidate}^{{commit}}"))
+        .status_stdout();
+
+      if let Ok(commit) = commit {
+        return Ok(commit.trim().to_string());
+      }
+    }
+
+    if !rev.is_empty() && rev.chars().all(|c| c.is_ascii_hexdigit()) {
+      if rev.len() < MIN_ABBREV_LEN {
+        anyhow::bail!("revision {rev:?} is too short, abbreviated hashes need at least {MIN_ABBREV_LEN} characters");
+      }
+
+      let commits = self.commits_with_prefix(rev).context("commits with prefix")?;
+      if commits.len() > 1 {
+        anyhow::bail!(
+          "revision {rev:?} is ambiguous, it could be any of:\n  {}",
+          commits.join("\n  ")
+        );
+      }
+    }
+
+    anyhow::bail!("unknown revision {rev:?}")
+  }
+
+  /// Returns the hash and summary of every commit whose hash starts with
+  /// `prefix`.
+  fn commits_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+    let objects = self
+      .git()
+      .arg("rev-parse")
+      .arg(format!("--disambiguate={prefix}"))
+      .status_stdout()
+      .context("status stdout")?;
+
+    let mut commits = Vec::new();
+    for object in objects.lines() {
+      let kind = self
+        .git()
+        .args(["cat-file", "-t", object])
+        .status_stdout()
+        .context("status stdout")?;
+
+      if kind.trim() == "commit" {
+        let commit = self
+          .git()
+          .args(["show", "--no-patch", "--format=%H %s", object])
+          .status_stdout()
+          .context("status stdout")?;
+
+        commits.push(commit.trim().to_string());
+      }
+    }
+
+    Ok(commits)
+  }
+
+  /// Fetches the `HEAD` of the first local repo into the remote repo,
+  /// returning its commit hash.
+  fn fetch_local_head(&self) -> Result<String> {
+    let local_dir = fs::canonicalize(&self.locals[0].1).context("canonicalize")?;
+
+    self
+      .git()
+      .arg("fetch")
+      .arg(local_dir)
+      .arg("HEAD")
+      .status_stdout()
+      .context("status stdout")?;
+
+    self.rev_parse("FETCH_HEAD")
+  }
+
+  /// Returns when `commit` was committed, in seconds since the epoch.
+  fn commit_time(&self, commit: &str) -> Result<u64> {
+    let time = self
+      .git()
+      .args(["show", "-s", "--format=%ct", commit])
+      .status_stdout()
+      .context("status stdout")?;
+
+    time.trim().parse().with_context(|| format!("parse {time:?}"))
+  }
+
+  /// Returns the most recent commits on any branch of the remote repo.
+  fn recent_commits(&self) -> Result<Vec<Candidate>> {
+    let log = self
+      .git()
+      .args(["log", "--all", "--date-order", "--format=%H %s"])
+      .arg(format!("--max-count={PICKABLE_COMMITS}"))
+      .status_stdout()
+      .context("status stdout")?;
+
+    let commits = log
+      .lines()
+      .filter_map(|line| {
+        let (hash, summary) = line.split_once(' ').unwrap_or((line, ""));
+
+        (!hash.is_empty()).then(|| Candidate {
+          hash: hash.to_string(),
+          summary: summary.to_string(),
+        })
+      })
+      .collect();
+
+    Ok(commits)
+  }
+
+  fn git(&self) -> Command {
+    let mut git = Command::new("git");
+    git.current_dir(self.remote_repo_dir());
+
+    git
+  }
+}
+
+/// Returns `since` as `git log --since` takes it, spelling out a number of
+/// hours, days or weeks such as `7d` as `7 days ago`. git reads `7d` itself,
+/// but not `2w`.
+fn git_date(since: &str) -> String {
+  let units = [('h', "hours"), ('d', "days"), ('w', "weeks")];
+  for (suffix, unit) in units {
+    if let Some(count) = since.strip_suffix(suffix).and_then(|count| count.parse::<u64>().ok()) {
+      return format!("{count} {unit} ago");
+    }
+  }
+
+  since.to_string()
+}
+
+/// Returns the label of each local repo: `local` if there is only one, and its
+/// directory name otherwise. The labels don't change with what's checked out,
+/// so that results can be compared across commits; the tables show the commit
+/// next to them instead, see [`local_commit`].
+pub fn local_labels(local_dirs: &[PathBuf]) -> Result<Vec<String>> {
+  let mut names = Vec::new();
+  for local_dir in local_dirs {
+    let name = if let [_] = local_dirs {
+      "local".to_string()
+    } else {
+      let canonical = fs::canonicalize(local_dir).with_context(|| format!("canonicalize {local_dir:?}"))?;
+      let name = canonical
+        .file_name()
+        .with_context(|| format!("{local_dir:?} has no directory name"))?;
+      name.to_string_lossy().into_owned()
+    };
+
+    if names.contains(&name) {
+      anyhow::bail!("local repos {local_dirs:?} share the directory name {name}");
+    }
+    names.push(name);
+  }
+
+  Ok(names)
+}
+
+/// Returns the commit checked out in `dir`, followed by [`DIRTY_SUFFIX`] if it
+/// has uncommitted changes, or `None` if `dir` isn't the root of a git repo.
+fn local_commit(dir: &Path) -> Option<String> {
+  let git = |args: &[&str]| Command::new("git").arg("-C").arg(dir).args(args).status_stdout().ok();
+
+  // not the repo of a parent directory
+  let toplevel = git(&["rev-parse", "--show-toplevel"])?;
+  if fs::canonicalize(toplevel.trim()).ok()? != fs::canonicalize(dir).ok()? {
+    return None;
+  }
+
+  let commit = git(&["rev-parse", "HEAD"])?;
+  let status = git(&["status", "--porcelain"])?;
+  let dirty = if status.trim().is_empty() { "" } else { DIRTY_SUFFIX };
+
+  Some(format!("{}{dirty}", commit.trim()))
+}
+
+/// Calls `run` until it succeeds, at most `retries` times more after the
+/// first, calling `on_retry` with the attempt number and the error before each
+/// retry. Timeouts are successful runs, so they aren't retried.
+fn retry<T, F, R>(retries: usize, mut run: F, mut on_retry: R) -> Result<T, RunError>
+where
+  F: FnMut() -> Result<T, RunError>,
+  R: FnMut(usize, &RunError),
+{
+  let mut attempt = 0;
+  loop {
+    match run() {
+      Err(err) if attempt < retries => {
+        attempt += 1;
+        on_retry(attempt, &err);
+      }
+      result => return result,
+    }
+  }
+}
+
+/// Returns the package version in the `Cargo.toml` of the repo at `dir`, if
+/// it has one.
+fn cargo_version(dir: &Path) -> Option<String> {
+  let manifest: toml::Table = toml::from_str(&fs::read_to_string(dir.join("Cargo.toml")).ok()?).ok()?;
+
+  Some(manifest.get("package")?.get("version")?.as_str()?.to_string())
+}
+
+/// Returns `label` with the characters that don't belong in a file name
+/// replaced, e.g. the `/` of `origin/main`.
+fn file_name(label: &str) -> String {
+  label.replace(|c: char| !c.is_ascii_alphanumeric() && !"-_.".contains(c), "_")
+}
+
+/// Copies `binary` to `cached`, atomically so that concurrent builds of the
+/// same commit don't leave a partial binary behind.
+fn cache_binary(binary: &Path, cached: &Path) -> Result<()> {
+  let dir = cached.parent().context("parent")?;
+  fs::create_dir_all(dir).context("create dir")?;
+
+  let temp = NamedTempFile::new_in(dir).context("tempfile")?;
+  fs::copy(binary, temp.path()).context("copy")?;
+  temp.persist(cached).context("persist")?;
+
+  Ok(())
+}
+
+/// Copies the programs in `from` into a new directory `to`, keeping the
+/// subdirectories they're in.
+fn copy_programs<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<()> {
+  let (from, to) = (from.as_ref(), to.as_ref());
+  fs::create_dir_all(to).context("create dir")?;
+
+  for program in programs::paths(from).with_context(|| format!("programs in {from:?}"))? {
+    let copy = to.join(program.strip_prefix(from).context("strip prefix")?);
+    if let Some(dir) = copy.parent() {
+      fs::create_dir_all(dir).context("create dir")?;
+    }
+    fs::copy(&program, copy).context("copy")?;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::os::unix::fs::PermissionsExt;
+
+  use super::*;
+  use crate::{log::Verbosity, stats::Reported};
+
+  /// A fake `hvm` that can't generate code for `bad.hvm`, and runs anything
+  /// else instantly.
+  const FAKE_HVM: &str = r#"#!/bin/sh
+case "$1:$(basename "$2")" in
+  --version:*) echo "hvm 2.0.0" ;;
+  gen-*:bad.hvm) echo "error: bad program" >&2; exit 1 ;;
+  gen-*) echo 'int main() { puts("- TIME: 0.01s"); }' ;;
+  *) echo "- TIME: 0.01s" ;;
+esac
+"#;
+
+  /// Writes an executable `name` into `dir` running `contents`.
+  fn script(dir: &Path, name: &str, contents: &str) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path
+  }
+
+  /// Returns a completed run that took `seconds`.
+  fn timing(seconds: f64) -> Timing {
+    Timing::Completed {
+      reported: Reported::parse(&format!("{seconds}s")).unwrap(),
+      wall: Duration::from_secs_f64(seconds),
+      peak_memory: None,
+      compile_time: None,
+      counters: None,
+      throttled: false,
+    }
+  }
+
+  /// Runs git with `args` in `dir`, returning its stdout.
+  fn git(dir: &Path, args: &[&str]) -> String {
+    Command::new("git").current_dir(dir).args(args).status_stdout().unwrap()
+  }
+
+  /// Returns a bench of the repo at `dir`, with `FAKE_HVM` as its binary and
+  /// programs `bad` and `good`, and of `remote_revs`.
+  fn fake_bench(dir: &Path, remote_revs: Vec<String>) -> (Bench, PathBuf, PathBuf) {
+    let hvm = script(dir, "hvm", FAKE_HVM);
+
+    let programs_dir = dir.join("programs");
+    fs::create_dir(&programs_dir).unwrap();
+    fs::write(programs_dir.join("bad.hvm"), "").unwrap();
+    fs::write(programs_dir.join("good.hvm"), "").unwrap();
+
+    let bench = Bench::builder()
+      .local_dirs([dir.to_path_buf()])
+      .revs(remote_revs)
+      .timeout(Duration::from_secs(10))
+      .programs_dir(&programs_dir)
+      .build_options(BuildOptions {
+        max_revs: 1,
+        keep_going: true,
+        ..BuildOptions::default()
+      })
+      .build()
+      .unwrap();
+
+    (bench, hvm, programs_dir)
+  }
+
+  #[test]
+  fn builder_checks_paths_exist() {
+    let dir = TempDir::new().unwrap();
+    let missing = dir.path().join("missing");
+    let builder = || Bench::builder().programs_dir(dir.path());
+
+    assert!(builder().build().is_ok());
+    assert!(builder().local_dirs([missing.clone()]).build().is_err());
+    assert!(builder()
+      .binaries([("v1".to_string(), missing.clone())])
+      .build()
+      .is_err());
+    assert!(builder().programs_dir(&missing).build().is_err());
+    assert!(builder().runs(0).build().is_err());
+  }
+
+  #[test]
+  fn revisions_build_in_their_own_target_dir() {
+    let dir = TempDir::new().unwrap();
+    let worktree = dir.path().join("worktree");
+    fs::create_dir_all(worktree.join("src")).unwrap();
+    fs::write(
+      worktree.join("Cargo.toml"),
+      "[package]\nname = \"hvm\"\nversion = \"0.1.0\"\nedition = \"2021\"\n[workspace]\n",
+    )
+    .unwrap();
+    fs::write(worktree.join("src/main.rs"), "fn main() {}\n").unwrap();
+
+    let target_dir = dir.path().join("target");
+    let build = || {
+      let (mut bench, ..) = fake_bench(&dir.path().join("local"), vec!["origin/main".to_string()]);
+      bench.build_options.target_dir = Some(target_dir.clone());
+      bench.build_rev("origin/main", "commit", &worktree).unwrap();
+      let binary = bench.bin_dir().join("origin_main/hvm");
+      assert!(binary.is_file());
+      assert_eq!(bench.binaries()[0].1, binary);
+    };
+    fs::create_dir(dir.path().join("local")).unwrap();
+    build();
+    let modified = || {
+      fs::metadata(target_dir.join("origin_main/release/hvm"))
+        .unwrap()
+        .modified()
+        .unwrap()
+    };
+    let built = modified();
+
+    // a second invocation finds the artifacts of the first
+    fs::remove_dir_all(dir.path().join("local")).unwrap();
+    fs::create_dir(dir.path().join("local")).unwrap();
+    build();
+
+    assert!(!worktree.join("target").exists());
+    assert_eq!(modified(), built);
+  }
+
+  #[test]
+  fn sampled_programs_are_the_smallest() {
+    let dir = TempDir::new().unwrap();
+    for (name, contents) in [("big", "xxxx"), ("small", "x"), ("tied_a", "xx"), ("tied_b", "xx")] {
+      fs::write(dir.path().join(format!("{name}.hvm")), contents).unwrap();
+    }
+    let programs = Programs {
+      source: ProgramsSource::Dir(dir.path().to_path_buf()),
+      sample: Some(2),
+      ..Programs::default()
+    };
+
+    let sampled = programs.select(dir.path()).unwrap();
+    assert_eq!(
+      sampled.iter().map(|i| i.name.as_str()).collect::<Vec<_>>(),
+      ["small", "tied_a"]
+    );
+  }
+
+  #[test]
+  fn program_files_are_benchmarked_with_the_dir() {
+    let dir = TempDir::new().unwrap();
+    let programs_dir = dir.path().join("programs");
+    fs::create_dir(&programs_dir).unwrap();
+    fs::write(programs_dir.join("fib.hvm"), "").unwrap();
+    fs::write(programs_dir.join("sort.hvm"), "").unwrap();
+    let draft = dir.path().join("draft.hvm");
+    fs::write(&draft, "").unwrap();
+
+    let names = |programs: &Programs| {
+      let invocations = programs.select(&programs_dir)?;
+      anyhow::Ok(invocations.into_iter().map(|i| i.name).collect::<Vec<_>>())
+    };
+    let mut programs = Programs {
+      source: ProgramsSource::Dir(programs_dir.clone()),
+      only: vec!["fib".to_string()],
+      files: vec![draft],
+      ..Programs::default()
+    };
+    assert_eq!(names(&programs).unwrap(), ["draft", "fib"]);
+
+    programs.only.clear();
+    programs.files_only = true;
+    assert_eq!(names(&programs).unwrap(), ["draft"]);
+
+    programs.files_only = false;
+    programs.files.push(dir.path().join("sort.hvm"));
+    assert!(names(&programs).is_err());
+  }
+
+  #[test]
+  fn empty_programs_dir_fails_before_building() {
+    let dir = TempDir::new().unwrap();
+    let mut bench = Bench::builder()
+      .revs(["main".to_string()])
+      .programs_dir(dir.path())
+      .build()
+      .unwrap();
+
+    let err = bench.bench().unwrap_err().to_string();
+    assert!(err.starts_with("no programs found in"), "{err}");
+  }
+
+  #[test]
+  fn failing_compile_does_not_stop_other_programs() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, hvm, programs_dir) = fake_bench(dir.path(), Vec::new());
+
+    bench
+      .bench_bin("local", &hvm, programs_dir, &Progress::new(10, Verbosity::Quiet))
+      .unwrap();
+
+    let programs = &bench.stats["local"].programs;
+    assert!(matches!(programs["bad"].compiled_c, Some(Err(RunError::Compile(_)))));
+    assert!(matches!(programs["bad"].interpreted_c, Some(Ok(_))));
+    assert!(matches!(programs["good"].interpreted_c, Some(Ok(_))));
+    assert!(matches!(
+      programs["good"].timing(Mode::CompiledC, Aggregate::Median),
+      Some(Ok(Timing::Completed {
+        compile_time: Some(_),
+        ..
+      }))
+    ));
+  }
+
+  /// Makes `bench` compile C with a fake compiler, only in the compiled C
+  /// mode. Every compile, and every run of a binary it compiled, adds a line to
+  /// `compiles` and `runs` in `dir` respectively, which the returned function
+  /// counts.
+  fn counting_compiler(bench: &mut Bench, dir: &Path) -> impl Fn(&str) -> usize {
+    let (compiles, runs) = (dir.join("compiles"), dir.join("runs"));
+    let compiler = script(
+      dir,
+      "cc",
+      &format!(
+        r#"#!/bin/sh
+echo >> {compiles:?}
+printf '#!/bin/sh\necho >> {runs:?}\necho "- TIME: 0.01s"\n' > "$3"
+chmod +x "$3"
+"#
+      ),
+    );
+    bench.run_options.c_compiler = run::Compiler::new(&compiler.to_string_lossy(), &[] as &[&str]);
+    bench.sampling.modes = vec![Mode::CompiledC];
+
+    let dir = dir.to_path_buf();
+    move |name| fs::read_to_string(dir.join(name)).map_or(0, |lines| lines.lines().count())
+  }
+
+  #[test]
+  fn compiled_programs_are_compiled_once_per_cell() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, hvm, programs_dir) = fake_bench(dir.path(), Vec::new());
+    let counted = counting_compiler(&mut bench, dir.path());
+    bench.sampling.runs = 3;
+
+    bench
+      .bench_bin("local", &hvm, programs_dir, &Progress::new(10, Verbosity::Quiet))
+      .unwrap();
+
+    // `bad` can't be generated, so only `good` is compiled
+    assert_eq!(counted("compiles"), 1);
+    let Some(Ok(timings)) = &bench.stats["local"].programs["good"].compiled_c else {
+      panic!("good didn't run");
+    };
+    assert_eq!(timings.len(), 3);
+    assert!(timings.iter().all(|timing| matches!(
+      timing,
+      Timing::Completed {
+        compile_time: Some(_),
+        ..
+      }
+    )));
+  }
+
+  #[test]
+  fn warmup_runs_run_the_measured_binary() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, hvm, programs_dir) = fake_bench(dir.path(), Vec::new());
+    let counted = counting_compiler(&mut bench, dir.path());
+    bench.sampling.warmup = 2;
+    bench.sampling.runs = 2;
+
+    bench
+      .bench_bin("local", &hvm, programs_dir, &Progress::new(10, Verbosity::Quiet))
+      .unwrap();
+
+    assert_eq!(counted("compiles"), 1);
+    assert_eq!(counted("runs"), 4);
+    assert!(matches!(
+      &bench.stats["local"].programs["good"].compiled_c,
+      Some(Ok(timings)) if timings.len() == 2
+    ));
+  }
+
+  #[test]
+  fn results_are_reported_as_they_finish() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, hvm, programs_dir) = fake_bench(dir.path(), Vec::new());
+    bench.sampling.modes = vec![Mode::InterpretedC, Mode::CompiledC];
+
+    let events = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let reported = events.clone();
+    bench.on_event = Box::new(move |event| {
+      let event = match event {
+        Event::Finished {
+          rev,
+          program,
+          mode,
+          runs,
+        } => format!("{rev} {program} {mode} {}", if runs.is_ok() { "ok" } else { "err" }),
+        Event::Failed(message) => message,
+      };
+      reported.lock().unwrap().push(event);
+    });
+
+    bench
+      .bench_bin("local", &hvm, programs_dir, &Progress::new(10, Verbosity::Quiet))
+      .unwrap();
+
+    let mut events = events.lock().unwrap().clone();
+    events.sort();
+    assert_eq!(events.len(), 5, "{events:?}");
+    assert!(events[0].contains("\"bad\" failed in compiled-c"), "{events:?}");
+    assert_eq!(
+      events[1..],
+      [
+        "local bad compiled-c err",
+        "local bad interpreted-c ok",
+        "local good compiled-c ok",
+        "local good interpreted-c ok",
+      ]
+    );
+  }
+
+  #[test]
+  fn plans_list_programs_and_commands() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, ..) = fake_bench(dir.path(), vec!["main".to_string()]);
+    bench.sampling.modes = vec![Mode::InterpretedC];
+
+    let plan = bench.plan().unwrap();
+    assert!(plan.starts_with("revisions:\n  main\n  local "), "{plan}");
+    assert!(plan.contains("programs, from "), "{plan}");
+    assert!(plan.contains("\n  bad\n  good\n"), "{plan}");
+    assert!(
+      plan.contains("good in interpreted-c, timeout 10s:\n  hvm run-c "),
+      "{plan}"
+    );
+  }
+
+  #[test]
+  fn runs_repeat_until_stable() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, ..) = fake_bench(dir.path(), Vec::new());
+    bench.sampling.until_stable = Some(0.02);
+    bench.sampling.max_runs = 20;
+
+    // the first two runs vary by more than 2%, until enough steady ones follow
+    let mut seconds = [1.0, 1.1].into_iter().chain(std::iter::repeat(1.05));
+    let runs = bench.sample_runs(None, || Ok(timing(seconds.next().unwrap()))).unwrap();
+    assert!(runs.len() > 2 && runs.len() < 20);
+
+    // noisy runs stop at the cap
+    let mut seconds = [1.0, 2.0].into_iter().cycle();
+    let runs = bench.sample_runs(None, || Ok(timing(seconds.next().unwrap()))).unwrap();
+    assert_eq!(runs.len(), 20);
+
+    // a timeout has nothing to settle
+    let runs = bench.sample_runs(None, || Ok(Timing::Timeout)).unwrap();
+    assert_eq!(runs.len(), 1);
+  }
+
+  #[test]
+  fn noisy_runs_warn_of_high_variance() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, ..) = fake_bench(dir.path(), Vec::new());
+
+    for (name, seconds) in [("steady", [1.0, 1.01, 0.99]), ("noisy", [1.0, 1.5, 2.0])] {
+      let mut program = Program::default();
+      *program.mode_mut(Mode::InterpretedC) = Some(Ok(seconds.map(timing).to_vec()));
+      bench.check_timings("main", name, &program);
+    }
+
+    let [Warning::HighVariance {
+      rev,
+      program,
+      mode,
+      coefficient,
+    }] = bench.warnings.as_slice()
+    else {
+      panic!(
+        "{}",
+        bench
+          .warnings
+          .iter()
+          .map(Warning::to_string)
+          .collect::<Vec<_>>()
+          .join("\n")
+      );
+    };
+    assert_eq!(
+      (rev.as_str(), program.as_str(), *mode),
+      ("main", "noisy", Mode::InterpretedC)
+    );
+    assert!((coefficient - 1.0 / 3.0).abs() < 1e-9, "{coefficient}");
+  }
+
+  #[test]
+  fn failed_runs_are_retried() {
+    let mut failures = 2;
+    let mut retried = Vec::new();
+    let result = retry(
+      3,
+      || match failures {
+        0 => Ok(Timing::Timeout),
+        _ => {
+          failures -= 1;
+          Err(RunError::Crash(anyhow::anyhow!("driver busy")))
+        }
+      },
+      |attempt, _| retried.push(attempt),
+    );
+    assert!(matches!(result, Ok(Timing::Timeout)));
+    assert_eq!(retried, [1, 2]);
+
+    let result = retry(
+      1,
+      || Err::<(), _>(RunError::Crash(anyhow::anyhow!("driver busy"))),
+      |_, _| {},
+    );
+    assert!(matches!(result, Err(RunError::Crash(_))));
+  }
+
+  #[test]
+  fn prebuilt_binaries_are_benchmarked_without_building() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, hvm, _) = fake_bench(dir.path(), Vec::new());
+    bench.locals.clear();
+    bench.prebuilt = vec![("v1".to_string(), hvm.clone()), ("v2".to_string(), hvm)];
+    bench.sampling.modes = vec![Mode::InterpretedC];
+    bench.run_options.verbosity = Verbosity::Quiet;
+
+    bench.bench().unwrap();
+
+    assert_eq!(bench.revisions(), ["v1", "v2"]);
+    assert!(matches!(bench.stats["v2"].programs["good"].interpreted_c, Some(Ok(_))));
+  }
+
+  #[test]
+  fn runs_stop_early_once_settled() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, ..) = fake_bench(dir.path(), Vec::new());
+    // a binary that takes `times` in turn, the next one kept in `counter`
+    let binary = |name: &str, times: &str| {
+      let counter = dir.path().join(format!("{name}.counter"));
+      let contents = format!(
+        "#!/bin/sh\nset -- {times}\ni=$(cat {counter:?} 2>/dev/null || echo 0)\necho $((i + 1)) > {counter:?}\nshift $((i % $#))\necho \"- TIME: $1s\"\n"
+      );
+      (name.to_string(), script(dir.path(), name, &contents))
+    };
+    bench.locals.clear();
+    // the baseline comes last, but is benchmarked first
+    bench.prebuilt = vec![
+      binary("slower", "2.0 2.1"),
+      binary("same", "1.0 1.01"),
+      binary("noisy", "0.5 1.5"),
+      binary("base", "1.0 1.01"),
+    ];
+    bench.sampling.modes = vec![Mode::InterpretedC];
+    bench.sampling.runs = 10;
+    bench.sampling.stop_early = Some(StopEarly {
+      baseline: "base".to_string(),
+      threshold: 5.0,
+    });
+    bench.run_options.verbosity = Verbosity::Quiet;
+
+    bench.bench().unwrap();
+
+    let runs = |rev: &str| match &bench.stats[rev].programs["good"].interpreted_c {
+      Some(Ok(timings)) => timings.len(),
+      _ => panic!("{rev} didn't run"),
+    };
+    assert_eq!(runs("base"), 10);
+    assert_eq!(runs("slower"), 2);
+    assert_eq!(runs("same"), 2);
+    assert_eq!(runs("noisy"), 10);
+  }
+
+  #[test]
+  fn runs_within_the_threshold_settle() {
+    let sampling = Sampling {
+      runs: 10,
+      stop_early: Some(StopEarly {
+        baseline: "base".to_string(),
+        threshold: 5.0,
+      }),
+      ..Sampling::default()
+    };
+    let baseline = [1.0, 1.01].map(timing);
+
+    // overlapping the baseline, but clearly less than 5% slower than it
+    let close = [1.0, 1.02].map(timing);
+    assert!(sampling.settled(&close, &baseline));
+    assert!(sampling.enough(&close, Some(&baseline)));
+
+    // could be either
+    let noisy = [0.5, 1.5].map(timing);
+    assert!(!sampling.settled(&noisy, &baseline));
+    assert!(!sampling.enough(&noisy, Some(&baseline)));
+  }
+
+  #[test]
+  fn baselines_that_did_not_complete_never_settle() {
+    let sampling = Sampling {
+      runs: 10,
+      stop_early: Some(StopEarly {
+        baseline: "base".to_string(),
+        threshold: 5.0,
+      }),
+      ..Sampling::default()
+    };
+    let baseline = [timing(1.0), Timing::Timeout, timing(1.01)];
+
+    for seconds in [[1.0, 1.01], [5.0, 5.01]] {
+      let timings = seconds.map(timing);
+      assert!(!sampling.settled(&timings, &baseline));
+      assert!(!sampling.enough(&timings, Some(&baseline)));
+    }
+  }
+
+  #[test]
+  fn partial_results_are_written_after_each_program() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, hvm, programs_dir) = fake_bench(dir.path(), Vec::new());
+    let path = dir.path().join("partial.json");
+    bench.partial_output = Some(PartialOutput {
+      path: path.clone(),
+      tags: Vec::new(),
+    });
+    bench.sampling.modes = vec![Mode::InterpretedC];
+
+    bench
+      .bench_bin("local", &hvm, programs_dir, &Progress::new(10, Verbosity::Quiet))
+      .unwrap();
+
+    let partial = format::parse_json(&fs::read_to_string(&path).unwrap()).unwrap();
+    let programs = &partial["local"].programs;
+    assert!(programs.contains_key("bad"));
+    assert!(programs.contains_key("good"));
+  }
+
+  #[test]
+  fn stale_binaries_are_warned_about() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, hvm, programs_dir) = fake_bench(dir.path(), Vec::new());
+    fs::write(
+      dir.path().join("Cargo.toml"),
+      "[package]\nname = \"hvm\"\nversion = \"2.1.0\"\n",
+    )
+    .unwrap();
+    let expected = cargo_version(dir.path()).unwrap();
+    bench.expected_versions.insert("local".to_string(), expected);
+    bench.sampling.modes = vec![Mode::InterpretedC];
+
+    bench
+      .bench_bin("local", &hvm, programs_dir, &Progress::new(10, Verbosity::Quiet))
+      .unwrap();
+
+    assert_eq!(bench.stats["local"].version.as_deref(), Some("2.0.0"));
+    assert!(matches!(
+      &bench.warnings[..],
+      [Warning::VersionMismatch { expected, actual, .. }] if expected == "2.1.0" && actual == "2.0.0"
+    ));
+  }
+
+  #[test]
+  fn failed_builds_are_build_errors() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, hvm, programs_dir) = fake_bench(dir.path(), vec!["v1".to_string()]);
+    bench
+      .failed_builds
+      .push(("v1".to_string(), "cargo build failed".to_string()));
+
+    bench
+      .bench_bin("local", &hvm, programs_dir, &Progress::new(10, Verbosity::Quiet))
+      .unwrap();
+    bench.record_failed_builds();
+
+    assert_eq!(bench.revisions(), ["v1", "local"]);
+    let programs = &bench.stats["v1"].programs;
+    assert!(matches!(programs["good"].interpreted_c, Some(Err(RunError::Build(_)))));
+    assert!(matches!(programs["bad"].compiled_c, Some(Err(RunError::Build(_)))));
+  }
+
+  /// Commits nothing with `message` to the repo at `repo`, returning the
+  /// commit's hash.
+  fn commit(repo: &Path, message: &str) -> String {
+    git(
+      repo,
+      &[
+        "-c",
+        "user.name=fork",
+        "-c",
+        "user.email=fork@example.com",
+        "commit",
+        "-q",
+        "--allow-empty",
+        "-m",
+        message,
+      ],
+    );
+    git(repo, &["rev-parse", "HEAD"]).trim().to_string()
+  }
+
+  /// Creates a repo at `remote`, or adds to the one there, with a commit on
+  /// `HEAD` and branch `feature`, and returns its hash.
+  fn fake_remote(remote: &Path) -> String {
+    git(remote, &["init", "-q"]);
+    let commit = commit(remote, "fork");
+    git(remote, &["branch", "-f", "feature"]);
+    commit
+  }
+
+  /// Returns a bench of `remote_revs` of a [`fake_remote`] in `dir/remote`,
+  /// with a [`fake_bench`] of `dir/local`, along with the fake `hvm` and the
+  /// commit of the remote's `HEAD`.
+  fn remote_bench(dir: &Path, remote_revs: Vec<String>) -> (Bench, PathBuf, String) {
+    let remote = dir.join("remote");
+    let local = dir.join("local");
+    fs::create_dir_all(&remote).unwrap();
+    fs::create_dir(&local).unwrap();
+    let commit = fake_remote(&remote);
+
+    let (mut bench, hvm, _) = fake_bench(&local, remote_revs);
+    bench.build_options.repo_url = remote.to_string_lossy().into_owned();
+
+    (bench, hvm, commit)
+  }
+
+  #[test]
+  fn remote_repos_are_cloned_without_contents() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, ..) = remote_bench(dir.path(), Vec::new());
+    let remote = dir.path().join("remote");
+    git(&remote, &["config", "uploadpack.allowFilter", "true"]);
+    bench.build_options.repo_url = format!("file://{}", remote.display());
+    bench.clone_remote().unwrap();
+
+    let filter = bench
+      .git()
+      .args(["config", "remote.origin.partialclonefilter"])
+      .status_stdout()
+      .unwrap();
+    assert_eq!(filter.trim(), "blob:none");
+  }
+
+  #[test]
+  fn revisions_of_the_same_commit_are_built_once() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, hvm, commit) = remote_bench(dir.path(), Vec::new());
+    bench.remote_revs = vec!["feature".to_string(), "feature".to_string(), commit.clone()];
+    bench.locals.clear();
+    bench.sampling.modes = vec![Mode::InterpretedC];
+    let cache_dir = dir.path().join("cache");
+    fs::create_dir_all(cache_dir.join(&commit)).unwrap();
+    fs::copy(&hvm, cache_dir.join(&commit).join("hvm")).unwrap();
+    bench.build_options.cache_dir = Some(cache_dir);
+
+    bench.clone_and_build().unwrap();
+    bench.bench_all().unwrap();
+
+    assert_eq!(bench.revisions(), ["feature", commit.as_str()]);
+    assert!(!bench.bin_dir().join(&commit).exists());
+    assert_eq!(bench.commits[&commit], commit);
+    assert!(bench.stats[&commit].programs.contains_key("good"));
+  }
+
+  #[test]
+  fn identical_binaries_are_benchmarked_once() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, hvm, fork) = remote_bench(dir.path(), Vec::new());
+    let next = commit(&dir.path().join("remote"), "next");
+    git(&dir.path().join("remote"), &["branch", "next"]);
+    // `feature` and `fork` are aliases, and `next` builds the same binary
+    bench.remote_revs = vec!["feature".to_string(), fork.clone(), "next".to_string()];
+    bench.locals.clear();
+    bench.skip_identical = true;
+    let counted = counting_compiler(&mut bench, dir.path());
+    let cache_dir = dir.path().join("cache");
+    for commit in [&fork, &next] {
+      fs::create_dir_all(cache_dir.join(commit)).unwrap();
+      fs::copy(&hvm, cache_dir.join(commit).join("hvm")).unwrap();
+    }
+    bench.build_options.cache_dir = Some(cache_dir);
+
+    bench.clone_and_build().unwrap();
+    bench.bench_all().unwrap();
+
+    assert_eq!(counted("compiles"), 1);
+    assert_eq!(counted("runs"), bench.sampling.warmup + bench.sampling.runs);
+    for rev in ["feature", fork.as_str(), "next"] {
+      assert!(
+        matches!(&bench.stats[rev].programs["good"].compiled_c, Some(Ok(_))),
+        "{rev} has no results"
+      );
+    }
+  }
+
+  #[test]
+  fn hung_checkouts_time_out() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, ..) = remote_bench(dir.path(), Vec::new());
+    bench.clone_remote().unwrap();
+    script(
+      &bench.remote_repo_dir().join(".git/hooks"),
+      "post-checkout",
+      "#!/bin/sh\nsleep 10\n",
+    );
+    bench.build_options.git_timeout = Duration::from_millis(200);
+
+    let err = bench.checkout_remote("feature").unwrap_err();
+
+    assert!(format!("{err:#}").contains("checking out feature timed out"), "{err:#}");
+  }
+
+  #[test]
+  fn revision_ranges_expand_to_their_commits() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, ..) = remote_bench(dir.path(), Vec::new());
+    let remote = dir.path().join("remote");
+    let commits = ["second", "third"].map(|message| commit(&remote, message)[..12].to_string());
+    bench.clone_remote().unwrap();
+
+    // a revision also within a range is benchmarked once
+    bench.build_options.max_revs = 2;
+    bench.remote_revs = vec!["feature..HEAD".to_string(), commits[1].clone()];
+    bench.expand_ranges().unwrap();
+    assert_eq!(bench.remote_revs, commits);
+
+    bench.build_options.max_revs = 1;
+    bench.remote_revs = vec!["feature..HEAD".to_string()];
+    let err = bench.expand_ranges().unwrap_err();
+    assert!(
+      err.to_string().contains("has 2 commits, more than --max-revs 1"),
+      "{err:#}"
+    );
+
+    bench.remote_revs = vec!["HEAD..feature".to_string()];
+    let err = bench.expand_ranges().unwrap_err();
+    assert!(err.to_string().contains("has no commits"), "{err:#}");
+  }
+
+  #[test]
+  fn commits_since_a_date_are_benchmarked() {
+    let dir = TempDir::new().unwrap();
+    let remote = dir.path().join("remote");
+    fs::create_dir(&remote).unwrap();
+    git(&remote, &["init", "-q"]);
+    for date in ["2000-01-01T00:00:00", "2000-06-01T00:00:00"] {
+      Command::new("git")
+        .current_dir(&remote)
+        .env("GIT_COMMITTER_DATE", date)
+        .args(["-c", "user.name=old", "-c", "user.email=old@example.com"])
+        .args(["commit", "-q", "--allow-empty", "-m", date])
+        .status_stdout()
+        .unwrap();
+    }
+
+    let (mut bench, ..) = remote_bench(dir.path(), Vec::new());
+    bench.build_options.max_revs = 2;
+    bench.build_options.since = Some("2000-03-01".to_string());
+    bench.clone_remote().unwrap();
+    bench.expand_since().unwrap();
+
+    let summaries = (bench.remote_revs.iter())
+      .map(|rev| {
+        let summary = bench.git().args(["log", "-1", "--format=%s", rev]).status_stdout();
+        summary.unwrap().trim().to_string()
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(summaries, ["2000-06-01T00:00:00", "fork"]);
+
+    bench.remote_revs.clear();
+    bench.build_options.since = Some("1d".to_string());
+    bench.expand_since().unwrap();
+    assert_eq!(bench.remote_revs.len(), 1);
+
+    // all three commits
+    bench.build_options.since = Some("1999-01-01".to_string());
+    assert!(bench.expand_since().is_err());
+  }
+
+  #[test]
+  fn revisions_resolve_against_a_custom_remote() {
+    let dir = TempDir::new().unwrap();
+    let (bench, _, commit) = remote_bench(dir.path(), Vec::new());
+    bench.clone_remote().unwrap();
+
+    assert_eq!(bench.resolve_rev("feature").unwrap(), commit);
+  }
+
+  #[test]
+  fn ambiguous_and_short_prefixes_are_reported() {
+    let dir = TempDir::new().unwrap();
+    let (bench, ..) = remote_bench(dir.path(), Vec::new());
+    // root commits of the empty tree with fixed dates, whose hashes share the
+    // prefix `84c34`
+    let remote = dir.path().join("remote");
+    for (branch, message) in [("a", "commit 291"), ("b", "commit 313")] {
+      let commit = Command::new("git")
+        .current_dir(&remote)
+        .envs([
+          ("GIT_AUTHOR_DATE", "1000000000 +0000"),
+          ("GIT_COMMITTER_DATE", "1000000000 +0000"),
+        ])
+        .args([
+          "-c",
+          "user.name=fork",
+          "-c",
+          "user.email=fork@example.com",
+          "commit-tree",
+        ])
+        .args(["4b825dc642cb6eb9a060e54bf8d69288fbee4904", "-m", message])
+        .status_stdout()
+        .unwrap();
+      git(&remote, &["branch", branch, commit.trim()]);
+    }
+    bench.clone_remote().unwrap();
+
+    let err = bench.rev_parse("84c34").unwrap_err().to_string();
+    assert!(err.contains("\"84c34\" is ambiguous"), "{err}");
+    assert!(
+      err.contains("84c34010f4b7fec178503407588e96cebd1941e3 commit 291"),
+      "{err}"
+    );
+    assert!(
+      err.contains("84c34a952e7891e9db38b9ceb60490c2a2772c6d commit 313"),
+      "{err}"
+    );
+
+    let err = bench.rev_parse("84c").unwrap_err().to_string();
+    assert!(err.contains("\"84c\" is too short"), "{err}");
+
+    assert_eq!(
+      bench.rev_parse("84c34a").unwrap(),
+      "84c34a952e7891e9db38b9ceb60490c2a2772c6d"
+    );
+  }
+
+  #[test]
+  fn merge_bases_resolve_against_the_local_head() {
+    let dir = TempDir::new().unwrap();
+    let (mut bench, _, fork) = remote_bench(dir.path(), Vec::new());
+    let (remote, local) = (dir.path().join("remote"), dir.path().join("local"));
+    // `HEAD` and `other` both move on from `fork`
+    git(&remote, &["checkout", "-q", "-b", "other"]);
+    let other = commit(&remote, "other");
+    git(&remote, &["checkout", "-q", "-"]);
+    commit(&remote, "main");
+    // the local repo has `other` checked out, with a commit of its own
+    git(&local, &["init", "-q"]);
+    git(&local, &["fetch", "-q", &remote.to_string_lossy(), "other"]);
+    git(&local, &["checkout", "-q", "FETCH_HEAD"]);
+    commit(&local, "local");
+    bench.clone_remote().unwrap();
+
+    assert_eq!(bench.resolve_rev("merge-base:HEAD").unwrap(), fork);
+    assert_eq!(bench.resolve_rev("merge-base:HEAD,other").unwrap(), fork);
+    assert_eq!(bench.resolve_rev("merge-base:other,other").unwrap(), other);
+
+    bench.locals.clear();
+    let err = bench.resolve_rev("merge-base:HEAD").unwrap_err();
+    assert!(err.to_string().contains("needs a local repo"), "{err:#}");
+    assert_eq!(bench.resolve_rev("merge-base:HEAD,other").unwrap(), fork);
+  }
+
+  #[test]
+  fn invalid_revisions_are_reported_together() {
+    let dir = TempDir::new().unwrap();
+    let revs = ["feature", "featuer", "mian..feature"].map(String::from).to_vec();
+    let (bench, ..) = remote_bench(dir.path(), revs);
+    bench.clone_remote().unwrap();
+
+    let err = bench.validate_revs().unwrap_err().to_string();
+    assert!(err.contains("featuer"), "{err}");
+    assert!(err.contains("mian"), "{err}");
+    assert!(!err.contains("feature:"), "{err}");
+  }
+
+  #[test]
+  fn local_repos_are_labeled_by_directory_name() {
+    let dir = TempDir::new().unwrap();
+    for path in ["hvm-a", "hvm-b", "other/hvm-a"] {
+      fs::create_dir_all(dir.path().join(path)).unwrap();
+    }
+    let local_dirs = |paths: &[&str]| paths.iter().map(|path| dir.path().join(path)).collect::<Vec<_>>();
+
+    assert_eq!(local_labels(&local_dirs(&["hvm-a"])).unwrap(), ["local"]);
+    assert_eq!(
+      local_labels(&local_dirs(&["hvm-a", "hvm-b"])).unwrap(),
+      ["hvm-a", "hvm-b"]
+    );
+    assert!(local_labels(&local_dirs(&["hvm-a", "other/hvm-a"])).is_err());
+  }
+
+  #[test]
+  fn local_commits_are_dirty_with_uncommitted_changes() {
+    let dir = TempDir::new().unwrap();
+    let commit = fake_remote(dir.path());
+
+    assert_eq!(local_commit(dir.path()), Some(commit.clone()));
+    // the label doesn't follow the commit
+    assert_eq!(local_labels(&[dir.path().to_path_buf()]).unwrap(), ["local"]);
+
+    fs::write(dir.path().join("new.hvm"), "").unwrap();
+    assert_eq!(local_commit(dir.path()), Some(format!("{commit}{DIRTY_SUFFIX}")));
+
+    let subdir = dir.path().join("sub");
+    fs::create_dir(&subdir).unwrap();
+    assert_eq!(local_commit(&subdir), None);
   }
 }
@@ -0,0 +1,104 @@
+use std::{ffi::OsString, fs, path::Path};
+
+use anyhow::{Context, Result};
+use clap::{parser::ValueSource, ArgMatches, Command};
+
+/// Returns the arguments the config file at `path` gives to `command`, written
+/// as on the command line. Keys are the long names of the arguments, e.g.
+/// `c-flags`, and arguments `matches` got from the command line are left out,
+/// so that they take precedence over the file.
+pub fn args(path: &Path, command: &Command, matches: &ArgMatches) -> Result<Vec<OsString>> {
+  let contents = fs::read_to_string(path).with_context(|| format!("read {path:?}"))?;
+  let table: toml::Table = toml::from_str(&contents).with_context(|| format!("parse {path:?}"))?;
+
+  let mut args = Vec::new();
+  for (key, value) in table {
+    let Some(arg) = (command.get_arguments()).find(|arg| arg.get_long() == Some(key.as_str()) && key != "config")
+    else {
+      anyhow::bail!("unknown key {key:?} in {path:?}");
+    };
+
+    if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+      continue;
+    }
+
+    if !arg.get_action().takes_values() {
+      match value {
+        toml::Value::Boolean(true) => args.push(format!("--{key}").into()),
+        toml::Value::Boolean(false) => {}
+        _ => anyhow::bail!("{key:?} in {path:?} must be true or false"),
+      }
+      continue;
+    }
+
+    let values = match value {
+      toml::Value::Array(values) => values,
+      value => vec![value],
+    };
+    for value in values {
+      let value = match value {
+        toml::Value::String(value) => value,
+        toml::Value::Integer(value) => value.to_string(),
+        toml::Value::Float(value) => value.to_string(),
+        toml::Value::Boolean(value) => value.to_string(),
+        value => anyhow::bail!("unsupported value {value} for {key:?} in {path:?}"),
+      };
+
+      // attached, so that values starting with a hyphen, e.g. compiler flags,
+      // aren't taken for arguments
+      args.push(format!("--{key}={value}").into());
+    }
+  }
+
+  Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+  use clap::{CommandFactory, Parser};
+  use tempfile::NamedTempFile;
+
+  use super::*;
+
+  #[derive(Parser)]
+  struct Args {
+    #[arg(long)]
+    revs: Vec<String>,
+    #[arg(long, default_value_t = 60)]
+    timeout: u64,
+    #[arg(long, allow_hyphen_values = true)]
+    c_flags: Option<String>,
+    #[arg(long)]
+    no_cache: bool,
+  }
+
+  fn config_args(config: &str, cli: &[&str]) -> Result<Vec<OsString>> {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), config).unwrap();
+
+    let command = Args::command();
+    let matches = command.clone().get_matches_from(["bench"].iter().chain(cli));
+    args(file.path(), &command, &matches)
+  }
+
+  #[test]
+  fn command_line_overrides_config() {
+    let config = r#"
+      revs = ["main", "v1"]
+      timeout = 10
+      c-flags = "-O3"
+      no-cache = true
+    "#;
+
+    let args = config_args(config, &["--timeout", "20"]).unwrap();
+    assert_eq!(args, ["--c-flags=-O3", "--no-cache", "--revs=main", "--revs=v1"]);
+
+    let parsed = Args::parse_from(["bench", "--timeout", "20"].into_iter().map(OsString::from).chain(args));
+    assert_eq!(parsed.revs, ["main", "v1"]);
+    assert_eq!(parsed.timeout, 20);
+    assert_eq!(parsed.c_flags.as_deref(), Some("-O3"));
+    assert!(parsed.no_cache);
+
+    assert!(config_args("runz = 3", &[]).is_err());
+  }
+}
@@ -0,0 +1,150 @@
+//! Describes the machine a benchmark runs on, so that shared results can be
+//! reproduced and compared with results from elsewhere.
+
+use std::{
+  env::consts::{ARCH, OS},
+  fs,
+  process::Command,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::run;
+
+/// What the timings of a benchmark depend on besides the revisions. Anything
+/// that couldn't be found out is `None`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Environment {
+  /// Model name of the CPU, e.g. `AMD Ryzen 9 7950X 16-Core Processor`.
+  pub cpu: Option<String>,
+  /// Number of CPUs available to the benchmark.
+  pub cores: usize,
+  /// Operating system and architecture, e.g. `Ubuntu 22.04.4 LTS (linux
+  /// 6.5.0-41-generic, x86_64)`.
+  pub os: String,
+  /// Models of the GPUs `nvidia-smi` lists, comma-separated.
+  pub gpu: Option<String>,
+  /// Version of the compiler of the compiled C mode.
+  pub c_compiler: Option<String>,
+  /// Version of the compiler of the compiled CUDA mode.
+  pub cuda_compiler: Option<String>,
+  /// Version of the `rustc` hvm is built with.
+  pub rustc: Option<String>,
+}
+
+impl Environment {
+  /// Gathers the environment of the host. The compilers and `nvidia-smi` are
+  /// run the way `options` runs commands, e.g. in its docker container, since
+  /// that's where hvm is built and run.
+  pub fn detect(options: &run::Options) -> Self {
+    let output = |program: &str, args: &[&str]| {
+      let output = options.command(program).args(args).output().ok()?;
+      output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    let version = |program: &str| output(program, &["--version"]).and_then(|output| version_line(&output));
+
+    let gpus = output("nvidia-smi", &["--query-gpu=name", "--format=csv,noheader"])
+      .map(|output| output.lines().map(str::trim).collect::<Vec<_>>().join(", "))
+      .filter(|gpus| !gpus.is_empty());
+
+    Self {
+      cpu: cpu_model(),
+      cores: std::thread::available_parallelism().map_or(1, usize::from),
+      os: os(),
+      gpu: gpus,
+      c_compiler: version(&options.c_compiler.program),
+      cuda_compiler: version(&options.cuda_compiler.program),
+      rustc: version("rustc"),
+    }
+  }
+
+  /// Returns the name and value of each field that's known, in the order
+  /// they're shown.
+  pub fn fields(&self) -> Vec<(&'static str, String)> {
+    let fields = [
+      ("cpu", self.cpu.clone()),
+      ("cores", Some(self.cores.to_string())),
+      ("os", Some(self.os.clone())),
+      ("gpu", self.gpu.clone()),
+      ("c compiler", self.c_compiler.clone()),
+      ("cuda compiler", self.cuda_compiler.clone()),
+      ("rustc", self.rustc.clone()),
+    ];
+
+    fields
+      .into_iter()
+      .filter_map(|(name, value)| Some((name, value?)))
+      .collect()
+  }
+}
+
+/// Returns the model name of the CPU, from `/proc/cpuinfo` on Linux and
+/// `sysctl` on macOS.
+fn cpu_model() -> Option<String> {
+  if let Ok(cpuinfo) = fs::read_to_string("/proc/cpuinfo") {
+    return cpuinfo.lines().find_map(|line| {
+      let (key, value) = line.split_once(':')?;
+      (key.trim() == "model name").then(|| value.trim().to_string())
+    });
+  }
+
+  let output = Command::new("sysctl")
+    .args(["-n", "machdep.cpu.brand_string"])
+    .output()
+    .ok()?;
+  let model = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+  (output.status.success() && !model.is_empty()).then_some(model)
+}
+
+/// Returns the name of the distribution, if known, with the kernel release
+/// and architecture.
+fn os() -> String {
+  let pretty_name = fs::read_to_string("/etc/os-release").ok().and_then(|release| {
+    release.lines().find_map(|line| {
+      let name = line.strip_prefix("PRETTY_NAME=")?;
+      Some(name.trim_matches('"').to_string())
+    })
+  });
+  let kernel = fs::read_to_string("/proc/sys/kernel/osrelease")
+    .map(|release| format!("{OS} {}", release.trim()))
+    .unwrap_or_else(|_| OS.to_string());
+
+  match pretty_name {
+    Some(name) => format!("{name} ({kernel}, {ARCH})"),
+    None => format!("{kernel} {ARCH}"),
+  }
+}
+
+/// Returns the line of the output of `--version` that has the version. That's
+/// the first one, except for `nvcc`, which starts with a banner and has it on
+/// the line with `release`.
+fn version_line(output: &str) -> Option<String> {
+  let mut lines = output.lines().map(str::trim).filter(|line| !line.is_empty());
+  let first = lines.clone().next()?;
+  let line = lines.find(|line| line.contains("release")).unwrap_or(first);
+
+  Some(line.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn compiler_versions() {
+    let gcc = "gcc (Ubuntu 11.4.0-1ubuntu1~22.04) 11.4.0\nCopyright (C) 2021 Free Software Foundation, Inc.\n";
+    assert_eq!(version_line(gcc).unwrap(), "gcc (Ubuntu 11.4.0-1ubuntu1~22.04) 11.4.0");
+
+    let nvcc = "nvcc: NVIDIA (R) Cuda compiler driver\nCopyright (c) 2005-2023 NVIDIA Corporation\nBuilt on Tue_Aug_15_22:02:13_PDT_2023\nCuda compilation tools, release 12.2, V12.2.140\nBuild cuda_12.2.r12.2/compiler.33191640_0\n";
+    assert_eq!(
+      version_line(nvcc).unwrap(),
+      "Cuda compilation tools, release 12.2, V12.2.140"
+    );
+
+    assert_eq!(version_line("\n"), None);
+  }
+}
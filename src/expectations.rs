@@ -0,0 +1,132 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+
+use crate::stats::{self, Aggregate, Mode, Stats, Timing};
+
+/// The maximum time a program may take in a mode.
+pub struct Expectation {
+  pub program: String,
+  pub mode: Mode,
+  pub max_seconds: f64,
+}
+
+/// Loads expectations from a file with one `<program> <mode> <max time>` per
+/// line, e.g. `sum_rec compiled-c 50ms`. Empty lines and lines starting with
+/// `#` are ignored.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Expectation>> {
+  let contents = fs::read_to_string(path).context("read")?;
+
+  contents
+    .lines()
+    .enumerate()
+    .map(|(i, line)| (i + 1, line.trim()))
+    .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+    .map(|(number, line)| parse_line(line).with_context(|| format!("line {number}")))
+    .collect()
+}
+
+fn parse_line(line: &str) -> Result<Expectation> {
+  let [program, mode, max] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+    anyhow::bail!("expected `<program> <mode> <max time>`, got {line:?}");
+  };
+
+  Ok(Expectation {
+    program: program.to_string(),
+    mode: Mode::from_str(mode, false).map_err(|err| anyhow::anyhow!("invalid mode {mode:?}: {err}"))?,
+    max_seconds: stats::parse_seconds(max).with_context(|| format!("invalid time {max:?}"))?,
+  })
+}
+
+/// Returns a description of every run in `stats` that took longer than
+/// expected, with repeated runs combined by `aggregate`. Timeouts count as
+/// taking longer, while failed and unselected runs have no timing and are not
+/// checked.
+pub fn violations(expectations: &[Expectation], stats: &BTreeMap<String, Stats>, aggregate: Aggregate) -> Vec<String> {
+  let mut violations = Vec::new();
+
+  for (revision, revision_stats) in stats {
+    for expectation in expectations {
+      let Some(program) = revision_stats.programs.get(&expectation.program) else {
+        continue;
+      };
+      let Some(Ok(timing)) = program.timing(expectation.mode, aggregate) else {
+        continue;
+      };
+
+      let exceeded = match &timing {
+        Timing::Completed { reported, .. } => reported.seconds > expectation.max_seconds,
+        Timing::Timeout | Timing::CompileTimeout => true,
+        Timing::Unsupported | Timing::Skipped => false,
+      };
+
+      if exceeded {
+        violations.push(format!(
+          "{revision} {} {} took {timing}, expected at most {}s",
+          expectation.program, expectation.mode, expectation.max_seconds
+        ));
+      }
+    }
+  }
+
+  violations
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use tempfile::TempDir;
+
+  use super::*;
+  use crate::stats::{Program, Reported};
+
+  fn stats(seconds: f64) -> Stats {
+    let timing = Timing::Completed {
+      reported: Reported::parse(&format!("{seconds}s")).unwrap(),
+      wall: Duration::from_secs_f64(seconds),
+      peak_memory: None,
+      compile_time: None,
+      counters: None,
+      throttled: false,
+    };
+    let mut program = Program::default();
+    *program.mode_mut(Mode::CompiledC) = Some(Ok(vec![timing]));
+
+    let mut stats = Stats::default();
+    stats.programs.insert("sum_rec".to_string(), program);
+    stats
+  }
+
+  #[test]
+  fn slower_runs_violate_expectations() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("expectations");
+    fs::write(&path, "# the tail recursive sum\n\nsum_rec compiled-c 50ms\n").unwrap();
+    let expectations = load(&path).unwrap();
+
+    let stats = BTreeMap::from([("fast".to_string(), stats(0.04)), ("slow".to_string(), stats(0.06))]);
+    assert_eq!(
+      violations(&expectations, &stats, Aggregate::Median),
+      ["slow sum_rec compiled-c took 0.06s, expected at most 0.05s"]
+    );
+  }
+
+  #[test]
+  fn malformed_lines_are_reported() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("expectations");
+    fs::write(&path, "sum_rec compiled-c 50ms\nsum_rec compiled-c\n").unwrap();
+
+    let err = format!("{:#}", load(&path).err().unwrap());
+    assert!(
+      err.starts_with("line 2: expected `<program> <mode> <max time>`"),
+      "{err}"
+    );
+
+    fs::write(&path, "sum_rec compiled-c fast\n").unwrap();
+    let err = format!("{:#}", load(&path).err().unwrap());
+    assert!(err.contains("invalid time \"fast\""), "{err}");
+  }
+}
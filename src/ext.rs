@@ -1,18 +1,144 @@
 use std::{
+  fmt,
   io::{Read, Write},
   process::{Child, Command, ExitStatus, Stdio},
+  thread::{self, JoinHandle},
   time::Duration,
 };
 
-use anyhow::{Context, Result};
-use tempfile::{Builder, NamedTempFile};
+#[cfg(unix)]
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use anyhow::{anyhow, Context, Result};
 use wait_timeout::ChildExt as WaitExt;
 
+/// The captured output of a command that ran to completion.
+pub struct Captured {
+  pub stdout: String,
+  pub stderr: String,
+}
+
+/// Reads `reader` to the end on another thread, so that a child filling one
+/// pipe can't block on it while we wait for it to exit.
+fn read_in_background<R: Read + Send + 'static>(mut reader: R) -> JoinHandle<std::io::Result<String>> {
+  thread::spawn(move || {
+    let mut output = Vec::new();
+    reader.read_to_end(&mut output)?;
+
+    Ok(String::from_utf8_lossy(&output).into_owned())
+  })
+}
+
+fn join_reader(reader: JoinHandle<std::io::Result<String>>) -> Result<String> {
+  reader
+    .join()
+    .map_err(|_| anyhow!("reader thread panicked"))?
+    .context("read")
+}
+
+/// Number of process groups of [`CommandExt::spawn_group`] that are killed on
+/// SIGINT or SIGTERM; groups spawned past it are left running.
+#[cfg(unix)]
+const MAX_GROUPS: usize = 64;
+
+/// Ids of the live process groups of [`CommandExt::spawn_group`], 0 for a free
+/// slot. Atomics, as they're read from a signal handler.
+#[cfg(unix)]
+static GROUPS: [AtomicI32; MAX_GROUPS] = [const { AtomicI32::new(0) }; MAX_GROUPS];
+
+/// Records `pgid` to be killed on SIGINT or SIGTERM, installing the handler
+/// that does so the first time.
+#[cfg(unix)]
+fn track_group(pgid: i32) {
+  static HANDLER: std::sync::Once = std::sync::Once::new();
+  HANDLER.call_once(|| {
+    let handler = kill_groups as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+      // SAFETY: the handler only makes async-signal-safe calls
+      unsafe { libc::signal(signal, handler) };
+    }
+  });
+
+  let _ = GROUPS.iter().find(|group| {
+    group
+      .compare_exchange(0, pgid, Ordering::SeqCst, Ordering::SeqCst)
+      .is_ok()
+  });
+}
+
+/// Forgets `pgid` once its leader was waited on, as the id may be reused.
+#[cfg(unix)]
+fn untrack_group(pgid: i32) {
+  let _ = GROUPS.iter().find(|group| {
+    group
+      .compare_exchange(pgid, 0, Ordering::SeqCst, Ordering::SeqCst)
+      .is_ok()
+  });
+}
+
+/// Kills the tracked process groups, which don't get the terminal's Ctrl-C,
+/// then dies of `signal` as if it had no handler.
+#[cfg(unix)]
+extern "C" fn kill_groups(signal: libc::c_int) {
+  for group in &GROUPS {
+    let pgid = group.load(Ordering::SeqCst);
+    if pgid != 0 {
+      // SAFETY: kill and signal are async-signal-safe and take no pointers
+      unsafe { libc::kill(-pgid, libc::SIGKILL) };
+    }
+  }
+
+  // SAFETY: as above, and raise is async-signal-safe too
+  unsafe {
+    libc::signal(signal, libc::SIG_DFL);
+    libc::raise(signal);
+  }
+}
+
+/// Number of trailing lines of stderr shown by [`ExitError`].
+const STDERR_TAIL_LINES: usize = 10;
+
+/// A command exited with a non-zero status.
+#[derive(Debug)]
+pub struct ExitError {
+  pub status: ExitStatus,
+  /// Captured stderr of the command, if any.
+  pub stderr: String,
+}
+
+impl fmt::Display for ExitError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "exited with non-zero status {}", self.status)?;
+
+    // the end of stderr usually says what went wrong
+    let lines = self.stderr.trim_end().lines().collect::<Vec<_>>();
+    let tail = &lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..];
+    if !tail.is_empty() {
+      write!(f, ", stderr:\n{}", tail.join("\n"))?;
+    }
+
+    Ok(())
+  }
+}
+
+impl std::error::Error for ExitError {}
+
 #[extend::ext]
 pub impl ExitStatus {
   fn check_success(&self) -> Result<()> {
+    self.check_success_stderr("")
+  }
+
+  /// Like `check_success`, but attaches the command's stderr to the error.
+  fn check_success_stderr(&self, stderr: &str) -> Result<()> {
     if !self.success() {
-      anyhow::bail!("exited with non-zero status {self}");
+      return Err(
+        ExitError {
+          status: *self,
+          stderr: stderr.to_string(),
+        }
+        .into(),
+      );
     }
 
     Ok(())
@@ -29,9 +155,7 @@ pub impl Child {
   /// Returns an error if the exit status was non-zero. On timeout, returns
   /// `Ok(None)`.
   fn check_success_timeout(&mut self, timeout: Duration) -> Result<Option<()>> {
-    let Some(status) = self.wait_timeout(timeout).context("wait")? else {
-      self.kill().expect("failed to kill child after timeout");
-
+    let Some(status) = self.wait_timeout_or_kill(timeout)? else {
       return Ok(None);
     };
 
@@ -39,6 +163,47 @@ pub impl Child {
 
     Ok(Some(()))
   }
+
+  /// Waits for the child to exit, killing it on timeout and returning
+  /// `Ok(None)`. If it leads a process group, see
+  /// [`CommandExt::spawn_group`], the whole group is killed.
+  fn wait_timeout_or_kill(&mut self, timeout: Duration) -> Result<Option<ExitStatus>> {
+    let Some(status) = self.wait_timeout(timeout).context("wait")? else {
+      self.kill_group().expect("failed to kill child after timeout");
+      self.wait().context("wait")?;
+
+      return Ok(None);
+    };
+
+    Ok(Some(status))
+  }
+
+  /// Like [`ChildExt::wait_timeout_or_kill`] for a child of
+  /// [`CommandExt::spawn_group`], which is no longer killed on SIGINT or
+  /// SIGTERM once this returns.
+  fn wait_group(&mut self, timeout: Duration) -> Result<Option<ExitStatus>> {
+    let status = self.wait_timeout_or_kill(timeout);
+    #[cfg(unix)]
+    untrack_group(i32::try_from(self.id()).expect("pid fits in i32"));
+
+    status
+  }
+
+  /// Kills the process group the child leads, so that the processes it
+  /// spawned, e.g. a compiled program under `time` or a CUDA run's helpers,
+  /// don't outlive it. Kills just the child if it doesn't lead a group.
+  fn kill_group(&mut self) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+      let pgid = libc::pid_t::try_from(self.id()).expect("pid fits in pid_t");
+      // SAFETY: kill takes no pointers; a negative pid signals the group
+      if unsafe { libc::kill(-pgid, libc::SIGKILL) } == 0 {
+        return Ok(());
+      }
+    }
+
+    self.kill()
+  }
 }
 
 #[extend::ext]
@@ -54,33 +219,61 @@ pub impl Command {
     // and waited on does not work for the compilers `gcc` and `nvcc`, they just
     // hang on `wait()`.
     let output = self.output().context("output")?;
-    output.status.check_success()?;
 
     std::io::stderr().write_all(&output.stderr).context("write")?;
+    output
+      .status
+      .check_success_stderr(&String::from_utf8_lossy(&output.stderr))?;
 
     Ok(String::from_utf8_lossy(&output.stdout).into_owned())
   }
 
-  /// Runs the command, capturing only stdout, returning an error on non-zero
-  /// exit, or `Ok(None)` on timeout.
+  /// Like [`CommandExt::status_stdout`], but kills the command and returns
+  /// `Ok(None)` if it takes longer than `timeout`.
   fn status_stdout_timeout(&mut self, timeout: Duration) -> Result<Option<String>> {
-    let mut child = self.stdout(Stdio::piped()).spawn().context("spawn")?;
-    let mut stdout = child.stdout.take().context("stdout")?;
-
-    if child.check_success_timeout(timeout)?.is_none() {
+    let Some(captured) = self.status_output_timeout(timeout)? else {
       return Ok(None);
-    }
+    };
 
-    let mut output = String::new();
-    stdout.read_to_string(&mut output).context("read")?;
+    std::io::stderr()
+      .write_all(captured.stderr.as_bytes())
+      .context("write")?;
 
-    Ok(Some(output))
+    Ok(Some(captured.stdout))
   }
-}
 
-#[extend::ext]
-pub impl NamedTempFile {
-  fn with_suffix(suffix: &str) -> Result<NamedTempFile> {
-    Builder::new().suffix(suffix).tempfile().context("tempfile")
+  /// Spawns the command in a process group of its own on Unix, which
+  /// [`ChildExt::kill_group`] kills with everything the command spawned. As
+  /// the group isn't the terminal's, Ctrl-C doesn't reach it, so the group is
+  /// killed on SIGINT or SIGTERM until [`ChildExt::wait_group`] reaps it.
+  fn spawn_group(&mut self) -> Result<Child> {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(self, 0);
+
+    let child = self.spawn().context("spawn")?;
+    #[cfg(unix)]
+    track_group(i32::try_from(child.id()).expect("pid fits in i32"));
+
+    Ok(child)
+  }
+
+  /// Runs the command, capturing stdout and stderr, returning an error with
+  /// the stderr on non-zero exit, or `Ok(None)` on timeout, after killing
+  /// the processes it spawned.
+  fn status_output_timeout(&mut self, timeout: Duration) -> Result<Option<Captured>> {
+    let mut child = self.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn_group()?;
+    let stdout = read_in_background(child.stdout.take().context("stdout")?);
+    let stderr = read_in_background(child.stderr.take().context("stderr")?);
+
+    let Some(status) = child.wait_group(timeout)? else {
+      return Ok(None);
+    };
+
+    let stdout = join_reader(stdout).context("stdout")?;
+    let stderr = join_reader(stderr).context("stderr")?;
+
+    status.check_success_stderr(&stderr)?;
+
+    Ok(Some(Captured { stdout, stderr }))
   }
 }
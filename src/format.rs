@@ -1,56 +1,308 @@
-use std::{collections::BTreeMap, fmt::Write};
+use std::{
+  cell::Cell,
+  collections::BTreeMap,
+  fmt::Write,
+  io::{self, IsTerminal},
+  time::Duration,
+};
 
-use anyhow::Result;
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
 
-use crate::stats::{Program, Stats};
+use crate::{
+  bench::DIRTY_SUFFIX,
+  environment::Environment,
+  programs,
+  run::RunError,
+  stats::{self, Aggregate, Mode, Program, Runs, Stats, Timing, Warning},
+};
 
 const COLUMN_WIDTH: usize = 14;
 const COLUMN_PADDING: &str = "  ";
+/// Number of characters of commit hashes shown in headers.
+const SHORT_HASH_LEN: usize = 7;
+/// Marks cells of `compare` that regressed beyond the threshold.
+const REGRESSION_MARK: &str = "!";
+/// Marks cells whose program printed a different result than in the leftmost
+/// revision.
+const MISMATCH_MARK: &str = "⚠";
+/// Marks the cells with runs the GPU was thermally throttled around.
+const THROTTLED_MARK: &str = "*";
+/// Relative difference in time below which [`format_verdict`] counts a
+/// program as a tie.
+const TIE_TOLERANCE: f64 = 0.02;
+/// Version of the structure of the JSON output, bumped whenever a field is
+/// renamed, removed or changes meaning, so that tooling reading it can tell.
+/// Added fields don't bump it. Output from before it was versioned has none,
+/// which reads as 0. Since version 2, the programs of a revision are under
+/// `programs` rather than next to its other fields.
+pub const JSON_SCHEMA_VERSION: u32 = 2;
+/// Start of the page written by [`format_html`], up to its tables.
+const HTML_HEAD: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>hvm-bench</title>
+<style>
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { padding: 0.3em 0.8em; border-bottom: 1px solid #ddd; }
+th { cursor: pointer; text-align: left; user-select: none; }
+th[data-order="ascending"]::after { content: " ▲"; }
+th[data-order="descending"]::after { content: " ▼"; }
+td[data-sort] { text-align: right; font-family: monospace; }
+</style>
+</head>
+<body>
+<h1>hvm-bench</h1>
+"#;
+/// End of the page written by [`format_html`], sorting a table by the column
+/// whose header is clicked: by `data-sort` for cells that have it, and by text
+/// otherwise.
+const HTML_TAIL: &str = r#"<script>
+for (const th of document.querySelectorAll("table.sortable th")) {
+  th.addEventListener("click", () => {
+    const ascending = th.dataset.order !== "ascending";
+    for (const other of th.parentElement.children) delete other.dataset.order;
+    th.dataset.order = ascending ? "ascending" : "descending";
 
-fn format_header<'a, I: IntoIterator<Item = &'a str>>(revisions: I) -> String {
-  let header = vec!["file", "runtime"]
-    .into_iter()
-    .chain(revisions)
-    .map(|col| format!("{col:<COLUMN_WIDTH$}"))
-    .collect::<Vec<_>>()
-    .join(COLUMN_PADDING);
+    const key = (row) => {
+      const cell = row.cells[th.cellIndex];
+      return cell.hasAttribute("data-sort") ? parseFloat(cell.dataset.sort || "NaN") : cell.textContent;
+    };
+    const compare = (a, b) => {
+      const [x, y] = [key(a), key(b)];
+      if (Number.isNaN(x) || Number.isNaN(y)) return Number.isNaN(x) - Number.isNaN(y);
+      const order = x < y ? -1 : x > y ? 1 : 0;
+      return ascending ? order : -order;
+    };
 
-  format!("{header}\n{}", "=".repeat(header.len()))
+    const body = th.closest("table").tBodies[0];
+    body.append(...[...body.rows].sort(compare));
+  });
 }
+</script>
+</body>
+</html>
+"#;
 
-macro_rules! writeln_row {
-  ($mode:ident, $rows:ident, $revisions:ident, $program:expr, $runtime:expr) => {{
-    let row = vec![$program, $runtime]
-      .into_iter()
-      .chain(
-        $revisions
-          .values()
-          .rev()
-          .map(|r| r.$mode($runtime).as_deref().unwrap_or("error")),
-      )
-      .enumerate()
-      .map(|(i, col)| {
-        if i < 2 {
-          format!("{col:<COLUMN_WIDTH$}")
-        } else {
-          format!("{col:>COLUMN_WIDTH$}")
-        }
-      })
-      .collect::<Vec<_>>()
-      .join(COLUMN_PADDING);
+/// How results are printed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+  #[default]
+  Table,
+  Json,
+  Csv,
+  Markdown,
+  Jsonl,
+  Tsv,
+  Html,
+  Pretty,
+}
 
-    writeln!($rows, "{row}")?;
+/// What the cells of the tables show.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Metric {
+  #[default]
+  Time,
+  Itrs,
+  Mips,
+  Memory,
+  CompileTime,
+  Cycles,
+  Instructions,
+  CacheMisses,
+}
 
-    row
-  }};
+/// When the tables are colored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Color {
+  #[default]
+  Auto,
+  Always,
+  Never,
 }
 
-fn by_program_revision(stats: &BTreeMap<String, Stats>) -> BTreeMap<String, BTreeMap<String, &Program>> {
-  let mut by_program_revision: BTreeMap<String, BTreeMap<String, &Program>> = BTreeMap::new();
-  for (revision, programs) in stats {
+impl Color {
+  /// Returns whether to color, where `auto` colors only if stdout is a
+  /// terminal.
+  pub fn enabled(self) -> bool {
+    match self {
+      Color::Auto => io::stdout().is_terminal(),
+      Color::Always => true,
+      Color::Never => false,
+    }
+  }
+}
+
+/// Which way a cell of [`format_compare`] changed relative to the baseline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Change {
+  /// Slower by more than the threshold, or newly timing out.
+  Regressed,
+  /// Faster by more than the threshold, or no longer timing out.
+  Improved,
+}
+
+/// Options controlling how results are rendered.
+#[derive(Default)]
+pub struct Options {
+  /// Timings are rounded to a multiple of this before being displayed, so that
+  /// differences below it read as equal.
+  pub noise_floor: Option<Duration>,
+  /// Revisions shown in the tables. If empty, all revisions are shown.
+  pub display_revs: Vec<String>,
+  /// How the timings of repeated runs are combined into one.
+  pub aggregate: Aggregate,
+  /// Append the coefficient of variation of repeated runs to each cell.
+  pub show_variance: bool,
+  /// Append the number of runs to each cell, e.g. when it varies with
+  /// [`Sampling::until_stable`](crate::bench::Sampling::until_stable).
+  pub show_runs: bool,
+  /// What the cells of [`format()`] show.
+  pub metric: Metric,
+  /// Append to each cell of [`format()`] how many times faster it is than
+  /// this mode for the same program and revision.
+  pub ratio_base: Option<Mode>,
+  /// Color the cells of [`format()`] from green for the fastest revision of the
+  /// row to red for the slowest.
+  pub color: bool,
+  /// Draw the borders of the tables of [`format()`] with box-drawing
+  /// characters instead of `=` and `-` lines.
+  pub pretty: bool,
+  /// Commit hash of each remote revision, shown next to its name.
+  pub commits: BTreeMap<String, String>,
+  /// Column order of the revisions without a commit date, e.g. local repos.
+  /// Revisions missing from it come after, in reverse order of their names.
+  pub order: Vec<String>,
+}
+
+impl Options {
+  fn displays(&self, revision: &str) -> bool {
+    self.display_revs.is_empty() || self.display_revs.iter().any(|rev| rev == revision)
+  }
+
+  /// Returns the column header of `revision`, e.g. `main (a1b2c3d)`, or
+  /// `local (a1b2c3d-dirty)` for a local repo with uncommitted changes.
+  fn label(&self, revision: &str) -> String {
+    match self.commits.get(revision) {
+      Some(commit) if !commit.starts_with(revision) => {
+        let (hash, dirty) = match commit.strip_suffix(DIRTY_SUFFIX) {
+          Some(hash) => (hash, DIRTY_SUFFIX),
+          None => (commit.as_str(), ""),
+        };
+        format!("{revision} ({}{dirty})", &hash[..hash.len().min(SHORT_HASH_LEN)])
+      }
+      _ => revision.to_string(),
+    }
+  }
+}
+
+/// Returns the aggregated seconds of successful runs, rounded to the noise
+/// floor.
+fn seconds(timings: Option<&Runs>, options: &Options) -> Option<f64> {
+  rank_seconds(timings, options).filter(|seconds| seconds.is_finite())
+}
+
+/// Like [`seconds`], but infinite for runs that timed out, so that they rank
+/// as the slowest. See [`Timing::rank_seconds`].
+fn rank_seconds(timings: Option<&Runs>, options: &Options) -> Option<f64> {
+  let timings = timings?.as_ref().ok()?;
+  let seconds = stats::aggregate_timings(timings, options.aggregate).rank_seconds()?;
+
+  match options.noise_floor {
+    Some(floor) if seconds.is_finite() => Some(stats::round_seconds(seconds, floor)),
+    _ => Some(seconds),
+  }
+}
+
+/// Formats a single cell, aggregating its runs and rounding the result to the
+/// noise floor if it has one.
+fn format_timing(timings: Option<&Runs>, options: &Options) -> String {
+  let timings = match timings {
+    Some(Ok(timings)) => timings,
+    Some(Err(err)) => return err.label().to_string(),
+    None => return "-".to_string(),
+  };
+  let timing = stats::aggregate_timings(timings, options.aggregate);
+
+  if let Some(cell) = format_metric(&timing, options.metric) {
+    return cell;
+  }
+
+  let cell = match (options.noise_floor, timing.seconds()) {
+    (Some(floor), Some(seconds)) => {
+      // show as many decimals as the floor has significant ones
+      let decimals = (-floor.as_secs_f64().log10().floor()).max(0.0) as usize;
+
+      format!("{:.decimals$}s", stats::round_seconds(seconds, floor))
+    }
+    _ => timing.to_string(),
+  };
+
+  if timing.seconds().is_none() {
+    return cell;
+  }
+
+  let seconds = timings.iter().filter_map(Timing::seconds).collect::<Vec<_>>();
+  let cell = match stats::variation(&seconds) {
+    Some((_, coefficient)) if options.show_variance => format!("{cell} ±{:.1}%", coefficient * 100.0),
+    _ => cell,
+  };
+  match options.show_runs {
+    true => format!("{cell} ({} runs)", timings.len()),
+    false => cell,
+  }
+}
+
+/// Formats `metric` of a completed run, or returns `None` if the run didn't
+/// complete or `metric` is the time.
+fn format_metric(timing: &Timing, metric: Metric) -> Option<String> {
+  let Timing::Completed {
+    reported,
+    peak_memory,
+    compile_time,
+    counters,
+    ..
+  } = timing
+  else {
+    return None;
+  };
+
+  let cell = match metric {
+    Metric::Time => return None,
+    Metric::Itrs => reported.interactions.map(|interactions| interactions.to_string()),
+    Metric::Mips => reported.mips.map(|mips| format!("{mips:.1}")),
+    Metric::Memory => peak_memory.map(|bytes| format!("{:.1}MiB", bytes as f64 / (1 << 20) as f64)),
+    Metric::CompileTime => compile_time.map(|time| format!("{:.3}s", time.as_secs_f64())),
+    Metric::Cycles => counters
+      .as_ref()
+      .and_then(|counters| counters.cycles)
+      .map(|cycles| cycles.to_string()),
+    Metric::Instructions => (counters.as_ref())
+      .and_then(|counters| counters.instructions)
+      .map(|instructions| instructions.to_string()),
+    Metric::CacheMisses => (counters.as_ref())
+      .and_then(|counters| counters.cache_misses)
+      .map(|misses| misses.to_string()),
+  };
+
+  Some(cell.unwrap_or_else(|| "-".to_string()))
+}
+
+/// Returns the results of each program by revision, keyed by the category
+/// and name of the program, so that programs are sorted by category first.
+/// Programs without a category have an empty one, and come first.
+fn by_program_revision<'a>(
+  stats: &'a BTreeMap<String, Stats>,
+  options: &Options,
+) -> BTreeMap<(String, String), BTreeMap<String, &'a Program>> {
+  let mut by_program_revision: BTreeMap<(String, String), BTreeMap<String, &Program>> = BTreeMap::new();
+  for (revision, programs) in stats.iter().filter(|(revision, _)| options.displays(revision)) {
     for (program, stats) in &programs.programs {
+      let category = programs.categories.get(program).cloned().unwrap_or_default();
       by_program_revision
-        .entry(program.to_string())
+        .entry((category, program.to_string()))
         .or_default()
         .insert(revision.to_string(), stats);
     }
@@ -59,53 +311,1776 @@ fn by_program_revision(stats: &BTreeMap<String, Stats>) -> BTreeMap<String, BTre
   by_program_revision
 }
 
-fn format_compiled_rows(stats: &BTreeMap<String, Stats>) -> Result<String> {
-  let by_program_revision = by_program_revision(stats);
+/// Formats a table with a group of rows per program, with a row per mode in
+/// `modes`, under a header naming the displayed revisions. The cells are
+/// formatted by `cell`, given the results of each revision for the program.
+/// Each column is as wide as its widest cell. Programs in a category are
+/// listed under a subheading with its name.
+fn format_table<F>(stats: &BTreeMap<String, Stats>, options: &Options, modes: &[Mode], cell: F) -> Result<String>
+where
+  F: Fn(&BTreeMap<String, &Program>, &str, Mode) -> String,
+{
+  let columns = displayed_revisions(stats, options).collect::<Vec<_>>();
+  let header = vec!["file".to_string(), "runtime".to_string()]
+    .into_iter()
+    .chain(columns.iter().map(|revision| options.label(revision)))
+    .collect::<Vec<_>>();
+
+  let groups = by_program_revision(stats, options)
+    .into_iter()
+    .map(|((category, program), revisions)| {
+      let rows = (modes.iter())
+        .map(|&mode| {
+          let cells = columns.iter().map(|revision| cell(&revisions, revision, mode));
+          (mode, cells.collect::<Vec<_>>())
+        })
+        // rows of empty cells are left out, e.g. unchanged ones of a comparison
+        .filter(|(_, cells)| cells.iter().any(|cell| !cell.is_empty()))
+        .enumerate()
+        .map(|(i, (mode, cells))| {
+          let program = if i == 0 { program.as_str() } else { "" };
+          vec![program.to_string(), mode.runtime().to_string()]
+            .into_iter()
+            .chain(cells)
+            .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+      (category, rows)
+    })
+    .filter(|(_, rows)| !rows.is_empty())
+    .collect::<Vec<_>>();
+
+  let mut widths = header.iter().map(|col| visible_len(col)).collect::<Vec<_>>();
+  for row in groups.iter().flat_map(|(_, rows)| rows) {
+    for (width, col) in widths.iter_mut().zip(row) {
+      *width = (*width).max(visible_len(col));
+    }
+  }
+  // subheadings share the file column of the boxed table
+  if options.pretty {
+    for (category, _) in groups.iter().filter(|(category, _)| !category.is_empty()) {
+      widths[0] = widths[0].max(visible_len(category) + 1);
+    }
+  }
+
+  // the header, file and runtime columns are aligned to the left, the cells
+  // to the right
+  let format_row = |row: &[String], left: usize| {
+    (row.iter().zip(&widths).enumerate())
+      .map(|(i, (col, &width))| pad(col, width, i < left))
+      .collect::<Vec<_>>()
+  };
+
+  if options.pretty {
+    return format_boxed(&format_row(&header, header.len()), &groups, &widths, format_row);
+  }
+
+  let header = format_row(&header, header.len()).join(COLUMN_PADDING);
+  let mut table = format!("{header}\n{}\n", "=".repeat(visible_len(&header)));
+  let mut last_category = "";
+  for (category, group) in &groups {
+    if category != last_category {
+      writeln!(table, "{category}/")?;
+      last_category = category;
+    }
+
+    let mut row = String::new();
+    for cells in group {
+      row = format_row(cells, 2).join(COLUMN_PADDING);
+      writeln!(table, "{row}")?;
+    }
+
+    writeln!(table, "{}", "-".repeat(visible_len(&row)))?;
+  }
+
+  Ok(table)
+}
+
+/// Formats the rows of [`format_table`], already padded to `widths`, inside
+/// borders drawn with box-drawing characters, with a line between programs.
+/// Category subheadings get a row of their own.
+fn format_boxed<F>(
+  header: &[String],
+  groups: &[(String, Vec<Vec<String>>)],
+  widths: &[usize],
+  format_row: F,
+) -> Result<String>
+where
+  F: Fn(&[String], usize) -> Vec<String>,
+{
+  let line = |left: &str, middle: &str, right: &str| {
+    let cols = widths.iter().map(|&width| "─".repeat(width + 2)).collect::<Vec<_>>();
+    format!("{left}{}{right}", cols.join(middle))
+  };
+  let row = |cells: &[String]| format!("│ {} │", cells.join(" │ "));
+
+  let mut table = String::new();
+  writeln!(table, "{}", line("┌", "┬", "┐"))?;
+  writeln!(table, "{}", row(header))?;
+
+  let mut last_category = "";
+  for (category, group) in groups {
+    writeln!(table, "{}", line("├", "┼", "┤"))?;
+    if category != last_category {
+      let mut subheading = vec![format!("{category}/")];
+      subheading.resize(widths.len(), String::new());
+      writeln!(table, "{}", row(&format_row(&subheading, widths.len())))?;
+      last_category = category;
+    }
+
+    for cells in group {
+      writeln!(table, "{}", row(&format_row(cells, 2)))?;
+    }
+  }
+
+  writeln!(table, "{}", line("└", "┴", "┘"))?;
+
+  Ok(table)
+}
+
+/// Pads `cell` with spaces to `width` visible characters, aligning it to the
+/// left or to the right.
+fn pad(cell: &str, width: usize, left: bool) -> String {
+  let padding = " ".repeat(width.saturating_sub(visible_len(cell)));
+  if left {
+    format!("{cell}{padding}")
+  } else {
+    format!("{padding}{cell}")
+  }
+}
+
+/// Returns the modes that any displayed revision ran, so that modes nobody
+/// selected get no rows.
+fn displayed_modes(stats: &BTreeMap<String, Stats>, options: &Options) -> Vec<Mode> {
+  Mode::ALL
+    .into_iter()
+    .filter(|&mode| {
+      stats
+        .iter()
+        .filter(|(revision, _)| options.displays(revision))
+        .flat_map(|(_, stats)| stats.programs.values())
+        .any(|program| program.mode(mode).is_some())
+    })
+    .collect()
+}
+
+/// Returns the revisions shown in the tables, in column order: revisions with
+/// a commit date from oldest to newest, then the others, such as local repos,
+/// in the order of `options.order`.
+fn displayed_revisions<'a>(stats: &'a BTreeMap<String, Stats>, options: &'a Options) -> impl Iterator<Item = &'a str> {
+  let ordered = (options.order.iter())
+    .map(String::as_str)
+    .filter(|revision| stats.contains_key(*revision));
+  let unordered = (stats.keys().rev())
+    .map(String::as_str)
+    .filter(|revision| !options.order.iter().any(|rev| rev == revision));
+
+  let mut revisions = ordered.chain(unordered).collect::<Vec<_>>();
+  // stable, so that revisions committed at the same time keep their order
+  revisions.sort_by_key(|revision| stats[*revision].committed_at.map_or((1, 0), |time| (0, time)));
+
+  revisions.into_iter().filter(|revision| options.displays(revision))
+}
+
+pub fn format(stats: &BTreeMap<String, Stats>, options: &Options) -> Result<String> {
+  let columns = displayed_revisions(stats, options).collect::<Vec<_>>();
+  let mismatched = Cell::new(false);
+  let throttled = Cell::new(false);
+
+  let mut table = format_tables(stats, options, |revisions, revision, mode| {
+    let timings = revisions.get(revision).and_then(|r| r.mode(mode));
+    let mut cell = format_timing(timings, options);
+
+    if let Some(base) = options.ratio_base.filter(|&base| base != mode) {
+      let base_seconds = seconds(revisions.get(revision).and_then(|r| r.mode(base)), options);
+      if let (Some(base_seconds), Some(seconds)) = (base_seconds, seconds(timings, options)) {
+        if seconds > 0.0 {
+          cell = format!("{cell} ({:.1}x)", base_seconds / seconds);
+        }
+      }
+    }
+
+    if let Some(Ok(timings)) = timings {
+      if timings.iter().any(Timing::is_throttled) {
+        throttled.set(true);
+        cell = format!("{cell} {THROTTLED_MARK}");
+      }
+    }
+
+    let outputs = (columns.iter()).filter_map(|revision| Some((*revision, revisions.get(*revision)?.output(mode)?)));
+    if stats::output_mismatch(outputs).is_some_and(|(_, revs)| revs.contains(&revision)) {
+      mismatched.set(true);
+      cell = format!("{cell} {MISMATCH_MARK}");
+    }
+
+    match rank(revisions, &columns, revision, mode, options).filter(|_| options.color) {
+      Some(rank) => colorize(&cell, rank),
+      None => cell,
+    }
+  })?;
+
+  if mismatched.get() {
+    writeln!(
+      table,
+      "{MISMATCH_MARK} mismatch: printed a different result than in the leftmost revision"
+    )?;
+  }
+  if throttled.get() {
+    writeln!(
+      table,
+      "{THROTTLED_MARK} throttled: the gpu was thermally throttled around some runs"
+    )?;
+  }
+
+  Ok(table)
+}
+
+/// Returns where the cell of `revision` in `mode` ranks among the `columns` of
+/// its row, from 0 for the fastest to 1 for the slowest, or `None` if there's
+/// nothing to rank. Timeouts are the slowest, and the completed runs are
+/// ranked among themselves.
+fn rank(
+  revisions: &BTreeMap<String, &Program>,
+  columns: &[&str],
+  revision: &str,
+  mode: Mode,
+  options: &Options,
+) -> Option<f64> {
+  let row = (columns.iter())
+    .filter_map(|revision| rank_seconds(revisions.get(*revision).and_then(|r| r.mode(mode)), options))
+    .collect::<Vec<_>>();
+  let (fastest, slowest) = (row.iter().filter(|seconds| seconds.is_finite()))
+    .fold((f64::INFINITY, f64::NEG_INFINITY), |(fastest, slowest), &seconds| {
+      (fastest.min(seconds), slowest.max(seconds))
+    });
+  let timed_out = row.iter().any(|seconds| seconds.is_infinite());
 
-  let mut rows = String::new();
+  match rank_seconds(revisions.get(revision).and_then(|r| r.mode(mode)), options)? {
+    seconds if seconds.is_infinite() && fastest.is_finite() => Some(1.0),
+    seconds if seconds.is_finite() && slowest > fastest => Some((seconds - fastest) / (slowest - fastest)),
+    seconds if seconds.is_finite() && timed_out => Some(0.0),
+    _ => None,
+  }
+}
 
-  for (program, revisions) in &by_program_revision {
-    writeln_row!(compiled, rows, revisions, program, "c");
-    let row = writeln_row!(compiled, rows, revisions, "", "cuda");
+/// Returns the red and green of a gradient from green, for a `rank` of 0,
+/// through yellow to red, for a `rank` of 1.
+fn gradient(rank: f64) -> (u8, u8) {
+  let red = (255.0 * (2.0 * rank).min(1.0)) as u8;
+  let green = (255.0 * (2.0 * (1.0 - rank)).min(1.0)) as u8;
+
+  (red, green)
+}
+
+/// Colors `cell` by `rank`, see [`gradient`].
+fn colorize(cell: &str, rank: f64) -> String {
+  let (red, green) = gradient(rank);
+
+  format!("\x1b[38;2;{red};{green};0m{cell}\x1b[0m")
+}
 
-    writeln!(rows, "{}", "-".repeat(row.len()))?;
+/// Returns the number of characters of `text` shown on a terminal, i.e.
+/// without its color escape sequences.
+fn visible_len(text: &str) -> usize {
+  let mut len = 0;
+  let mut chars = text.chars();
+  while let Some(char) = chars.next() {
+    if char == '\x1b' {
+      chars.by_ref().find(|&char| char == 'm');
+    } else {
+      len += 1;
+    }
   }
 
-  Ok(rows)
+  len
 }
 
-fn format_interpreted_rows(stats: &BTreeMap<String, Stats>) -> Result<String> {
-  let by_program_revision = by_program_revision(stats);
+/// Formats the results relative to `baseline`, with each cell showing how much
+/// slower or faster the revision is than the baseline. Cells slower by more
+/// than `threshold` percent are marked, as are new timeouts. With `only`, just
+/// the cells that changed that way are shown, in the rows that have any.
+pub fn format_compare(
+  stats: &BTreeMap<String, Stats>,
+  baseline: &str,
+  threshold: f64,
+  only: Option<Change>,
+  options: &Options,
+) -> Result<String> {
+  if !stats.contains_key(baseline) {
+    anyhow::bail!("no results for baseline {baseline:?}");
+  }
+
+  let changed = |revisions: &BTreeMap<String, &Program>, revision: &str, mode| {
+    let baseline_timings = revisions.get(baseline).and_then(|r| r.mode(mode));
+    let timings = revisions.get(revision).and_then(|r| r.mode(mode));
+    only.is_none_or(|only| change(baseline_timings, timings, threshold, options) == Some(only))
+  };
+
+  let mut table = format_tables(stats, options, |revisions, revision, mode| {
+    let timings = revisions.get(revision).and_then(|r| r.mode(mode));
+    if revision == baseline {
+      // shown for reference, if any revision changed
+      let any_changed = (revisions.keys()).any(|revision| revision != baseline && changed(revisions, revision, mode));
+      return if any_changed {
+        format_timing(timings, options)
+      } else {
+        String::new()
+      };
+    }
+
+    if !changed(revisions, revision, mode) {
+      return String::new();
+    }
 
-  let mut rows = String::new();
+    let baseline_timings = revisions.get(baseline).and_then(|r| r.mode(mode));
+    format_delta(baseline_timings, timings, threshold, options)
+  })?;
 
-  for (program, revisions) in &by_program_revision {
-    writeln_row!(interpreted, rows, revisions, program, "c");
-    writeln_row!(interpreted, rows, revisions, "", "cuda");
-    let row = writeln_row!(interpreted, rows, revisions, "", "rust");
+  writeln!(
+    table,
+    "{REGRESSION_MARK} slower than {baseline} by more than {threshold}%, or timed out"
+  )?;
 
-    writeln!(rows, "{}", "-".repeat(row.len()))?;
+  Ok(table)
+}
+
+/// Formats the change of a cell relative to the same cell of the baseline,
+/// marking it if it regressed by more than `threshold` percent. Timeouts on
+/// either side are shown as such, since they have no meaningful percentage.
+fn format_delta(baseline: Option<&Runs>, timings: Option<&Runs>, threshold: f64, options: &Options) -> String {
+  let (Some(Ok(baseline_timings)), Some(Ok(revision_timings))) = (baseline, timings) else {
+    return format_timing(timings, options);
+  };
+
+  let baseline_timing = stats::aggregate_timings(baseline_timings, options.aggregate);
+  let timing = stats::aggregate_timings(revision_timings, options.aggregate);
+  match (&baseline_timing, &timing) {
+    (Timing::Completed { .. }, Timing::Timeout) => return format!("timeout {REGRESSION_MARK}"),
+    (Timing::Completed { .. }, Timing::CompileTimeout) => return format!("compile-timeout {REGRESSION_MARK}"),
+    (Timing::Timeout | Timing::CompileTimeout, Timing::Completed { .. }) => return "no timeout".to_string(),
+    _ => {}
   }
 
-  Ok(rows)
+  match delta_percent(baseline, timings, options) {
+    Some(delta) if delta > threshold => format!("{delta:+.1}% {REGRESSION_MARK}"),
+    Some(delta) => format!("{delta:+.1}%"),
+    None => format_timing(timings, options),
+  }
 }
 
-pub fn format(stats: &BTreeMap<String, Stats>) -> Result<String> {
+/// Returns how much slower `timings` are than `baseline`, in percent, if both
+/// completed.
+fn delta_percent(baseline: Option<&Runs>, timings: Option<&Runs>, options: &Options) -> Option<f64> {
+  match (seconds(baseline, options), seconds(timings, options)) {
+    (Some(baseline_seconds), Some(seconds)) if baseline_seconds > 0.0 => {
+      Some((seconds - baseline_seconds) / baseline_seconds * 100.0)
+    }
+    _ => None,
+  }
+}
+
+/// Returns which way a cell changed relative to the same cell of the baseline,
+/// if by more than `threshold` percent or from or to a timeout.
+fn change(baseline: Option<&Runs>, timings: Option<&Runs>, threshold: f64, options: &Options) -> Option<Change> {
+  match (rank_seconds(baseline, options)?, rank_seconds(timings, options)?) {
+    (baseline_seconds, seconds) if baseline_seconds.is_finite() && seconds.is_infinite() => Some(Change::Regressed),
+    (baseline_seconds, seconds) if baseline_seconds.is_infinite() && seconds.is_finite() => Some(Change::Improved),
+    _ => match delta_percent(baseline, timings, options)? {
+      delta if delta > threshold => Some(Change::Regressed),
+      delta if delta < -threshold => Some(Change::Improved),
+      _ => None,
+    },
+  }
+}
+
+/// Formats a table of the compiled modes and one of the interpreted modes,
+/// with cells formatted by `cell`.
+fn format_tables<F>(stats: &BTreeMap<String, Stats>, options: &Options, cell: F) -> Result<String>
+where
+  F: Fn(&BTreeMap<String, &Program>, &str, Mode) -> String,
+{
   let mut table = String::new();
 
-  writeln!(table, "compiled")?;
-  writeln!(table, "========")?;
-  writeln!(table)?;
+  let (compiled, interpreted): (Vec<Mode>, Vec<Mode>) = displayed_modes(stats, options)
+    .into_iter()
+    .partition(|mode| mode.is_compiled());
+
+  if !compiled.is_empty() {
+    writeln!(table, "compiled")?;
+    writeln!(table, "========")?;
+    writeln!(table)?;
+
+    writeln!(table, "{}", format_table(stats, options, &compiled, &cell)?)?;
+  }
+
+  if !interpreted.is_empty() {
+    writeln!(table, "interpreted")?;
+    writeln!(table, "===========")?;
+    writeln!(table)?;
+
+    writeln!(table, "{}", format_table(stats, options, &interpreted, &cell)?)?;
+  }
+
+  Ok(table)
+}
+
+/// Formats the results as GitHub-flavored markdown, e.g. for PR comments, with
+/// a table of the compiled modes and one of the interpreted modes.
+pub fn format_markdown(stats: &BTreeMap<String, Stats>, options: &Options) -> Result<String> {
+  let mut markdown = String::new();
+
+  let by_program_revision = by_program_revision(stats, options);
+  let revisions = displayed_revisions(stats, options).collect::<Vec<_>>();
+  let (compiled, interpreted): (Vec<Mode>, Vec<Mode>) = displayed_modes(stats, options)
+    .into_iter()
+    .partition(|mode| mode.is_compiled());
 
-  writeln!(table, "{}", format_header(stats.keys().rev().map(String::as_str)))?;
-  writeln!(table, "{}", format_compiled_rows(stats)?)?;
+  for (title, modes) in [("compiled", compiled), ("interpreted", interpreted)] {
+    if modes.is_empty() {
+      continue;
+    }
+
+    writeln!(markdown, "### {title}")?;
+    writeln!(markdown)?;
+
+    let header = ["file".to_string(), "runtime".to_string()]
+      .into_iter()
+      .chain(revisions.iter().map(|revision| options.label(revision)));
+    writeln!(markdown, "{}", markdown_row(header))?;
+    let divider = ["---", "---"].into_iter().chain(revisions.iter().map(|_| "---:"));
+    writeln!(markdown, "{}", markdown_row(divider))?;
+
+    let mut last_category = "";
+    for ((category, program), program_revisions) in &by_program_revision {
+      if category != last_category {
+        // the rest of the row empty, under the runtime and each revision
+        let heading = std::iter::once(format!("**{category}/**")).chain((0..=revisions.len()).map(|_| String::new()));
+        writeln!(markdown, "{}", markdown_row(heading))?;
+        last_category = category;
+      }
+
+      for (i, &mode) in modes.iter().enumerate() {
+        let program = if i == 0 { program.as_str() } else { "" };
+        let cells = (revisions.iter())
+          .map(|revision| format_timing(program_revisions.get(*revision).and_then(|r| r.mode(mode)), options));
+        let row = [program.to_string(), mode.runtime().to_string()]
+          .into_iter()
+          .chain(cells);
+        writeln!(markdown, "{}", markdown_row(row))?;
+      }
+    }
 
-  writeln!(table, "interpreted")?;
-  writeln!(table, "===========")?;
+    writeln!(markdown)?;
+  }
+
+  Ok(markdown)
+}
+
+/// Formats the results as a self-contained HTML page, e.g. to share with
+/// people who won't read a terminal, with a table of the compiled modes and
+/// one of the interpreted modes. Clicking a column header sorts the rows by
+/// it, and each cell is colored like in [`format()`].
+pub fn format_html(stats: &BTreeMap<String, Stats>, options: &Options) -> Result<String> {
+  let mut html = String::new();
+  write!(html, "{HTML_HEAD}")?;
+
+  let by_program_revision = by_program_revision(stats, options);
+  let revisions = displayed_revisions(stats, options).collect::<Vec<_>>();
+  let (compiled, interpreted): (Vec<Mode>, Vec<Mode>) = displayed_modes(stats, options)
+    .into_iter()
+    .partition(|mode| mode.is_compiled());
+
+  for (title, modes) in [("compiled", compiled), ("interpreted", interpreted)] {
+    if modes.is_empty() {
+      continue;
+    }
+
+    writeln!(html, "<h2>{title}</h2>")?;
+    writeln!(html, "<table class=\"sortable\">")?;
+    write!(html, "<thead><tr><th>file</th><th>runtime</th>")?;
+    for revision in &revisions {
+      write!(html, "<th>{}</th>", escape_html(&options.label(revision)))?;
+    }
+    writeln!(html, "</tr></thead>")?;
+
+    writeln!(html, "<tbody>")?;
+    for ((category, program), program_revisions) in &by_program_revision {
+      // named after its category too, as there are no subheadings to keep
+      // together when sorting
+      let name = match category.as_str() {
+        "" => program.clone(),
+        category => format!("{category}/{program}"),
+      };
+      for &mode in &modes {
+        // every row names its program, as sorting breaks up the groups
+        write!(html, "<tr><td>{}</td><td>{}</td>", escape_html(&name), mode.runtime())?;
+        for revision in &revisions {
+          let timings = program_revisions.get(*revision).and_then(|r| r.mode(mode));
+          // timeouts sort after every time, and missing cells after those
+          let sort_key = match rank_seconds(timings, options) {
+            Some(seconds) if seconds.is_infinite() => "Infinity".to_string(),
+            Some(seconds) => seconds.to_string(),
+            None => String::new(),
+          };
+          let style = rank(program_revisions, &revisions, revision, mode, options).map_or(String::new(), |rank| {
+            let (red, green) = gradient(rank);
+            format!(" style=\"background: rgba({red}, {green}, 0, 0.3)\"")
+          });
+          write!(
+            html,
+            "<td data-sort=\"{sort_key}\"{style}>{}</td>",
+            escape_html(&format_timing(timings, options))
+          )?;
+        }
+        writeln!(html, "</tr>")?;
+      }
+    }
+    writeln!(html, "</tbody>")?;
+    writeln!(html, "</table>")?;
+  }
+
+  write!(html, "{HTML_TAIL}")?;
+
+  Ok(html)
+}
+
+/// Escapes the characters of `text` that are markup in HTML.
+fn escape_html(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+/// Joins `cells` into a row of a markdown table, escaping the `|` in them.
+fn markdown_row<I: IntoIterator<Item = S>, S: AsRef<str>>(cells: I) -> String {
+  let cells = (cells.into_iter())
+    .map(|cell| cell.as_ref().replace('|', "\\|"))
+    .collect::<Vec<_>>();
+
+  format!("| {} |", cells.join(" | "))
+}
+
+/// Formats the results as JSON. Timings are keyed by revision, then program,
+/// then mode, and hold every run so that they can be aggregated downstream.
+pub fn format_json(
+  stats: &BTreeMap<String, Stats>,
+  commits: &BTreeMap<String, String>,
+  tags: &[(String, String)],
+  environment: Option<&Environment>,
+  warnings: &[Warning],
+) -> Result<String> {
+  #[derive(Serialize)]
+  struct Report<'a> {
+    schema_version: u32,
+    tags: BTreeMap<&'a str, &'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    environment: Option<&'a Environment>,
+    warnings: &'a [Warning],
+    commits: &'a BTreeMap<String, String>,
+    revisions: &'a BTreeMap<String, Stats>,
+  }
+
+  let report = Report {
+    schema_version: JSON_SCHEMA_VERSION,
+    tags: tags.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect(),
+    environment,
+    warnings,
+    commits,
+    revisions: stats,
+  };
+
+  serde_json::to_string_pretty(&report).context("serialize")
+}
+
+/// Formats the result of `program` in `mode` of `revision` as a single line
+/// of JSON with `tags`, for `--format jsonl`. Every key is present, `null`
+/// where it doesn't apply, so that each line describes itself.
+pub fn format_jsonl(
+  revision: &str,
+  program: &str,
+  mode: Mode,
+  runs: &Runs,
+  tags: &[(String, String)],
+) -> Result<String> {
+  #[derive(Serialize)]
+  struct Line<'a> {
+    revision: &'a str,
+    program: &'a str,
+    mode: Mode,
+    runs: Option<&'a [Timing]>,
+    error: Option<String>,
+    failure: Option<&'static str>,
+    tags: BTreeMap<&'a str, &'a str>,
+  }
+
+  let (runs, error, failure) = match runs {
+    Ok(timings) => (Some(timings.as_slice()), None, None),
+    Err(err) => (None, Some(err.to_string()), Some(err.label())),
+  };
+  let line = Line {
+    revision,
+    program,
+    mode,
+    runs,
+    error,
+    failure,
+    tags: tags.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect(),
+  };
+
+  serde_json::to_string(&line).context("serialize")
+}
+
+/// Parses the results of every revision from JSON written by [`format_json`],
+/// of this or an older schema version.
+pub fn parse_json(json: &str) -> Result<BTreeMap<String, Stats>> {
+  #[derive(Deserialize)]
+  struct Report {
+    #[serde(default)]
+    schema_version: u32,
+    revisions: serde_json::Value,
+  }
+
+  /// The results of a revision before schema version 2, with the programs
+  /// next to the other fields.
+  #[derive(Deserialize)]
+  struct FlatStats {
+    #[serde(default)]
+    committed_at: Option<u64>,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    categories: BTreeMap<String, String>,
+    #[serde(flatten)]
+    programs: BTreeMap<String, Program>,
+  }
+
+  let report: Report = serde_json::from_str(json).context("deserialize")?;
+  ensure!(
+    report.schema_version <= JSON_SCHEMA_VERSION,
+    "schema version {} is newer than {JSON_SCHEMA_VERSION}, update hvm-bench to read it",
+    report.schema_version
+  );
+
+  if report.schema_version >= 2 {
+    return serde_json::from_value(report.revisions).context("deserialize revisions");
+  }
+
+  let revisions: BTreeMap<String, FlatStats> =
+    serde_json::from_value(report.revisions).context("deserialize flat revisions")?;
+  let revisions = (revisions.into_iter())
+    .map(|(revision, stats)| {
+      let stats = Stats {
+        committed_at: stats.committed_at,
+        version: stats.version,
+        categories: stats.categories,
+        programs: stats.programs,
+      };
+      (revision, stats)
+    })
+    .collect();
+
+  Ok(revisions)
+}
+
+/// Formats a summary of the whole benchmark as a single line of JSON, for
+/// programs driving it: how many programs were benchmarked, how many cells of
+/// each revision completed, timed out, were skipped, failed or failed to
+/// build, with repeated runs combined by `aggregate`, and how long it took.
+pub fn format_exit_summary(stats: &BTreeMap<String, Stats>, aggregate: Aggregate, elapsed: Duration) -> Result<String> {
+  #[derive(Default, Serialize)]
+  struct Counts {
+    successes: usize,
+    timeouts: usize,
+    skipped: usize,
+    errors: usize,
+    build_failures: usize,
+  }
+
+  #[derive(Serialize)]
+  struct Summary<'a> {
+    programs: usize,
+    wall_seconds: f64,
+    revisions: BTreeMap<&'a str, Counts>,
+  }
+
+  let mut revisions = BTreeMap::new();
+  for (revision, revision_stats) in stats {
+    let counts: &mut Counts = revisions.entry(revision.as_str()).or_default();
+    for program in revision_stats.programs.values() {
+      for timing in Mode::ALL.into_iter().filter_map(|mode| program.timing(mode, aggregate)) {
+        match timing {
+          Ok(Timing::Completed { .. }) => counts.successes += 1,
+          Ok(Timing::Timeout | Timing::CompileTimeout) => counts.timeouts += 1,
+          Ok(Timing::Unsupported | Timing::Skipped) => counts.skipped += 1,
+          Err(RunError::Build(_)) => counts.build_failures += 1,
+          Err(_) => counts.errors += 1,
+        }
+      }
+    }
+  }
+
+  let summary = Summary {
+    programs: by_program_revision(stats, &Options::default()).len(),
+    wall_seconds: elapsed.as_secs_f64(),
+    revisions,
+  };
+
+  serde_json::to_string(&summary).context("serialize")
+}
+
+/// Quotes a CSV field if it contains a separator, quote or newline.
+fn escape_csv(value: &str) -> String {
+  if value.contains([',', '"', '\n', '\r']) {
+    format!("\"{}\"", value.replace('"', "\"\""))
+  } else {
+    value.to_string()
+  }
+}
+
+/// Replaces the tabs and line breaks of a TSV field with spaces, as TSV has no
+/// quoting.
+fn escape_tsv(value: &str) -> String {
+  value.replace(['\t', '\n', '\r'], " ")
+}
+
+/// Formats the results as CSV, with one row per revision, program and mode in
+/// that order, and repeated runs combined by `aggregate`. Only completed runs
+/// have a timing.
+pub fn format_csv(stats: &BTreeMap<String, Stats>, aggregate: Aggregate) -> Result<String> {
+  format_delimited(stats, aggregate, ',', escape_csv)
+}
+
+/// Formats the results like [`format_csv`], but separated by tabs, so that
+/// they paste into a spreadsheet as columns.
+pub fn format_tsv(stats: &BTreeMap<String, Stats>, aggregate: Aggregate) -> Result<String> {
+  format_delimited(stats, aggregate, '\t', escape_tsv)
+}
+
+fn format_delimited(
+  stats: &BTreeMap<String, Stats>,
+  aggregate: Aggregate,
+  separator: char,
+  escape: fn(&str) -> String,
+) -> Result<String> {
+  let mut formatted = String::new();
+
+  let header = [
+    "revision",
+    "program",
+    "mode",
+    "status",
+    "seconds",
+    "wall_seconds",
+    "runs",
+  ];
+  writeln!(formatted, "{}", header.join(&separator.to_string()))?;
+
+  for (revision, revision_stats) in stats {
+    for (program, program_stats) in &revision_stats.programs {
+      for mode in Mode::ALL {
+        let Some(timing) = program_stats.timing(mode, aggregate) else {
+          continue;
+        };
+        let runs = program_stats
+          .mode(mode)
+          .and_then(|timings| timings.as_ref().ok())
+          .map_or(0, Vec::len);
+        let (status, seconds, wall) = match timing {
+          Ok(Timing::Completed { reported, wall, .. }) => {
+            ("ok", reported.seconds.to_string(), wall.as_secs_f64().to_string())
+          }
+          Ok(Timing::Timeout) => ("timeout", String::new(), String::new()),
+          Ok(Timing::CompileTimeout) => ("compile-timeout", String::new(), String::new()),
+          Ok(Timing::Unsupported) => ("unsupported", String::new(), String::new()),
+          Ok(Timing::Skipped) => ("skipped", String::new(), String::new()),
+          Err(err) => (err.label(), String::new(), String::new()),
+        };
+
+        let row = [
+          escape(revision),
+          escape(program),
+          mode.to_string(),
+          status.to_string(),
+          seconds,
+          wall,
+          runs.to_string(),
+        ];
+        writeln!(formatted, "{}", row.join(&separator.to_string()))?;
+      }
+    }
+  }
+
+  Ok(formatted)
+}
+
+/// Escapes a Prometheus label value.
+fn escape_label(value: &str) -> String {
+  value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Formats the results as Prometheus text exposition metrics, with repeated
+/// runs combined by `aggregate`. Timeouts and errors have no timing, but are
+/// reported by the status metric.
+pub fn format_prometheus(stats: &BTreeMap<String, Stats>, aggregate: Aggregate) -> Result<String> {
+  let mut timings = String::new();
+  let mut walls = String::new();
+  let mut statuses = String::new();
+
+  writeln!(
+    timings,
+    "# HELP hvm_bench_timing_seconds Time reported by hvm for a run."
+  )?;
+  writeln!(timings, "# TYPE hvm_bench_timing_seconds gauge")?;
+  writeln!(walls, "# HELP hvm_bench_wall_seconds Wall-clock time of a run.")?;
+  writeln!(walls, "# TYPE hvm_bench_wall_seconds gauge")?;
+  writeln!(statuses, "# HELP hvm_bench_status Outcome of a run, 1 for its status.")?;
+  writeln!(statuses, "# TYPE hvm_bench_status gauge")?;
+
+  for (revision, revision_stats) in stats {
+    for (program, program_stats) in &revision_stats.programs {
+      for mode in Mode::ALL {
+        let Some(timing) = program_stats.timing(mode, aggregate) else {
+          continue;
+        };
+
+        let labels = format!(
+          "program=\"{}\",mode=\"{mode}\",revision=\"{}\"",
+          escape_label(program),
+          escape_label(revision)
+        );
+
+        let status = match timing {
+          Ok(Timing::Completed { reported, wall, .. }) => {
+            writeln!(timings, "hvm_bench_timing_seconds{{{labels}}} {}", reported.seconds)?;
+            writeln!(walls, "hvm_bench_wall_seconds{{{labels}}} {}", wall.as_secs_f64())?;
+            "ok"
+          }
+          Ok(Timing::Timeout) => "timeout",
+          Ok(Timing::CompileTimeout) => "compile-timeout",
+          Ok(Timing::Unsupported) => "unsupported",
+          Ok(Timing::Skipped) => "skipped",
+          Err(err) => err.label(),
+        };
+
+        writeln!(statuses, "hvm_bench_status{{{labels},status=\"{}\"}} 1", status)?;
+      }
+    }
+  }
+
+  Ok(timings + &walls + &statuses)
+}
+
+/// Formats a single score per revision and mode: the geometric mean over all
+/// programs of `reference time / revision time`. A score above 1 means the
+/// revision is faster than `reference`. Programs that didn't succeed on both
+/// revisions are left out.
+pub fn format_score(stats: &BTreeMap<String, Stats>, reference: &str, options: &Options) -> Result<String> {
+  let reference_stats = stats
+    .get(reference)
+    .with_context(|| format!("reference revision {reference:?} was not benchmarked"))?;
+
+  let mut table = String::new();
+
+  let title = format!("score (relative to {reference})");
+  writeln!(table, "{title}")?;
+  writeln!(table, "{}", "=".repeat(title.len()))?;
   writeln!(table)?;
 
-  writeln!(table, "{}", format_header(stats.keys().rev().map(String::as_str)))?;
-  writeln!(table, "{}", format_interpreted_rows(stats)?)?;
+  let header = vec!["mode".to_string()]
+    .into_iter()
+    .chain(displayed_revisions(stats, options).map(|revision| options.label(revision)))
+    .map(|col| format!("{col:<COLUMN_WIDTH$}"))
+    .collect::<Vec<_>>()
+    .join(COLUMN_PADDING);
+
+  writeln!(table, "{header}\n{}", "=".repeat(header.len()))?;
+
+  for mode in displayed_modes(stats, options) {
+    let mut row = vec![format!("{:<COLUMN_WIDTH$}", mode.name())];
+
+    for revision in displayed_revisions(stats, options) {
+      let (speedups, ..) = speedups(reference_stats, &stats[revision], mode, options);
+
+      let score = match stats::geomean(speedups) {
+        Some(score) => format!("{score:.3}"),
+        None => "n/a".to_string(),
+      };
+
+      row.push(format!("{score:>COLUMN_WIDTH$}"));
+    }
+
+    writeln!(table, "{}", row.join(COLUMN_PADDING))?;
+  }
 
   Ok(table)
 }
+
+/// Formats a line per revision with the geometric mean, for each mode, of the
+/// speedups of its programs relative to `reference`, i.e. of `reference time /
+/// revision time`, and how many programs were left out for timing out or
+/// otherwise not completing on either revision. Empty if there's only one
+/// revision to compare.
+pub fn format_summary(stats: &BTreeMap<String, Stats>, reference: &str, options: &Options) -> Result<String> {
+  let reference_stats = stats
+    .get(reference)
+    .with_context(|| format!("reference revision {reference:?} was not benchmarked"))?;
+
+  let revisions = displayed_revisions(stats, options).collect::<Vec<_>>();
+  if revisions.len() < 2 {
+    return Ok(String::new());
+  }
+
+  let mut summary = String::new();
+  writeln!(summary, "geometric mean speedup over {}", options.label(reference))?;
+
+  let modes = displayed_modes(stats, options);
+  for revision in revisions {
+    let mut line = format!("{:<COLUMN_WIDTH$}", options.label(revision));
+    let (mut timed_out, mut skipped) = (0, 0);
+
+    for &mode in &modes {
+      let (speedups, mode_timed_out, mode_skipped) = speedups(reference_stats, &stats[revision], mode, options);
+      timed_out += mode_timed_out;
+      skipped += mode_skipped;
+
+      match stats::geomean(speedups) {
+        Some(speedup) => write!(line, "{COLUMN_PADDING}{mode} {speedup:.3}x")?,
+        None => write!(line, "{COLUMN_PADDING}{mode} n/a")?,
+      }
+    }
+
+    let left_out = [(timed_out, "timed out"), (skipped, "skipped")]
+      .into_iter()
+      .filter(|&(count, _)| count > 0)
+      .map(|(count, reason)| format!("{count} {reason}"))
+      .collect::<Vec<_>>();
+    if !left_out.is_empty() {
+      write!(line, "{COLUMN_PADDING}({})", left_out.join(", "))?;
+    }
+
+    writeln!(summary, "{line}")?;
+  }
+
+  Ok(summary)
+}
+
+/// Formats a verdict on whether `revision` is faster than `reference`: for
+/// each mode, on how many programs it's faster, slower, or within
+/// [`TIE_TOLERANCE`] of it, and the geometric mean speedup as in
+/// [`format_summary`]. A program that timed out on one revision only counts
+/// against it, and one that didn't complete otherwise isn't counted. Empty if
+/// `revision` is `reference`.
+pub fn format_verdict(
+  stats: &BTreeMap<String, Stats>,
+  revision: &str,
+  reference: &str,
+  options: &Options,
+) -> Result<String> {
+  if revision == reference {
+    return Ok(String::new());
+  }
+
+  let reference_stats = stats
+    .get(reference)
+    .with_context(|| format!("reference revision {reference:?} was not benchmarked"))?;
+  let revision_stats = stats
+    .get(revision)
+    .with_context(|| format!("revision {revision:?} was not benchmarked"))?;
+
+  let mut verdict = String::new();
+  let title = format!("{} vs {}", options.label(revision), options.label(reference));
+  writeln!(verdict, "{title}")?;
+  writeln!(verdict, "{}", "=".repeat(title.len()))?;
+  writeln!(verdict)?;
+
+  for mode in displayed_modes(stats, options) {
+    let (mut wins, mut losses, mut ties) = (0, 0, 0);
+    for (program, program_stats) in &revision_stats.programs {
+      let reference_seconds = reference_stats
+        .programs
+        .get(program)
+        .and_then(|p| rank_seconds(p.mode(mode), options));
+      let Some((reference_seconds, seconds)) = reference_seconds.zip(rank_seconds(program_stats.mode(mode), options))
+      else {
+        continue;
+      };
+
+      let tied = reference_seconds == seconds
+        || (seconds - reference_seconds).abs() <= reference_seconds.min(seconds) * TIE_TOLERANCE;
+      match () {
+        _ if tied => ties += 1,
+        _ if seconds < reference_seconds => wins += 1,
+        _ => losses += 1,
+      }
+    }
+
+    let (speedups, ..) = speedups(reference_stats, revision_stats, mode, options);
+    let speedup = match stats::geomean(speedups) {
+      Some(speedup) if speedup >= 1.0 => format!("{speedup:.3}x faster"),
+      Some(speedup) => format!("{:.3}x slower", 1.0 / speedup),
+      None => "n/a".to_string(),
+    };
+    let counts = format!("{wins} faster, {losses} slower, {ties} tied");
+    writeln!(
+      verdict,
+      "{:<COLUMN_WIDTH$}{COLUMN_PADDING}{counts:<width$}{COLUMN_PADDING}{speedup}",
+      mode.name(),
+      width = 2 * COLUMN_WIDTH
+    )?;
+  }
+
+  Ok(verdict)
+}
+
+/// Returns `reference time / revision time` in `mode` for every program of
+/// `revision` that completed on both revisions, how many timed out on either,
+/// and how many didn't complete otherwise.
+fn speedups(reference: &Stats, revision: &Stats, mode: Mode, options: &Options) -> (Vec<f64>, usize, usize) {
+  let mut speedups = Vec::new();
+  let (mut timed_out, mut skipped) = (0, 0);
+  for (program, program_stats) in &revision.programs {
+    let Some(timings) = program_stats.mode(mode) else {
+      continue;
+    };
+
+    let reference_seconds = reference
+      .programs
+      .get(program)
+      .and_then(|p| rank_seconds(p.mode(mode), options));
+    match (reference_seconds, rank_seconds(Some(timings), options)) {
+      (Some(reference_seconds), Some(seconds)) if reference_seconds.is_infinite() || seconds.is_infinite() => {
+        timed_out += 1
+      }
+      (Some(reference_seconds), Some(seconds)) if reference_seconds > 0.0 && seconds > 0.0 => {
+        speedups.push(reference_seconds / seconds);
+      }
+      _ => skipped += 1,
+    }
+  }
+
+  (speedups, timed_out, skipped)
+}
+
+/// Formats a line per program with its name, size and first-line comment.
+pub fn format_list(programs: &[programs::Info]) -> Result<String> {
+  let mut list = String::new();
+
+  for program in programs {
+    let size = format!("{} B", program.size);
+    let line = format!(
+      "{:<COLUMN_WIDTH$}{COLUMN_PADDING}{size:>COLUMN_WIDTH$}{COLUMN_PADDING}{}",
+      program.name,
+      program.comment.as_deref().unwrap_or_default()
+    );
+    writeln!(list, "{}", line.trim_end())?;
+  }
+
+  Ok(list)
+}
+
+pub fn format_tags(tags: &[(String, String)]) -> Result<String> {
+  let mut section = String::new();
+
+  writeln!(section, "tags")?;
+  writeln!(section, "====")?;
+  writeln!(section)?;
+
+  for (key, value) in tags {
+    writeln!(section, "{key:<COLUMN_WIDTH$}{COLUMN_PADDING}{value}")?;
+  }
+
+  Ok(section)
+}
+
+pub fn format_environment(environment: &Environment) -> Result<String> {
+  let mut section = String::new();
+
+  writeln!(section, "environment")?;
+  writeln!(section, "===========")?;
+  writeln!(section)?;
+
+  for (name, value) in environment.fields() {
+    writeln!(section, "{name:<COLUMN_WIDTH$}{COLUMN_PADDING}{value}")?;
+  }
+
+  Ok(section)
+}
+
+pub fn format_warnings(warnings: &[Warning]) -> Result<String> {
+  let mut section = String::new();
+
+  writeln!(section, "warnings")?;
+  writeln!(section, "========")?;
+  writeln!(section)?;
+
+  for warning in warnings {
+    writeln!(section, "- {warning}")?;
+  }
+
+  Ok(section)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{run::RunError, stats::Reported};
+
+  fn completed(raw: &str) -> Option<Runs> {
+    let reported = Reported::parse(raw).unwrap();
+    let wall = Duration::from_secs_f64(reported.seconds);
+
+    Some(Ok(vec![Timing::Completed {
+      reported,
+      wall,
+      peak_memory: None,
+      compile_time: None,
+      counters: None,
+      throttled: false,
+    }]))
+  }
+
+  #[test]
+  fn deltas_are_relative_to_the_baseline() {
+    let options = Options::default();
+    let delta = |baseline: &Option<Runs>, timings: &Option<Runs>| {
+      format_delta(baseline.as_ref(), timings.as_ref(), 5.0, &options)
+    };
+    let timeout = Some(Ok(vec![Timing::Timeout]));
+
+    assert_eq!(delta(&completed("2s"), &completed("2.08s")), "+4.0%");
+    assert_eq!(delta(&completed("2s"), &completed("2.5s")), "+25.0% !");
+    assert_eq!(delta(&completed("2s"), &completed("1s")), "-50.0%");
+    assert_eq!(delta(&completed("2s"), &timeout), "timeout !");
+    assert_eq!(delta(&timeout, &completed("2s")), "no timeout");
+    assert_eq!(delta(&timeout, &timeout), "timeout");
+    assert_eq!(delta(&None, &completed("2s")), "2s");
+  }
+
+  #[test]
+  fn summary_is_the_geometric_mean_speedup() {
+    let revision = |times: [&str; 3]| {
+      let mut stats = Stats::default();
+      for (program, time) in ["a", "b", "c"].into_iter().zip(times) {
+        let mut program_stats = Program::default();
+        *program_stats.mode_mut(Mode::InterpretedC) = match time {
+          "timeout" => Some(Ok(vec![Timing::Timeout])),
+          time => completed(time),
+        };
+        stats.programs.insert(program.to_string(), program_stats);
+      }
+      stats
+    };
+    let stats = BTreeMap::from([
+      ("main".to_string(), revision(["2s", "8s", "1s"])),
+      ("new".to_string(), revision(["1s", "2s", "timeout"])),
+    ]);
+
+    let summary = format_summary(&stats, "main", &Options::default()).unwrap();
+    let line = summary.lines().find(|line| line.starts_with("new")).unwrap();
+    assert!(line.contains("interpreted-c 2.828x"), "{summary}");
+    assert!(line.ends_with("(1 timed out)"), "{summary}");
+  }
+
+  #[test]
+  fn score_is_the_geometric_mean_speedup() {
+    let revision = |times: [&str; 3]| {
+      let mut stats = Stats::default();
+      for (program, time) in ["a", "b", "c"].into_iter().zip(times) {
+        let mut program_stats = Program::default();
+        *program_stats.mode_mut(Mode::InterpretedC) = completed(time);
+        stats.programs.insert(program.to_string(), program_stats);
+      }
+      stats
+    };
+    let stats = BTreeMap::from([
+      ("main".to_string(), revision(["2s", "8s", "1s"])),
+      ("new".to_string(), revision(["1s", "2s", "0.5s"])),
+    ]);
+
+    // the speedups are 2, 4 and 2, whose geometric mean is the cube root of 16
+    let score = format_score(&stats, "main", &Options::default()).unwrap();
+    let row = |start: &str| score.lines().find(|line| line.starts_with(start)).unwrap();
+    let scores = (row("mode").split_whitespace())
+      .zip(row(Mode::InterpretedC.name()).split_whitespace())
+      .skip(1)
+      .collect::<BTreeMap<_, _>>();
+    assert_eq!(scores, BTreeMap::from([("main", "1.000"), ("new", "2.520")]), "{score}");
+
+    assert!(format_score(&stats, "old", &Options::default()).is_err());
+  }
+
+  #[test]
+  fn programs_are_grouped_by_category() {
+    let mut stats = Stats::default();
+    for program in ["fib", "bitonic", "radix", "sqrt"] {
+      let mut program_stats = Program::default();
+      *program_stats.mode_mut(Mode::InterpretedC) = completed("1s");
+      stats.programs.insert(program.to_string(), program_stats);
+    }
+    stats.categories = BTreeMap::from([
+      ("bitonic".to_string(), "sorting".to_string()),
+      ("radix".to_string(), "sorting".to_string()),
+      ("sqrt".to_string(), "numeric".to_string()),
+    ]);
+    let stats = BTreeMap::from([("main".to_string(), stats)]);
+
+    let table = format(&stats, &Options::default()).unwrap();
+    // the first column of every row below the header
+    let lines = (table.lines())
+      .skip_while(|line| !line.starts_with("file"))
+      .skip(2)
+      .filter(|line| !line.starts_with('-'))
+      .filter_map(|line| line.split_whitespace().next())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      lines,
+      ["fib", "numeric/", "sqrt", "sorting/", "bitonic", "radix"],
+      "{table}"
+    );
+  }
+
+  #[test]
+  fn labels_show_commits() {
+    let options = Options {
+      commits: BTreeMap::from([
+        ("main".to_string(), "a1b2c3d4e5f6".to_string()),
+        ("a1b2c3d".to_string(), "a1b2c3d4e5f6".to_string()),
+        ("local".to_string(), format!("f6e5d4c3b2a1{DIRTY_SUFFIX}")),
+      ]),
+      ..Options::default()
+    };
+
+    assert_eq!(options.label("main"), "main (a1b2c3d)");
+    assert_eq!(options.label("a1b2c3d"), "a1b2c3d");
+    assert_eq!(options.label("local"), "local (f6e5d4c-dirty)");
+    assert_eq!(options.label("v1"), "v1");
+  }
+
+  #[test]
+  fn pretty_tables_are_boxed() {
+    let mut stats = Stats::default();
+    for (program, time) in [("fib", "1s"), ("bitonic", "12.5s")] {
+      let mut program_stats = Program::default();
+      *program_stats.mode_mut(Mode::InterpretedC) = completed(time);
+      stats.programs.insert(program.to_string(), program_stats);
+    }
+    stats.categories = BTreeMap::from([("bitonic".to_string(), "sorting".to_string())]);
+    let stats = BTreeMap::from([("main".to_string(), stats)]);
+
+    let table = format(
+      &stats,
+      &Options {
+        pretty: true,
+        ..Options::default()
+      },
+    )
+    .unwrap();
+    let lines = (table.lines())
+      .skip_while(|line| !line.starts_with('┌'))
+      .take_while(|line| !line.is_empty())
+      .collect::<Vec<_>>();
+    assert!(lines.last().unwrap().starts_with('└'), "{table}");
+    // every line is as wide as the box
+    let width = lines[0].chars().count();
+    assert!(lines.iter().all(|line| line.chars().count() == width), "{table}");
+    // the timings are aligned to the right
+    let cells = (lines.iter())
+      .filter(|line| line.starts_with('│'))
+      .map(|line| line.trim_end_matches('│').rsplit('│').next().unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(cells[0].trim(), "main", "{table}");
+    assert!(
+      cells[1..]
+        .iter()
+        .all(|cell| cell.trim().is_empty() || cell.ends_with("s ")),
+      "{table}"
+    );
+    assert!(lines.iter().any(|line| line.starts_with("│ sorting/ ")), "{table}");
+  }
+
+  #[test]
+  fn verdict_counts_wins_losses_and_ties() {
+    let revision = |times: [&str; 4]| {
+      let mut stats = Stats::default();
+      for (program, time) in ["a", "b", "c", "d"].into_iter().zip(times) {
+        let mut program_stats = Program::default();
+        *program_stats.mode_mut(Mode::InterpretedC) = match time {
+          "timeout" => Some(Ok(vec![Timing::Timeout])),
+          time => completed(time),
+        };
+        stats.programs.insert(program.to_string(), program_stats);
+      }
+      stats
+    };
+    let stats = BTreeMap::from([
+      ("main".to_string(), revision(["2s", "8s", "1s", "timeout"])),
+      ("local".to_string(), revision(["1s", "2s", "1.01s", "3s"])),
+    ]);
+
+    let verdict = format_verdict(&stats, "local", "main", &Options::default()).unwrap();
+    assert!(verdict.starts_with("local vs main\n"), "{verdict}");
+    let line = verdict.lines().find(|line| line.starts_with("interpreted-c")).unwrap();
+    assert!(line.contains("3 faster, 0 slower, 1 tied"), "{verdict}");
+    assert!(line.ends_with("1.993x faster"), "{verdict}");
+
+    assert_eq!(format_verdict(&stats, "main", "main", &Options::default()).unwrap(), "");
+  }
+
+  #[test]
+  fn revisions_are_ordered_by_commit_date() {
+    let committed_at = |time| Stats {
+      committed_at: time,
+      ..Stats::default()
+    };
+    let stats = BTreeMap::from([
+      ("local".to_string(), committed_at(None)),
+      ("a-new".to_string(), committed_at(Some(2))),
+      ("b-old".to_string(), committed_at(Some(1))),
+    ]);
+    let options = Options {
+      order: vec!["local".to_string()],
+      ..Options::default()
+    };
+
+    assert_eq!(
+      displayed_revisions(&stats, &options).collect::<Vec<_>>(),
+      ["b-old", "a-new", "local"]
+    );
+  }
+
+  #[test]
+  fn markdown_has_a_table_per_kind_of_mode() {
+    let mut program = Program::default();
+    *program.mode_mut(Mode::CompiledC) = completed("1.5s");
+    *program.mode_mut(Mode::InterpretedC) = Some(Ok(vec![Timing::Timeout]));
+
+    let mut stats = Stats::default();
+    stats.programs.insert("sum_rec".to_string(), program);
+    let stats = BTreeMap::from([("main".to_string(), stats)]);
+
+    let markdown = format_markdown(&stats, &Options::default()).unwrap();
+    let lines = markdown.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "### compiled");
+    assert_eq!(lines[2], "| file | runtime | main |");
+    assert_eq!(lines[3], "| --- | --- | ---: |");
+    assert!(lines[4].starts_with("| sum_rec | c | "));
+    assert!(lines.contains(&"### interpreted"));
+  }
+
+  #[test]
+  fn html_cells_sort_by_seconds() {
+    let stats = [("fast", "1.0s"), ("<slow>", "3.0s")].map(|(revision, time)| {
+      let mut program = Program::default();
+      *program.mode_mut(Mode::CompiledC) = completed(time);
+      *program.mode_mut(Mode::InterpretedC) = Some(Ok(vec![Timing::Timeout]));
+
+      let mut stats = Stats::default();
+      stats.programs.insert("sum_rec".to_string(), program);
+      (revision.to_string(), stats)
+    });
+
+    let html = format_html(&BTreeMap::from(stats), &Options::default()).unwrap();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<th>&lt;slow&gt;</th>"));
+    assert!(html.contains("<td data-sort=\"1\" style=\"background: rgba(0, 255, 0, 0.3)\">"));
+    assert!(html.contains("<td data-sort=\"3\" style=\"background: rgba(255, 0, 0, 0.3)\">"));
+    // timeouts sort last, but aren't ranked among only timeouts
+    assert!(html.contains("<td data-sort=\"Infinity\">"));
+    assert_eq!(html.matches("<table").count(), 2);
+  }
+
+  #[test]
+  fn fastest_cells_are_green_and_slowest_red() {
+    let stats = ["1.0s", "2.0s", "3.0s"].map(|time| {
+      let mut program = Program::default();
+      *program.mode_mut(Mode::InterpretedC) = completed(time);
+
+      let mut stats = Stats::default();
+      stats.programs.insert("sum_rec".to_string(), program);
+      (time.to_string(), stats)
+    });
+    let options = Options {
+      color: true,
+      ..Options::default()
+    };
+
+    let table = format(&BTreeMap::from(stats), &options).unwrap();
+    let row = table.lines().find(|line| line.starts_with("sum_rec")).unwrap();
+    let separator = table.lines().find(|line| line.starts_with('-')).unwrap();
+    assert!(row.contains("\x1b[38;2;0;255;0m"));
+    assert!(row.contains("\x1b[38;2;255;255;0m"));
+    assert!(row.contains("\x1b[38;2;255;0;0m"));
+    assert_eq!(separator.len(), visible_len(row));
+  }
+
+  #[test]
+  fn timeouts_rank_as_the_slowest() {
+    let stats = [("a", completed("1.0s")), ("b", Some(Ok(vec![Timing::Timeout])))].map(|(revision, timings)| {
+      let mut program = Program::default();
+      *program.mode_mut(Mode::InterpretedC) = timings;
+      let mut stats = Stats::default();
+      stats.programs.insert("sum_rec".to_string(), program);
+      (revision.to_string(), stats)
+    });
+    let options = Options {
+      color: true,
+      ..Options::default()
+    };
+
+    let table = format(&BTreeMap::from(stats), &options).unwrap();
+    let row = table.lines().find(|line| line.starts_with("sum_rec")).unwrap();
+    assert!(row.contains("\x1b[38;2;0;255;0m1.0"), "{row}");
+    assert!(row.contains("\x1b[38;2;255;0;0mtimeout"), "{row}");
+  }
+
+  #[test]
+  fn csv_fields_are_quoted() {
+    let mut program = Program::default();
+    *program.mode_mut(Mode::InterpretedC) = completed("1.5s");
+    let mut stats = Stats::default();
+    stats.programs.insert("a,\"b\"".to_string(), program);
+
+    let csv = format_csv(&BTreeMap::from([("main".to_string(), stats)]), Aggregate::default()).unwrap();
+    let lines = csv.lines().collect::<Vec<_>>();
+
+    assert_eq!(lines[0], "revision,program,mode,status,seconds,wall_seconds,runs");
+    assert_eq!(lines[1], "main,\"a,\"\"b\"\"\",interpreted-c,ok,1.5,1.5,1");
+  }
+
+  #[test]
+  fn prometheus_metrics_are_labeled() {
+    let mut program = Program::default();
+    *program.mode_mut(Mode::InterpretedC) = completed("1.5s");
+    *program.mode_mut(Mode::CompiledC) = Some(Ok(vec![Timing::Timeout]));
+    let mut stats = Stats::default();
+    stats.programs.insert("sum\"rec".to_string(), program);
+
+    let metrics = format_prometheus(&BTreeMap::from([("main".to_string(), stats)]), Aggregate::default()).unwrap();
+    let labels = |mode: &str| format!(r#"program="sum\"rec",mode="{mode}",revision="main""#);
+
+    for line in [
+      "# TYPE hvm_bench_timing_seconds gauge".to_string(),
+      "# TYPE hvm_bench_wall_seconds gauge".to_string(),
+      "# TYPE hvm_bench_status gauge".to_string(),
+      format!("hvm_bench_timing_seconds{{{}}} 1.5", labels("interpreted-c")),
+      format!("hvm_bench_wall_seconds{{{}}} 1.5", labels("interpreted-c")),
+      format!(r#"hvm_bench_status{{{},status="ok"}} 1"#, labels("interpreted-c")),
+      format!(r#"hvm_bench_status{{{},status="timeout"}} 1"#, labels("compiled-c")),
+    ] {
+      assert!(metrics.lines().any(|metric| metric == line), "{line}\n{metrics}");
+    }
+    assert!(
+      !metrics.contains(&format!("hvm_bench_timing_seconds{{{}}}", labels("compiled-c"))),
+      "{metrics}"
+    );
+  }
+
+  #[test]
+  fn tsv_fields_are_tab_separated() {
+    let mut program = Program::default();
+    *program.mode_mut(Mode::InterpretedC) = completed("1.5s");
+    let mut stats = Stats::default();
+    stats.programs.insert("sum\trec".to_string(), program);
+
+    let tsv = format_tsv(&BTreeMap::from([("main".to_string(), stats)]), Aggregate::default()).unwrap();
+    let lines = tsv.lines().collect::<Vec<_>>();
+
+    assert_eq!(lines[0], "revision\tprogram\tmode\tstatus\tseconds\twall_seconds\truns");
+    assert!(lines[1].starts_with("main\tsum rec\tinterpreted-c\tok\t1.5\t"), "{tsv}");
+  }
+
+  #[test]
+  fn only_changed_cells_are_compared() {
+    let stats = [("main", ["1.0s", "1.0s"]), ("new", ["2.0s", "1.01s"])].map(|(revision, times)| {
+      let mut stats = Stats::default();
+      for (program, time) in ["fib", "sum"].into_iter().zip(times) {
+        let mut program_stats = Program::default();
+        *program_stats.mode_mut(Mode::InterpretedC) = completed(time);
+        stats.programs.insert(program.to_string(), program_stats);
+      }
+      (revision.to_string(), stats)
+    });
+    let stats = BTreeMap::from(stats);
+
+    let regressed = format_compare(&stats, "main", 5.0, Some(Change::Regressed), &Options::default()).unwrap();
+    assert!(regressed.contains("fib"), "{regressed}");
+    assert!(regressed.contains("+100.0%"), "{regressed}");
+    assert!(!regressed.contains("sum"), "{regressed}");
+
+    let improved = format_compare(&stats, "main", 5.0, Some(Change::Improved), &Options::default()).unwrap();
+    assert!(!improved.contains("fib") && !improved.contains("sum"), "{improved}");
+  }
+
+  #[test]
+  fn exit_summary_counts_each_revision() {
+    let mut program = Program::default();
+    *program.mode_mut(Mode::InterpretedC) = completed("1.0s");
+    *program.mode_mut(Mode::CompiledC) = Some(Ok(vec![Timing::Timeout]));
+    let mut stats = Stats::default();
+    stats.programs.insert("fib".to_string(), program);
+    let mut failed = Stats::default();
+    *failed
+      .programs
+      .entry("fib".to_string())
+      .or_default()
+      .mode_mut(Mode::InterpretedC) = Some(Err(RunError::Build(anyhow::anyhow!("cargo build failed"))));
+    let stats = BTreeMap::from([("main".to_string(), stats), ("broken".to_string(), failed)]);
+
+    let summary = format_exit_summary(&stats, Aggregate::default(), Duration::from_secs(3)).unwrap();
+    let summary: serde_json::Value = serde_json::from_str(&summary).unwrap();
+
+    assert_eq!(summary["programs"], 1);
+    assert_eq!(summary["wall_seconds"], 3.0);
+    assert_eq!(summary["revisions"]["main"]["successes"], 1);
+    assert_eq!(summary["revisions"]["main"]["timeouts"], 1);
+    assert_eq!(summary["revisions"]["broken"]["build_failures"], 1);
+  }
+
+  #[test]
+  fn hidden_revisions_and_their_modes_are_not_displayed() {
+    let stats = [("main", Mode::InterpretedC), ("old", Mode::CompiledC)].map(|(revision, mode)| {
+      let mut program = Program::default();
+      *program.mode_mut(mode) = completed("1s");
+      let mut stats = Stats::default();
+      stats.programs.insert("sum_rec".to_string(), program);
+      (revision.to_string(), stats)
+    });
+    let stats = BTreeMap::from(stats);
+    let options = Options {
+      display_revs: vec!["main".to_string()],
+      ..Options::default()
+    };
+
+    assert_eq!(displayed_revisions(&stats, &options).collect::<Vec<_>>(), ["main"]);
+    assert_eq!(displayed_modes(&stats, &options), [Mode::InterpretedC]);
+    let table = format(&stats, &options).unwrap();
+    assert!(!table.contains("old"), "{table}");
+
+    assert_eq!(displayed_modes(&stats, &Options::default()).len(), 2);
+  }
+
+  #[test]
+  fn columns_fit_their_widest_cell() {
+    let stats = [("main", "1s"), ("a-very-long-branch-name", "12.345s")].map(|(revision, time)| {
+      let mut program = Program::default();
+      *program.mode_mut(Mode::InterpretedC) = completed(time);
+      let mut stats = Stats::default();
+      stats.programs.insert("sum_rec".to_string(), program);
+      (revision.to_string(), stats)
+    });
+
+    let table = format(&BTreeMap::from(stats), &Options::default()).unwrap();
+    let lines = table.lines().skip(3).take(4).collect::<Vec<_>>();
+    assert!(lines[0].contains("a-very-long-branch-name"), "{table}");
+    assert!(lines.iter().all(|line| line.len() == lines[0].len()), "{table}");
+    assert!(lines[2].starts_with("sum_rec  c"), "{table}");
+  }
+
+  #[test]
+  fn ratios_are_relative_to_the_base_mode() {
+    let mut program = Program::default();
+    *program.mode_mut(Mode::InterpretedRust) = completed("3.4s");
+    *program.mode_mut(Mode::CompiledC) = completed("1s");
+    let mut stats = Stats::default();
+    stats.programs.insert("sum_rec".to_string(), program);
+    let options = Options {
+      ratio_base: Some(Mode::InterpretedRust),
+      ..Options::default()
+    };
+
+    let table = format(&BTreeMap::from([("main".to_string(), stats)]), &options).unwrap();
+    assert!(table.contains("1s (3.4x)"), "{table}");
+    assert!(!table.contains("3.4s ("), "{table}");
+  }
+
+  #[test]
+  fn timings_are_rounded_to_the_noise_floor() {
+    let options = Options {
+      noise_floor: Some(Duration::from_millis(50)),
+      ..Options::default()
+    };
+    let timings = completed("1.234s");
+
+    assert_eq!(format_timing(timings.as_ref(), &options), "1.25s");
+    let seconds = rank_seconds(timings.as_ref(), &options).unwrap();
+    assert!((seconds - 1.25).abs() < 1e-9, "{seconds}");
+
+    let options = Options {
+      noise_floor: Some(Duration::from_secs(1)),
+      ..options
+    };
+    assert_eq!(format_timing(timings.as_ref(), &options), "1s");
+  }
+
+  #[test]
+  fn variance_is_shown_for_several_runs() {
+    let options = Options {
+      show_variance: true,
+      ..Options::default()
+    };
+    let runs = |raws: &[&str]| -> Option<Runs> {
+      let timings = raws.iter().map(|raw| completed(raw).unwrap().unwrap().remove(0));
+      Some(Ok(timings.collect()))
+    };
+
+    assert_eq!(format_timing(runs(&["1.9s"]).as_ref(), &options), "1.9s");
+    assert_eq!(
+      format_timing(runs(&["1.9s", "2.1s"]).as_ref(), &options),
+      "2.000s ±7.1%"
+    );
+    assert_eq!(
+      format_timing(runs(&["2s", "2s", "2s"]).as_ref(), &options),
+      "2.000s ±0.0%"
+    );
+    assert_eq!(
+      format_timing(runs(&["1.9s", "2.1s"]).as_ref(), &Options::default()),
+      "2.000s"
+    );
+  }
+
+  #[test]
+  fn throttled_runs_are_marked() {
+    let mut stats = Stats::default();
+    for (program, throttled) in [("fib", false), ("sum_rec", true)] {
+      let timing = completed("1.0s").unwrap().unwrap().remove(0);
+      let mut program_stats = Program::default();
+      *program_stats.mode_mut(Mode::InterpretedCuda) = Some(Ok(vec![timing.clone(), timing.with_throttled(throttled)]));
+      stats.programs.insert(program.to_string(), program_stats);
+    }
+
+    let table = format(&BTreeMap::from([("main".to_string(), stats)]), &Options::default()).unwrap();
+    let row = |program: &str| table.lines().find(|line| line.starts_with(program)).unwrap();
+    assert!(row("sum_rec").ends_with(&format!("1.000s {THROTTLED_MARK}")), "{table}");
+    assert!(!row("fib").contains(THROTTLED_MARK), "{table}");
+    assert!(table.contains(&format!("{THROTTLED_MARK} throttled")), "{table}");
+  }
+
+  #[test]
+  fn mismatched_results_are_marked() {
+    let stats = [("old", 1, "Result: 1"), ("new", 2, "Result: 2")].map(|(rev, committed_at, output)| {
+      let mut reported = Reported::parse("1.0s").unwrap();
+      reported.output = Some(output.to_string());
+
+      let mut program = Program::default();
+      *program.mode_mut(Mode::InterpretedC) = Some(Ok(vec![Timing::Completed {
+        reported,
+        wall: Duration::from_secs(1),
+        peak_memory: None,
+        compile_time: None,
+        counters: None,
+        throttled: false,
+      }]));
+
+      let mut stats = Stats {
+        committed_at: Some(committed_at),
+        ..Stats::default()
+      };
+      stats.programs.insert("sum_rec".to_string(), program);
+      (rev.to_string(), stats)
+    });
+
+    let table = format(&BTreeMap::from(stats), &Options::default()).unwrap();
+    let row = table.lines().find(|line| line.starts_with("sum_rec")).unwrap();
+    assert!(row.ends_with(&format!("1.0s {MISMATCH_MARK}")), "{row}");
+    assert_eq!(row.matches(MISMATCH_MARK).count(), 1);
+    assert!(table.contains(&format!("{MISMATCH_MARK} mismatch")));
+  }
+
+  #[test]
+  fn jsonl_lines_have_every_key() {
+    let keys = |runs: &Runs| {
+      let line = format_jsonl("main", "sum_rec", Mode::CompiledC, runs, &[]).unwrap();
+      assert!(!line.contains('\n'));
+      let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+      value.as_object().unwrap().keys().cloned().collect::<Vec<_>>()
+    };
+
+    let failed = Err(RunError::Crash(anyhow::anyhow!("segfault")));
+    assert_eq!(keys(&completed("1s").unwrap()), keys(&failed));
+    assert_eq!(
+      keys(&failed),
+      ["error", "failure", "mode", "program", "revision", "runs", "tags"]
+    );
+  }
+
+  #[test]
+  fn jsonl_lines_have_the_tags() {
+    let tags = [
+      ("machine".to_string(), "ci".to_string()),
+      ("gpu".to_string(), "a100".to_string()),
+    ];
+    let line = format_jsonl("main", "sum_rec", Mode::CompiledC, &completed("1s").unwrap(), &tags).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+
+    assert_eq!(value["tags"], serde_json::json!({ "machine": "ci", "gpu": "a100" }));
+  }
+
+  #[test]
+  fn json_round_trips() {
+    let mut program = Program::default();
+    *program.mode_mut(Mode::CompiledC) = completed("1.5s");
+    *program.mode_mut(Mode::InterpretedC) = Some(Ok(vec![Timing::Timeout, Timing::Skipped]));
+    *program.mode_mut(Mode::CompiledCuda) = Some(Err(RunError::Compile(anyhow::anyhow!("no nvcc"))));
+
+    let mut stats = Stats {
+      committed_at: Some(1_700_000_000),
+      ..Stats::default()
+    };
+    stats.programs.insert("sum_rec".to_string(), program);
+    let stats = BTreeMap::from([("main".to_string(), stats)]);
+
+    let json = format_json(&stats, &BTreeMap::new(), &[], None, &[]).unwrap();
+    let parsed = parse_json(&json).unwrap();
+
+    assert_eq!(format_json(&parsed, &BTreeMap::new(), &[], None, &[]).unwrap(), json);
+    assert_eq!(parsed["main"].committed_at, Some(1_700_000_000));
+    assert!(parsed["main"].programs["sum_rec"].mode(Mode::InterpretedRust).is_none());
+    assert!(matches!(
+      parsed["main"].programs["sum_rec"].mode(Mode::CompiledCuda),
+      Some(Err(RunError::Compile(_)))
+    ));
+  }
+
+  #[test]
+  fn programs_named_like_fields_round_trip() {
+    let mut stats = Stats {
+      version: Some("2.0.0".to_string()),
+      ..Stats::default()
+    };
+    let mut program = Program::default();
+    *program.mode_mut(Mode::InterpretedC) = completed("1s");
+    stats.programs.insert("version".to_string(), program);
+    let stats = BTreeMap::from([("main".to_string(), stats)]);
+
+    let json = format_json(&stats, &BTreeMap::new(), &[], None, &[]).unwrap();
+    let parsed = parse_json(&json).unwrap();
+
+    assert_eq!(parsed["main"].version.as_deref(), Some("2.0.0"));
+    assert!(parsed["main"].programs["version"].mode(Mode::InterpretedC).is_some());
+  }
+
+  #[test]
+  fn json_schema_version() {
+    let json = format_json(&BTreeMap::new(), &BTreeMap::new(), &[], None, &[]).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["schema_version"], JSON_SCHEMA_VERSION);
+
+    // output from before the version was added, with the programs next to
+    // the other fields of a revision
+    let flat = r#"{ "revisions": { "main": { "version": "2.0.0", "sum_rec": { "interpreted-c": { "runs": [] } } } } }"#;
+    let parsed = parse_json(flat).unwrap();
+    assert_eq!(parsed["main"].version.as_deref(), Some("2.0.0"));
+    assert!(parsed["main"].programs["sum_rec"].mode(Mode::InterpretedC).is_some());
+
+    let newer = format!(
+      r#"{{ "schema_version": {}, "revisions": {{}} }}"#,
+      JSON_SCHEMA_VERSION + 1
+    );
+    let Err(err) = parse_json(&newer) else {
+      panic!("parsed a newer schema version");
+    };
+    assert!(err.to_string().contains("newer"), "{err:#}");
+  }
+}
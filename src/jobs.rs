@@ -0,0 +1,218 @@
+use std::{
+  collections::VecDeque,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Condvar, Mutex,
+  },
+  thread,
+};
+
+use crate::stats::Mode;
+
+/// How many runs may happen at once, in total and per category.
+#[derive(Clone, Copy)]
+pub struct Jobs {
+  pub total: usize,
+  /// Compiled runs also spawn a compiler.
+  pub compiled: usize,
+  /// CUDA runs contend for the same GPU.
+  pub cuda: usize,
+}
+
+impl Default for Jobs {
+  fn default() -> Self {
+    Self {
+      total: 1,
+      compiled: 1,
+      cuda: 1,
+    }
+  }
+}
+
+/// Number of runs currently happening in each category.
+#[derive(Default)]
+struct Running {
+  total: usize,
+  compiled: usize,
+  cuda: usize,
+}
+
+impl Running {
+  fn admits(&self, mode: Mode, jobs: Jobs) -> bool {
+    self.total < jobs.total
+      && (!mode.is_compiled() || self.compiled < jobs.compiled)
+      && (!mode.is_cuda() || self.cuda < jobs.cuda)
+  }
+
+  /// Returns the counters a run in `mode` counts towards.
+  fn counters(&mut self, mode: Mode) -> impl Iterator<Item = &mut usize> {
+    [
+      Some(&mut self.total),
+      mode.is_compiled().then_some(&mut self.compiled),
+      mode.is_cuda().then_some(&mut self.cuda),
+    ]
+    .into_iter()
+    .flatten()
+  }
+
+  fn start(&mut self, mode: Mode) {
+    self.counters(mode).for_each(|counter| *counter += 1);
+  }
+
+  fn finish(&mut self, mode: Mode) {
+    self.counters(mode).for_each(|counter| *counter -= 1);
+  }
+}
+
+struct State<R> {
+  /// Indices of the items that haven't started, in order.
+  pending: VecDeque<usize>,
+  running: Running,
+  results: Vec<Option<R>>,
+}
+
+/// Calls `run` on every item on up to `jobs.total` threads, starting items in
+/// order as long as their mode's category has room. Returns the results in the
+/// order of `items`, regardless of the order they finished in.
+pub fn run_all<T, R, F>(items: &[(Mode, T)], jobs: Jobs, run: F) -> Vec<R>
+where
+  T: Sync,
+  R: Send,
+  F: Fn(Mode, &T) -> R + Sync,
+{
+  let state = Mutex::new(State {
+    pending: (0..items.len()).collect(),
+    running: Running::default(),
+    results: items.iter().map(|_| None).collect(),
+  });
+  let changed = Condvar::new();
+
+  thread::scope(|scope| {
+    for _ in 0..jobs.total.min(items.len()) {
+      scope.spawn(|| loop {
+        let index = {
+          let mut state = state.lock().unwrap();
+          loop {
+            if state.pending.is_empty() {
+              return;
+            }
+
+            let admitted = state
+              .pending
+              .iter()
+              .position(|&index| state.running.admits(items[index].0, jobs));
+            if let Some(position) = admitted {
+              let index = state.pending.remove(position).unwrap();
+              state.running.start(items[index].0);
+
+              break index;
+            }
+
+            state = changed.wait(state).unwrap();
+          }
+        };
+
+        let (mode, item) = &items[index];
+        let result = run(*mode, item);
+
+        let mut state = state.lock().unwrap();
+        state.running.finish(*mode);
+        state.results[index] = Some(result);
+        changed.notify_all();
+      });
+    }
+  });
+
+  let state = state.into_inner().unwrap();
+  state
+    .results
+    .into_iter()
+    .map(|result| result.expect("every item ran"))
+    .collect()
+}
+
+/// Calls `run` on every item on up to `jobs` threads. Returns the results in
+/// the order of `items`.
+pub fn map<T, R, F>(items: &[T], jobs: usize, run: F) -> Vec<R>
+where
+  T: Sync,
+  R: Send,
+  F: Fn(&T) -> R + Sync,
+{
+  let next = AtomicUsize::new(0);
+  let results = Mutex::new(items.iter().map(|_| None).collect::<Vec<_>>());
+
+  thread::scope(|scope| {
+    for _ in 0..jobs.min(items.len()) {
+      scope.spawn(|| loop {
+        let index = next.fetch_add(1, Ordering::Relaxed);
+        let Some(item) = items.get(index) else {
+          return;
+        };
+
+        let result = run(item);
+        results.lock().unwrap()[index] = Some(result);
+      });
+    }
+  });
+
+  results
+    .into_inner()
+    .unwrap()
+    .into_iter()
+    .map(|result| result.expect("every item ran"))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::*;
+
+  #[test]
+  fn results_are_in_order() {
+    let items = (0..20).map(|i| (Mode::InterpretedC, i)).collect::<Vec<_>>();
+    let jobs = Jobs {
+      total: 4,
+      ..Jobs::default()
+    };
+
+    let results = run_all(&items, jobs, |_, &i| {
+      thread::sleep(Duration::from_millis(20 - i));
+      i
+    });
+
+    assert_eq!(results, (0..20).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn cuda_runs_one_at_a_time() {
+    let items = (0..8)
+      .map(|i| {
+        (
+          if i % 2 == 0 {
+            Mode::InterpretedCuda
+          } else {
+            Mode::CompiledCuda
+          },
+          i,
+        )
+      })
+      .collect::<Vec<_>>();
+    let jobs = Jobs {
+      total: 4,
+      compiled: 4,
+      cuda: 1,
+    };
+
+    let (running, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+    run_all(&items, jobs, |_, _| {
+      most.fetch_max(running.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+      thread::sleep(Duration::from_millis(5));
+      running.fetch_sub(1, Ordering::SeqCst);
+    });
+
+    assert_eq!(most.load(Ordering::SeqCst), 1);
+  }
+}
@@ -0,0 +1,22 @@
+//! Benchmarks revisions of hvm against each other, building them from git or
+//! local repos, and formats the results.
+//!
+//! The `hvm-bench` binary is a thin command-line wrapper over this crate. To
+//! benchmark from another tool, configure a [`bench::Bench`] with
+//! [`bench::Bench::builder`], call [`bench::Bench::bench`], and read its
+//! [`stats::Stats`], or run single programs with the functions in [`run`].
+
+pub mod bench;
+pub mod config;
+pub mod environment;
+pub mod expectations;
+mod ext;
+pub mod format;
+pub mod jobs;
+pub mod log;
+mod pick;
+pub mod programs;
+mod progress;
+pub mod regressions;
+pub mod run;
+pub mod stats;
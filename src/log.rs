@@ -0,0 +1,35 @@
+use std::fmt;
+
+/// How much is reported on stderr while benchmarking. Errors and warnings are
+/// reported at every level.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+  /// Nothing but errors and warnings.
+  Quiet,
+  /// What's being built and run, and how far along the benchmark is.
+  #[default]
+  Normal,
+  /// Also the stderr of every run.
+  Verbose,
+}
+
+impl Verbosity {
+  pub fn new(quiet: bool, verbose: bool) -> Self {
+    match (quiet, verbose) {
+      (true, _) => Self::Quiet,
+      (_, true) => Self::Verbose,
+      _ => Self::Normal,
+    }
+  }
+
+  /// Reports `message` on stderr, unless quiet.
+  pub fn info(self, message: fmt::Arguments) {
+    if self >= Self::Normal {
+      eprintln!("{message}");
+    }
+  }
+
+  pub fn is_verbose(self) -> bool {
+    self >= Self::Verbose
+  }
+}
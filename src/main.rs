@@ -1,15 +1,23 @@
-mod bench;
-mod ext;
-mod format;
-mod run;
-mod stats;
-
-use std::{path::PathBuf, time::Duration};
+use std::{
+  env, fs,
+  path::{Path, PathBuf},
+  time::{Duration, Instant},
+};
 
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-
-use self::bench::Bench;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use hvm_bench::{
+  bench::{
+    self, Bench, BenchBuilder, BuildOptions, Event, PartialOutput, Programs, ProgramsSource, Sampling, StopEarly,
+  },
+  config, expectations,
+  format::{self, Change, Color, Format, Metric},
+  jobs::Jobs,
+  log::Verbosity,
+  programs, regressions,
+  run::{self, Generator},
+  stats::{self, Aggregate, Mode},
+};
 
 #[derive(Parser)]
 struct Args {
@@ -19,35 +27,981 @@ struct Args {
 
 #[derive(Subcommand, Debug)]
 enum Command {
+  /// Benchmark revisions of hvm and print their timings.
   Bench {
-    /// Path to local hvm repo to benchmark.
-    #[arg(long, default_value = "./hvm")]
-    repo_dir: PathBuf,
-    /// Which revisions in the remote repository to benchmark.
+    #[command(flatten)]
+    bench: BenchArgs,
+    #[command(flatten)]
+    display: DisplayArgs,
+    /// Show the coefficient of variation of repeated runs after each timing,
+    /// e.g. `1.234s ±2.1%`.
+    #[arg(long)]
+    show_variance: bool,
+    /// What the table cells show: the reported time, the number of
+    /// interactions, millions of interactions per second, the peak memory
+    /// measured with `--measure-memory`, the time the compiled modes spent in
+    /// the C or CUDA compiler, or a hardware counter counted with `--perf`.
+    #[arg(long, value_enum, default_value_t = Metric::Time)]
+    metric: Metric,
+    /// Append to each cell how many times faster it is than this mode for the
+    /// same program and revision, e.g. `0.5s (3.4x)`.
+    #[arg(long, value_enum, value_name = "MODE")]
+    ratio_base: Option<Mode>,
+    /// Color each row of the tables from green for the fastest revision to red
+    /// for the slowest. `auto` colors only if stdout is a terminal.
+    #[arg(long, value_enum, default_value_t = Color::Auto)]
+    color: Color,
+    /// Instead of benchmarking, profile this program under `perf record` for
+    /// every revision.
+    #[arg(long)]
+    profile: Option<PathBuf>,
+    /// Mode to profile with `--profile`.
+    #[arg(long, value_enum, default_value_t = Mode::InterpretedC)]
+    profile_mode: Mode,
+    /// Directory to write `perf.data` files to with `--profile`.
+    #[arg(long, default_value = ".")]
+    profile_out: PathBuf,
+    /// Attach a `key=value` label to the results, e.g. `--tag machine=ci-box`.
+    #[arg(long = "tag", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    tags: Vec<(String, String)>,
+    /// How to print the results. `json` includes every run, the tags and the
+    /// warnings, and `csv` has a row per revision, program and mode, as does
+    /// `tsv`, separated by tabs to paste into spreadsheets. They ignore the
+    /// table options. `markdown` prints the tables as GitHub
+    /// markdown, e.g. for PR comments. `jsonl` prints a line of JSON per
+    /// revision, program and mode as soon as each finishes. `html` prints a
+    /// page with sortable, colored tables, e.g. `--format html > report.html`.
+    /// `pretty` prints the tables with box-drawing borders.
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+    /// Instead of the full tables, print a single score per revision and mode,
+    /// relative to `--score-reference`.
+    #[arg(long)]
+    score: bool,
+    /// Revision the scores are relative to. Defaults to the first of `--revs`,
+    /// or the local repo if there are none.
+    #[arg(long)]
+    score_reference: Option<String>,
+    /// Revision the local repos are judged against in the verdict printed
+    /// after the tables, e.g. the latest release. Defaults to
+    /// `--score-reference`.
+    #[arg(long)]
+    reference: Option<String>,
+    /// Also write the results as Prometheus text metrics to this file.
+    #[arg(long)]
+    prometheus_out: Option<PathBuf>,
+    /// Also write the results as JSON to this file, to compare later runs with
+    /// using `compare --baseline`.
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// Fail if any run takes longer than expected by this file, which has one
+    /// `<program> <mode> <max time>` per line, e.g. `sum_rec compiled-c 50ms`.
+    #[arg(long)]
+    expectations: Option<PathBuf>,
+    /// Fail if the local repo is slower than the first of `--revs` by more
+    /// than this percentage in any program and mode.
+    #[arg(long, value_name = "PCT")]
+    fail_on_regression: Option<f64>,
+    /// Print a line of JSON last, counting the successes, timeouts, skipped
+    /// runs, errors and build failures of each revision, with how long the
+    /// whole benchmark took.
+    #[arg(long)]
+    exit_summary: bool,
+  },
+  /// Benchmark revisions of hvm and print how much slower or faster each is
+  /// than a baseline revision.
+  Compare {
+    /// Revision the others are compared to. It's benchmarked even if it isn't
+    /// one of `--revs`, unless it's read from `--baseline`.
+    #[arg(required_unless_present = "saved_baseline")]
+    baseline: Option<String>,
+    /// Read the results of the baseline revision from this file, written by
+    /// `bench --output`, instead of benchmarking it. The revision may be
+    /// omitted if the file has only one.
+    #[arg(long = "baseline", value_name = "FILE")]
+    saved_baseline: Option<PathBuf>,
+    /// Mark cells that are slower than the baseline by more than this
+    /// percentage.
+    #[arg(long, default_value_t = 5.0)]
+    threshold: f64,
+    /// Only show the cells that regressed or improved by more than the
+    /// threshold, leaving out the rows where nothing did.
+    #[arg(long, value_enum)]
+    only: Option<Change>,
+    #[command(flatten)]
+    bench: BenchArgs,
+    #[command(flatten)]
+    display: DisplayArgs,
+  },
+  /// List the programs that can be benchmarked, with their size and
+  /// first-line comment.
+  List {
+    /// Directory of the programs to list.
+    #[arg(long, default_value = bench::PROGRAMS_DIR)]
+    programs_dir: PathBuf,
+    /// Extensions of the files that are programs.
+    #[arg(long = "program-ext", value_name = "EXT", value_delimiter = ',', default_values = programs::EXTENSIONS)]
+    program_exts: Vec<String>,
+  },
+  /// Write the C or CUDA code an hvm binary generates for a program to a file,
+  /// without compiling or running it, e.g. to inspect what the compiler sees.
+  Generate {
+    /// The hvm binary to generate the code with.
+    binary: PathBuf,
+    /// The program to generate the code for.
+    program: PathBuf,
+    /// Which code to generate.
+    #[arg(long, value_enum, default_value_t = Generator::GenC)]
+    mode: Generator,
+    /// File to write the generated code to.
     #[arg(short, long)]
-    revs: Vec<String>,
-    /// Timeout in seconds
-    #[arg(long, default_value_t = 60)]
-    timeout: u64,
+    output: PathBuf,
   },
 }
 
-fn main() -> Result<()> {
-  match Args::parse().command {
-    Command::Bench {
-      repo_dir,
+/// What to benchmark, and how.
+#[derive(clap::Args, Debug)]
+struct BenchArgs {
+  /// Path to a local hvm repo to benchmark. Repeat it to benchmark several
+  /// working trees, each labeled by its directory name.
+  #[arg(long = "local", alias = "repo-dir", value_name = "DIR", default_value = "./hvm")]
+  locals: Vec<PathBuf>,
+  /// Benchmark a prebuilt hvm binary, labeled `NAME`, instead of building
+  /// anything. Repeat it to benchmark several.
+  #[arg(
+    long = "binary",
+    value_name = "NAME=PATH",
+    value_parser = parse_binary,
+    conflicts_with_all = ["locals", "revs", "rev_range", "since", "interactive", "programs_from_repo"],
+  )]
+  binaries: Vec<(String, PathBuf)>,
+  /// Pick revisions to benchmark, in addition to `--revs`, from a list of
+  /// recent commits in the remote repository.
+  #[arg(long)]
+  interactive: bool,
+  /// Print the revisions that would be built and the commands that would be
+  /// run, then exit without cloning, building or running anything.
+  #[arg(long)]
+  dry_run: bool,
+  /// Which revisions in the remote repository to benchmark. Use
+  /// `merge-base:<base>[,<head>]` to benchmark the merge base of two
+  /// revisions, where `<head>` defaults to the first local repo's `HEAD`.
+  #[arg(short, long)]
+  revs: Vec<String>,
+  /// Benchmark every commit in a range of the remote repository, e.g.
+  /// `v1.0..v2.0`, oldest first.
+  #[arg(long)]
+  rev_range: Vec<String>,
+  /// Benchmark every commit on the default branch of the remote repository
+  /// committed since this date, e.g. `7d`, `2w` or `2024-01-01`, oldest
+  /// first.
+  #[arg(long, value_name = "DATE")]
+  since: Option<String>,
+  /// Repo the remote revisions are cloned from, e.g. a fork.
+  #[arg(long, value_name = "URL", default_value = bench::GIT_URL)]
+  repo_url: String,
+  /// Most commits `--rev-range` or `--since` may expand to, so that a wide
+  /// range isn't built by accident.
+  #[arg(long, default_value_t = 20)]
+  max_revs: u64,
+  /// Clone the contents of every commit of the repo, instead of only its
+  /// history and the contents of the benchmarked revisions.
+  #[arg(long)]
+  full_clone: bool,
+  /// Timeout in seconds for cloning the repo and checking out each revision,
+  /// after which a hung network fails the benchmark.
+  #[arg(long, default_value_t = bench::GIT_TIMEOUT)]
+  git_timeout: u64,
+  /// Timeout in seconds
+  #[arg(long, default_value_t = 60)]
+  timeout: u64,
+  /// Timeout in seconds for the compiled modes, instead of `--timeout`.
+  #[arg(long)]
+  timeout_compiled: Option<u64>,
+  /// Timeout in seconds for the interpreted modes, instead of `--timeout`.
+  #[arg(long)]
+  timeout_interpreted: Option<u64>,
+  /// Run each program this many times in each mode.
+  #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+  runs: u64,
+  /// Keep running each program in each mode, after `--runs`, until the
+  /// coefficient of variation of its runs is below this percentage, e.g.
+  /// `2`, or it ran `--max-runs` times. The tables show how many runs each
+  /// cell needed.
+  #[arg(long, value_name = "PERCENT")]
+  repeat_until_stable: Option<f64>,
+  /// Most runs of each program in each mode with `--repeat-until-stable`.
+  #[arg(long, default_value_t = bench::MAX_RUNS as u64)]
+  max_runs: u64,
+  /// Stop running a program in a mode before `--runs` once it's clearly
+  /// faster or slower than the baseline, or clearly within the threshold of
+  /// it: the baseline and `--threshold` of `compare`, or the first of
+  /// `--revs` and `--fail-on-regression` of `bench`.
+  #[arg(long)]
+  stop_early: bool,
+  /// Run each program this many times in each mode before the measured runs,
+  /// discarding their timings, to warm up caches. The compiled modes run the
+  /// same binary as the measured runs.
+  #[arg(long, default_value_t = 0)]
+  warmup: u64,
+  /// Retry a run that fails this many times before recording the failure,
+  /// e.g. for CUDA runs that fail transiently. Timeouts aren't retried.
+  #[arg(long, default_value_t = 0)]
+  retries: u64,
+  /// Modes to run programs in, e.g. `interpreted-c,compiled-c`. Defaults to
+  /// all of them.
+  #[arg(long, value_enum, value_delimiter = ',')]
+  modes: Vec<Mode>,
+  /// Number of runs to do at once. Concurrent runs compete for the machine,
+  /// so only compare timings taken with the same number of jobs.
+  #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+  jobs: u64,
+  /// Number of compiled runs, which also compile the program, to do at once.
+  /// Defaults to `--jobs`.
+  #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+  compiled_jobs: Option<u64>,
+  /// Number of CUDA runs, which share the GPU, to do at once.
+  #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+  cuda_jobs: u64,
+  /// Number of remote revisions to build at once.
+  #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+  build_jobs: u64,
+  /// Keep built binaries in this directory, keyed by commit hash, and reuse
+  /// them instead of rebuilding the same commit. The local repo is always
+  /// built.
+  #[arg(long)]
+  cache_dir: Option<PathBuf>,
+  /// Keep cargo's build artifacts in this directory, in a subdirectory per
+  /// revision, so that rebuilding a revision that moved to a nearby commit
+  /// only recompiles what changed.
+  #[arg(long)]
+  target_dir: Option<PathBuf>,
+  /// Rebuild cached revisions, replacing their cached binaries.
+  #[arg(long)]
+  no_cache: bool,
+  /// Benchmark the other revisions if one fails to build, showing its results
+  /// as build-err, instead of stopping.
+  #[arg(long)]
+  keep_going: bool,
+  /// Run the CUDA modes even if `nvcc` or `nvidia-smi` can't be found,
+  /// instead of skipping them.
+  #[arg(long)]
+  force_cuda: bool,
+  /// Directory of the programs to benchmark.
+  #[arg(long, default_value = bench::PROGRAMS_DIR)]
+  programs_dir: PathBuf,
+  /// Benchmark the programs shipped in the hvm repo instead of the local
+  /// programs directory. `each` runs each revision on the programs of its
+  /// own checkout; `fixed:<rev>` runs every revision on the programs of
+  /// `<rev>`.
+  #[arg(long)]
+  programs_from_repo: Option<String>,
+  /// Path of the programs directory inside the hvm repo, used with
+  /// `--programs-from-repo`.
+  #[arg(long, default_value = "examples")]
+  repo_programs_dir: PathBuf,
+  /// Only benchmark these programs, by file name without extension.
+  #[arg(long, value_delimiter = ',')]
+  programs: Vec<String>,
+  /// Don't benchmark these programs, by file name without extension.
+  #[arg(long, value_delimiter = ',')]
+  exclude: Vec<String>,
+  /// Extensions of the files in the programs directory that are programs.
+  /// Other files, e.g. READMEs, are skipped.
+  #[arg(long = "program-ext", value_name = "EXT", value_delimiter = ',', default_values = programs::EXTENSIONS)]
+  program_exts: Vec<String>,
+  /// Only benchmark the N smallest of the selected programs, by file size, for
+  /// a quick check. The sample is the same on every run.
+  #[arg(long, value_name = "N")]
+  sample_programs: Option<usize>,
+  /// Also benchmark this program file, wherever it is, e.g. a program being
+  /// written before it's added to the programs directory. Named after its
+  /// file name without extension, and benchmarked regardless of
+  /// `--programs`, `--exclude` and `--sample-programs`. May be repeated.
+  #[arg(long, value_name = "PATH")]
+  program_file: Vec<PathBuf>,
+  /// Only benchmark the `--program-file`s, not the programs directory.
+  #[arg(long, requires = "program_file", conflicts_with_all = ["programs_from_repo", "programs", "exclude", "sample_programs"])]
+  only_program_files: bool,
+  /// Reuse the results of the previously benchmarked revision when a binary
+  /// is byte-identical to it, instead of benchmarking it again.
+  #[arg(long)]
+  skip_identical: bool,
+  /// Compiler for the compiled C mode.
+  #[arg(long, default_value = run::C_COMPILER)]
+  c_compiler: String,
+  /// Flags for `--c-compiler`, replacing the defaults. May be repeated or
+  /// hold several space-separated flags.
+  #[arg(long, allow_hyphen_values = true, default_value = run::C_FLAGS)]
+  c_flags: Vec<String>,
+  /// Compiler for the compiled CUDA mode.
+  #[arg(long, default_value = run::CUDA_COMPILER)]
+  cuda_compiler: String,
+  /// Flags for `--cuda-compiler`, replacing the defaults. May be repeated or
+  /// hold several space-separated flags.
+  #[arg(long, allow_hyphen_values = true, default_value = run::CUDA_FLAGS)]
+  cuda_flags: Vec<String>,
+  /// Build and run hvm inside a container of this docker image, which must
+  /// provide cargo and the C/CUDA compilers.
+  #[arg(long)]
+  docker: Option<String>,
+  /// Extra argument to `docker run`, e.g. `--docker-arg=--gpus=all`.
+  #[arg(long, allow_hyphen_values = true)]
+  docker_arg: Vec<String>,
+  /// Measure the peak memory of every run, shown by `--metric memory`. Runs
+  /// are wrapped in `/usr/bin/time`, which must be installed.
+  #[arg(long)]
+  measure_memory: bool,
+  /// Pin every run to these CPUs, e.g. `2,3` or `0-3`, to reduce scheduler
+  /// noise. Runs are wrapped in `taskset -c`, which must be installed. Only
+  /// supported on Linux; elsewhere runs aren't pinned.
+  #[arg(long, value_name = "CPUS", value_parser = parse_cpu_list)]
+  cpu_affinity: Option<String>,
+  /// Count the cycles, instructions and cache misses of every run, shown by
+  /// `--metric cycles`, `instructions` and `cache-misses`. Runs are wrapped in
+  /// `perf stat`, which must be installed and allowed to count, see
+  /// `kernel.perf_event_paranoid`. Only supported on Linux; elsewhere, or if
+  /// perf can't count, runs aren't counted.
+  #[arg(long)]
+  perf: bool,
+  /// Ask `nvidia-smi` before and after every run of a CUDA mode whether the
+  /// GPU is thermally throttled, warning about the runs it was throttled
+  /// around and marking their cells.
+  #[arg(long)]
+  detect_throttling: bool,
+  /// Keep the generated source and compiled binary of every failed run of a
+  /// compiled mode in `hvm-bench-failures`, printing where with the error.
+  #[arg(long)]
+  keep_temp: bool,
+  /// Print the stderr of every run. Failed runs print the end of theirs
+  /// either way.
+  #[arg(long)]
+  verbose: bool,
+  /// Print only the results, errors and warnings, without reporting what's
+  /// being built and run.
+  #[arg(long, conflicts_with = "verbose")]
+  quiet: bool,
+  /// Set an environment variable for every run, e.g.
+  /// `--env CUDA_VISIBLE_DEVICES=1`. Compilers and builds don't see it.
+  #[arg(long, value_name = "KEY=VALUE", value_parser = parse_key_value)]
+  env: Vec<(String, String)>,
+  /// Read arguments from this TOML file, keyed by their long names, e.g.
+  /// `revs = ["main"]` or `c-flags = "-O3"`. Arguments given on the command
+  /// line override the file.
+  #[arg(long, value_name = "FILE")]
+  config: Option<PathBuf>,
+}
+
+/// How the tables are displayed.
+#[derive(clap::Args, Debug)]
+struct DisplayArgs {
+  /// How to combine the timings of repeated runs. A cell times out if most
+  /// of its runs did.
+  #[arg(long, value_enum, default_value_t = Aggregate::Median)]
+  aggregate: Aggregate,
+  /// Round timings to this granularity (e.g. `10ms`) so that differences
+  /// below it read as equal.
+  #[arg(long, value_parser = parse_duration)]
+  noise_floor: Option<Duration>,
+  /// Only show these revisions in the tables, while still benchmarking all of
+  /// them. The local repo is `local`, or its directory name when there are
+  /// several.
+  #[arg(long)]
+  display_revs: Vec<String>,
+}
+
+fn parse_key_value(key_value: &str) -> Result<(String, String)> {
+  let (key, value) = key_value.split_once('=').context("expected `key=value`")?;
+
+  Ok((key.to_string(), value.to_string()))
+}
+
+fn parse_binary(binary: &str) -> Result<(String, PathBuf)> {
+  let (name, path) = parse_key_value(binary)?;
+
+  Ok((name, PathBuf::from(path)))
+}
+
+fn parse_cpu_list(cpus: &str) -> Result<String> {
+  for range in cpus.split(',') {
+    let (first, last) = range.split_once('-').unwrap_or((range, range));
+    let parse = |cpu: &str| cpu.parse::<usize>().with_context(|| format!("invalid CPU {cpu:?}"));
+    if parse(first)? > parse(last)? {
+      anyhow::bail!("invalid CPU range {range:?}");
+    }
+  }
+
+  Ok(cpus.to_string())
+}
+
+fn parse_duration(duration: &str) -> Result<Duration> {
+  let seconds = stats::parse_seconds(duration).with_context(|| format!("invalid duration {duration:?}"))?;
+
+  Duration::try_from_secs_f64(seconds).context("duration")
+}
+
+impl BenchArgs {
+  /// Returns the labels of the local repos, or of the prebuilt binaries if
+  /// there are any.
+  fn labels(&self) -> Result<Vec<String>> {
+    if !self.binaries.is_empty() {
+      return Ok(self.binaries.iter().map(|(name, _)| name.clone()).collect());
+    }
+
+    bench::local_labels(&self.locals).context("local labels")
+  }
+
+  /// Returns the bench these arguments describe, which compares revisions
+  /// with `baseline` and its threshold, if any, to stop early.
+  fn into_builder(self, baseline: Option<(String, f64)>) -> Result<BenchBuilder> {
+    let BenchArgs {
+      mut locals,
+      binaries,
+      interactive,
+      dry_run: _,
       revs,
+      rev_range,
+      since,
+      repo_url,
+      max_revs,
+      git_timeout,
+      full_clone,
       timeout,
+      timeout_compiled,
+      timeout_interpreted,
+      runs,
+      repeat_until_stable,
+      max_runs,
+      stop_early,
+      warmup,
+      retries,
+      modes,
+      jobs,
+      compiled_jobs,
+      cuda_jobs,
+      build_jobs,
+      cache_dir,
+      target_dir,
+      no_cache,
+      keep_going,
+      force_cuda,
+      programs_dir,
+      programs_from_repo,
+      repo_programs_dir,
+      programs,
+      exclude,
+      program_exts,
+      sample_programs,
+      program_file,
+      only_program_files,
+      skip_identical,
+      c_compiler,
+      c_flags,
+      cuda_compiler,
+      cuda_flags,
+      docker,
+      docker_arg,
+      measure_memory,
+      cpu_affinity,
+      perf,
+      detect_throttling,
+      keep_temp,
+      verbose,
+      quiet,
+      env,
+      config: _,
+    } = self;
+
+    // the default local repo isn't built when there are prebuilt binaries
+    if !binaries.is_empty() {
+      locals.clear();
+    }
+
+    let stop_early = match baseline.filter(|_| stop_early) {
+      Some((baseline, threshold)) => Some(StopEarly { baseline, threshold }),
+      None if stop_early => anyhow::bail!("--stop-early needs a baseline, from `compare` or `--fail-on-regression`"),
+      None => None,
+    };
+
+    let programs_source = match programs_from_repo.as_deref() {
+      None => ProgramsSource::Dir(programs_dir),
+      Some("each") => ProgramsSource::EachRev(repo_programs_dir),
+      Some(policy) => match policy.strip_prefix("fixed:") {
+        Some(rev) => ProgramsSource::FixedRev(rev.to_string(), repo_programs_dir),
+        None => anyhow::bail!("invalid --programs-from-repo {policy:?}, expected `each` or `fixed:<rev>`"),
+      },
+    };
+
+    // absolute, so that they can be mounted in the docker container
+    let program_files = program_file
+      .iter()
+      .map(|file| fs::canonicalize(file).with_context(|| format!("program file {file:?}")))
+      .collect::<Result<Vec<_>>>()?;
+
+    // absolute, as cargo runs in each revision's worktree
+    let target_dir = target_dir
+      .map(|dir| {
+        fs::create_dir_all(&dir).with_context(|| format!("create {dir:?}"))?;
+        fs::canonicalize(&dir).with_context(|| format!("canonicalize {dir:?}"))
+      })
+      .transpose()?;
+
+    let docker = match docker {
+      Some(image) => {
+        let workdir = env::current_dir().context("current dir")?;
+        let mut mounts = vec![workdir.clone(), env::temp_dir()];
+        for local in &locals {
+          mounts.push(fs::canonicalize(local).context("canonicalize")?);
+        }
+        for (_, binary) in &binaries {
+          mounts.push(fs::canonicalize(binary).context("canonicalize")?);
+        }
+        mounts.extend(target_dir.clone());
+        match &programs_source {
+          ProgramsSource::Dir(dir) if !only_program_files => {
+            mounts.push(fs::canonicalize(dir).context("canonicalize")?);
+          }
+          _ => {}
+        }
+        mounts.extend(
+          program_files
+            .iter()
+            .filter_map(|file| file.parent())
+            .map(Path::to_path_buf),
+        );
+
+        Some(run::Docker {
+          image,
+          args: docker_arg,
+          mounts,
+          workdir,
+        })
+      }
+      None => None,
+    };
+
+    let builder = Bench::builder()
+      .local_dirs(locals)
+      .revs(revs.into_iter().chain(rev_range))
+      .binaries(binaries)
+      .sampling(Sampling {
+        timeout: Duration::from_secs(timeout),
+        compiled_timeout: timeout_compiled.map(Duration::from_secs),
+        interpreted_timeout: timeout_interpreted.map(Duration::from_secs),
+        runs: runs as usize,
+        until_stable: repeat_until_stable.map(|percent| percent / 100.0),
+        max_runs: max_runs as usize,
+        warmup: warmup as usize,
+        retries: retries as usize,
+        modes: if modes.is_empty() { Mode::ALL.to_vec() } else { modes },
+        jobs: Jobs {
+          total: jobs as usize,
+          compiled: compiled_jobs.unwrap_or(jobs) as usize,
+          cuda: cuda_jobs as usize,
+        },
+        force_cuda,
+        stop_early,
+      })
+      .programs(Programs {
+        source: programs_source,
+        only: programs,
+        exclude,
+        extensions: program_exts,
+        sample: sample_programs,
+        files: program_files,
+        files_only: only_program_files,
+      })
+      .skip_identical(skip_identical)
+      .run_options(run::Options {
+        docker,
+        c_compiler: run::Compiler::new(&c_compiler, &c_flags),
+        cuda_compiler: run::Compiler::new(&cuda_compiler, &cuda_flags),
+        measure_memory,
+        cpu_affinity,
+        perf,
+        verbosity: Verbosity::new(quiet, verbose),
+        env,
+        keep_temp: keep_temp.then(|| PathBuf::from(run::KEEP_TEMP_DIR)),
+        detect_throttling,
+      })
+      .interactive(interactive)
+      .build_options(BuildOptions {
+        repo_url,
+        jobs: build_jobs as usize,
+        max_revs: max_revs as usize,
+        since,
+        cache_dir,
+        rebuild: no_cache,
+        target_dir,
+        keep_going,
+        git_timeout: Duration::from_secs(git_timeout),
+        full_clone,
+      });
+
+    Ok(builder)
+  }
+}
+
+impl DisplayArgs {
+  fn options(self, bench: &Bench) -> format::Options {
+    format::Options {
+      noise_floor: self.noise_floor,
+      display_revs: self.display_revs,
+      aggregate: self.aggregate,
+      commits: bench.commits.clone(),
+      order: bench.revisions(),
+      ..format::Options::default()
+    }
+  }
+}
+
+/// Parses the command line, along with the config file it names, if any.
+fn parse_args() -> Result<Args> {
+  let matches = Args::command().get_matches();
+  let Some((name, subcommand_matches)) = matches.subcommand() else {
+    return Args::from_arg_matches(&matches).context("from arg matches");
+  };
+  let Some(config) = subcommand_matches.try_get_one::<PathBuf>("config").ok().flatten() else {
+    return Args::from_arg_matches(&matches).context("from arg matches");
+  };
+
+  let command = Args::command();
+  let subcommand = command.find_subcommand(name).context("find subcommand")?;
+  let config_args = config::args(config, subcommand, subcommand_matches).context("config")?;
+
+  Ok(Args::parse_from(env::args_os().chain(config_args)))
+}
+
+fn main() -> Result<()> {
+  match parse_args()?.command {
+    Command::Bench {
+      bench: bench_args,
+      display,
+      show_variance,
+      metric,
+      ratio_base,
+      color,
+      profile,
+      profile_mode,
+      profile_out,
+      tags,
+      format: output_format,
+      score,
+      score_reference,
+      reference,
+      prometheus_out,
+      output,
+      expectations,
+      fail_on_regression,
+      exit_summary,
+    } => {
+      let locals = bench_args.labels()?;
+      let score_reference = match score_reference.or_else(|| bench_args.revs.first().cloned()) {
+        Some(reference) => reference,
+        None => locals[0].clone(),
+      };
+
+      let regression_baseline = match fail_on_regression {
+        Some(threshold) => match bench_args.revs.first() {
+          Some(rev) => Some((rev.clone(), threshold)),
+          None => anyhow::bail!("--fail-on-regression needs a revision in `--revs` to compare with"),
+        },
+        None => None,
+      };
+
+      let expectations = match expectations {
+        Some(path) => expectations::load(&path).with_context(|| format!("load {path:?}"))?,
+        None => Vec::new(),
+      };
+
+      let dry_run = bench_args.dry_run;
+      let show_runs = bench_args.repeat_until_stable.is_some();
+      let mut bench = bench_args
+        .into_builder(regression_baseline.clone())?
+        .partial_output(output.clone().map(|path| PartialOutput {
+          path,
+          tags: tags.clone(),
+        }))
+        .on_event(print_events(output_format == Format::Jsonl, tags.clone()))
+        .build()?;
+
+      if dry_run {
+        print!("{}", bench.plan().context("plan")?);
+        return Ok(());
+      }
+
+      if let Some(program) = profile {
+        let profiles = bench.profile(&program, profile_mode, &profile_out).context("profile")?;
+        for profile in profiles {
+          println!(
+            "{}\n{}\n{}\n",
+            profile.rev,
+            "=".repeat(profile.rev.len()),
+            profile.summary
+          );
+        }
+        return Ok(());
+      }
+
+      let start = Instant::now();
+      bench.bench().context("bench")?;
+
+      let aggregate = display.aggregate;
+      let noise_floor = display.noise_floor;
+
+      if let Some(path) = prometheus_out {
+        let metrics = format::format_prometheus(&bench.stats, aggregate).context("format prometheus")?;
+        fs::write(&path, metrics).with_context(|| format!("write {path:?}"))?;
+      }
+
+      if let Some(path) = output {
+        let json = format::format_json(
+          &bench.stats,
+          &bench.commits,
+          &tags,
+          bench.environment.as_ref(),
+          &bench.warnings,
+        )
+        .context("format json")?;
+        fs::write(&path, json).with_context(|| format!("write {path:?}"))?;
+      }
+
+      match output_format {
+        Format::Table | Format::Pretty => {
+          if !tags.is_empty() {
+            println!("{}", format::format_tags(&tags).context("format tags")?);
+          }
+          if let Some(environment) = &bench.environment {
+            println!(
+              "{}",
+              format::format_environment(environment).context("format environment")?
+            );
+          }
+
+          let options = format::Options {
+            show_variance,
+            show_runs,
+            metric,
+            ratio_base,
+            color: color.enabled(),
+            pretty: output_format == Format::Pretty,
+            ..display.options(&bench)
+          };
+          if score {
+            let table = format::format_score(&bench.stats, &score_reference, &options).context("format score")?;
+            println!("{table}");
+          } else {
+            println!("{}", format::format(&bench.stats, &options).context("format")?);
+
+            let summary = format::format_summary(&bench.stats, &score_reference, &options).context("format summary")?;
+            if !summary.is_empty() {
+              println!("{summary}");
+            }
+
+            let reference = reference.as_ref().unwrap_or(&score_reference);
+            for local in &locals {
+              let verdict =
+                format::format_verdict(&bench.stats, local, reference, &options).context("format verdict")?;
+              if !verdict.is_empty() {
+                println!("{verdict}");
+              }
+            }
+          }
+
+          print_warnings(&bench)?;
+        }
+        Format::Json => {
+          let json = format::format_json(
+            &bench.stats,
+            &bench.commits,
+            &tags,
+            bench.environment.as_ref(),
+            &bench.warnings,
+          )
+          .context("format json")?;
+          println!("{json}");
+        }
+        Format::Csv => print!("{}", format::format_csv(&bench.stats, aggregate).context("format csv")?),
+        Format::Tsv => print!("{}", format::format_tsv(&bench.stats, aggregate).context("format tsv")?),
+        // printed while benchmarking
+        Format::Jsonl => {}
+        Format::Markdown => {
+          let options = format::Options {
+            show_variance,
+            show_runs,
+            metric,
+            ..display.options(&bench)
+          };
+          print!(
+            "{}",
+            format::format_markdown(&bench.stats, &options).context("format markdown")?
+          );
+        }
+        Format::Html => {
+          let options = format::Options {
+            show_variance,
+            show_runs,
+            metric,
+            ..display.options(&bench)
+          };
+          print!(
+            "{}",
+            format::format_html(&bench.stats, &options).context("format html")?
+          );
+        }
+      }
+
+      let violations = expectations::violations(&expectations, &bench.stats, aggregate);
+      for violation in &violations {
+        eprintln!("{violation}");
+      }
+
+      let mut regressions = Vec::new();
+      if let Some((baseline, threshold)) = &regression_baseline {
+        for local in &locals {
+          regressions.extend(
+            regressions::regressions(&bench.stats, baseline, local, *threshold, aggregate, noise_floor)
+              .context("regressions")?,
+          );
+        }
+      }
+      for regression in &regressions {
+        eprintln!("{regression}");
+      }
+
+      if exit_summary {
+        let summary =
+          format::format_exit_summary(&bench.stats, aggregate, start.elapsed()).context("format exit summary")?;
+        println!("{summary}");
+      }
+
+      if !violations.is_empty() {
+        anyhow::bail!("{} runs took longer than expected", violations.len());
+      }
+      if let Some((baseline, threshold)) = regression_baseline.filter(|_| !regressions.is_empty()) {
+        anyhow::bail!(
+          "{} runs regressed by more than {threshold}% from {baseline}",
+          regressions.len()
+        );
+      }
+    }
+    Command::Compare {
+      baseline,
+      saved_baseline,
+      threshold,
+      only,
+      bench: mut bench_args,
+      display,
     } => {
-      if !repo_dir.exists() {
-        anyhow::bail!("{repo_dir:?} does not exist");
+      let saved = match &saved_baseline {
+        Some(path) => {
+          let json = fs::read_to_string(path).with_context(|| format!("read {path:?}"))?;
+          let mut saved = format::parse_json(&json).with_context(|| format!("parse {path:?}"))?;
+
+          let rev = match &baseline {
+            Some(rev) => rev.clone(),
+            None if saved.len() == 1 => saved.keys().next().unwrap().clone(),
+            None => anyhow::bail!(
+              "{path:?} has several revisions, pick one of: {}",
+              saved.keys().cloned().collect::<Vec<_>>().join(", ")
+            ),
+          };
+          let stats = saved
+            .remove(&rev)
+            .with_context(|| format!("no revision {rev:?} in {path:?}"))?;
+
+          Some((format!("{rev} (saved)"), stats))
+        }
+        None => None,
+      };
+
+      let baseline = match &saved {
+        Some((label, _)) => label.clone(),
+        None => {
+          let baseline = baseline.context("baseline")?;
+          let is_local = bench_args.labels()?.contains(&baseline);
+          if !is_local && !bench_args.revs.contains(&baseline) {
+            bench_args.revs.insert(0, baseline.clone());
+          }
+
+          baseline
+        }
+      };
+
+      let dry_run = bench_args.dry_run;
+      let mut bench = bench_args
+        .into_builder(Some((baseline.clone(), threshold)))?
+        .on_event(print_events(false, Vec::new()))
+        .build()?;
+      if dry_run {
+        print!("{}", bench.plan().context("plan")?);
+        return Ok(());
       }
 
-      let mut bench = Bench::new(repo_dir, revs, Duration::from_secs(timeout)).context("Bench::new")?;
+      // before benchmarking, so that the other revisions can stop early
+      let saved = saved.map(|(label, stats)| {
+        bench.stats.insert(label.clone(), stats);
+        label
+      });
       bench.bench().context("bench")?;
 
-      println!("{}", format::format(&bench.stats).context("format")?);
+      let mut options = display.options(&bench);
+      if let Some(label) = saved {
+        options.order.insert(0, label);
+      }
+      let table =
+        format::format_compare(&bench.stats, &baseline, threshold, only, &options).context("format compare")?;
+      println!("{table}");
+
+      print_warnings(&bench)?;
+    }
+    Command::List {
+      programs_dir,
+      program_exts,
+    } => {
+      let programs =
+        programs::describe(&programs_dir, &program_exts).with_context(|| format!("describe {programs_dir:?}"))?;
+      print!("{}", format::format_list(&programs).context("format list")?);
     }
+    Command::Generate {
+      binary,
+      program,
+      mode,
+      output,
+    } => {
+      let code = run::generate_program(&binary, mode, &program, &run::Options::default())
+        .with_context(|| format!("generate {program:?}"))?;
+      fs::write(&output, code).with_context(|| format!("write {output:?}"))?;
+    }
+  }
+
+  Ok(())
+}
+
+/// Prints what a bench reports as it goes: failures that don't stop it, and
+/// each result as a line of JSON with `tags` if `jsonl`.
+fn print_events(jsonl: bool, tags: Vec<(String, String)>) -> Box<dyn Fn(Event) + Send + Sync> {
+  Box::new(move |event| match event {
+    Event::Finished {
+      rev,
+      program,
+      mode,
+      runs,
+    } if jsonl => match format::format_jsonl(rev, program, mode, runs, &tags) {
+      Ok(line) => println!("{line}"),
+      Err(err) => eprintln!("failed to format {program:?} in {mode} as json: {err:#}"),
+    },
+    Event::Finished { .. } => {}
+    Event::Failed(message) => eprintln!("{message}"),
+  })
+}
+
+fn print_warnings(bench: &Bench) -> Result<()> {
+  if !bench.warnings.is_empty() {
+    println!(
+      "{}",
+      format::format_warnings(&bench.warnings).context("format warnings")?
+    );
   }
 
   Ok(())
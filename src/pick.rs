@@ -0,0 +1,80 @@
+use std::io::{self, BufRead, Write};
+
+use anyhow::{Context, Result};
+
+/// Number of candidates listed at once.
+const SHOWN: usize = 20;
+
+/// A commit that can be picked.
+pub struct Candidate {
+  pub hash: String,
+  pub summary: String,
+}
+
+/// Returns whether the characters of `pattern` appear in order in `text`,
+/// ignoring case.
+fn fuzzy_matches(pattern: &str, text: &str) -> bool {
+  let mut text = text.chars().flat_map(char::to_lowercase);
+
+  pattern
+    .chars()
+    .flat_map(char::to_lowercase)
+    .all(|c| text.any(|t| t == c))
+}
+
+/// Lets the user pick commits on the terminal. Typing text fuzzy-filters the
+/// list, typing numbers picks the listed commits with those numbers, and an
+/// empty line finishes. Returns the hashes of the picked commits, in the order
+/// they were picked.
+pub fn pick(candidates: &[Candidate]) -> Result<Vec<String>> {
+  let mut picked: Vec<String> = Vec::new();
+  let mut filter = String::new();
+  let mut stdin = io::stdin().lock();
+  let mut stderr = io::stderr();
+
+  loop {
+    let shown = candidates
+      .iter()
+      .filter(|c| fuzzy_matches(&filter, &format!("{} {}", c.hash, c.summary)))
+      .take(SHOWN)
+      .collect::<Vec<_>>();
+
+    writeln!(stderr)?;
+    for (i, candidate) in shown.iter().enumerate() {
+      let mark = if picked.contains(&candidate.hash) { '*' } else { ' ' };
+      writeln!(stderr, "{mark}{i:>3}  {:.12}  {}", candidate.hash, candidate.summary)?;
+    }
+
+    write!(stderr, "filter, or numbers to pick (empty line to finish)> ")?;
+    stderr.flush()?;
+
+    let mut line = String::new();
+    if stdin.read_line(&mut line).context("read line")? == 0 {
+      break;
+    }
+    let line = line.trim();
+
+    if line.is_empty() {
+      break;
+    }
+
+    let numbers = line
+      .split_whitespace()
+      .map(str::parse::<usize>)
+      .collect::<Result<Vec<_>, _>>();
+    let Ok(numbers) = numbers else {
+      filter = line.to_string();
+      continue;
+    };
+
+    for number in numbers {
+      match shown.get(number) {
+        Some(candidate) if !picked.contains(&candidate.hash) => picked.push(candidate.hash.clone()),
+        Some(_) => {}
+        None => writeln!(stderr, "no commit numbered {number}")?,
+      }
+    }
+  }
+
+  Ok(picked)
+}
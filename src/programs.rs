@@ -0,0 +1,213 @@
+use std::{
+  collections::BTreeMap,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+/// Extension of the file next to a program that holds the arguments it's run
+/// with, e.g. `sum_rec.args` for `sum_rec.hvm`.
+const ARGS_EXTENSION: &str = "args";
+
+/// Extensions of the files taken for programs by default.
+pub const EXTENSIONS: &[&str] = &["hvm", "hvml"];
+
+/// A program file, as listed by the `list` subcommand.
+pub struct Info {
+  /// File name without extension, as accepted by `--programs`.
+  pub name: String,
+  /// Size of the file in bytes.
+  pub size: u64,
+  /// The comment on the first line of the file, if it starts with one.
+  pub comment: Option<String>,
+}
+
+/// A program and the arguments it's run with.
+pub struct Invocation {
+  /// Name of the program, followed by its arguments if it has any, e.g.
+  /// `sum_rec 20`.
+  pub name: String,
+  /// Subdirectory of the programs directory the program is in, e.g.
+  /// `sorting`, or empty if it's at the top.
+  pub category: String,
+  pub path: PathBuf,
+  pub args: Vec<String>,
+}
+
+/// Returns the invocations of the program at `path`, named `name`, in
+/// `category`: one per non-empty line of its args file, with the
+/// whitespace-separated arguments on that line, or a single one without
+/// arguments if it has no args file.
+pub fn invocations(name: &str, category: &str, path: &Path) -> Result<Vec<Invocation>> {
+  let args_path = path.with_extension(ARGS_EXTENSION);
+  if !args_path.exists() {
+    let invocation = Invocation {
+      name: name.to_string(),
+      category: category.to_string(),
+      path: path.to_path_buf(),
+      args: Vec::new(),
+    };
+
+    return Ok(vec![invocation]);
+  }
+
+  let contents = fs::read_to_string(&args_path).with_context(|| format!("read {args_path:?}"))?;
+  let invocations = contents
+    .lines()
+    .filter(|line| !line.trim().is_empty())
+    .map(|line| {
+      let args = line.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+      Invocation {
+        name: format!("{name} {}", args.join(" ")),
+        category: category.to_string(),
+        path: path.to_path_buf(),
+        args,
+      }
+    })
+    .collect();
+
+  Ok(invocations)
+}
+
+/// Returns every file in `dir` and its subdirectories, except hidden ones
+/// such as `.git`.
+pub fn paths<P: AsRef<Path>>(dir: P) -> Result<Vec<PathBuf>> {
+  let mut files = Vec::new();
+  for entry in fs::read_dir(&dir).with_context(|| format!("read dir {:?}", dir.as_ref()))? {
+    let path = entry.context("entry")?.path();
+    if !path.is_dir() {
+      files.push(path);
+    } else if !path.file_name().unwrap_or_default().to_string_lossy().starts_with('.') {
+      files.extend(paths(&path)?);
+    }
+  }
+
+  Ok(files)
+}
+
+/// Returns the category of the program at `path` in `dir`: the subdirectory
+/// it's in, e.g. `sorting` or `numeric/float`, or empty if it's directly in
+/// `dir`.
+pub fn category(dir: &Path, path: &Path) -> String {
+  let parent = path.parent().unwrap_or(Path::new(""));
+  let relative = parent.strip_prefix(dir).unwrap_or(Path::new(""));
+
+  (relative.components())
+    .map(|component| component.as_os_str().to_string_lossy())
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+/// Returns the programs in `dir` and its subdirectories, i.e. the files with
+/// one of `extensions`, keyed by file name without extension. Anything else,
+/// e.g. args files or READMEs, is skipped. Programs must be named uniquely
+/// across subdirectories, so that `--programs` can select them by name.
+pub fn by_name<P: AsRef<Path>>(dir: P, extensions: &[String]) -> Result<BTreeMap<String, PathBuf>> {
+  let is_program = |path: &Path| {
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    path.is_file() && (extensions.iter()).any(|ext| ext.trim_start_matches('.') == extension)
+  };
+
+  let mut programs = BTreeMap::new();
+  for program in paths(dir)?.into_iter().filter(|path| is_program(path)) {
+    let name = program.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    if let Some(other) = programs.insert(name.clone(), program.clone()) {
+      anyhow::bail!("programs {other:?} and {program:?} are both named {name:?}");
+    }
+  }
+
+  Ok(programs)
+}
+
+/// Describes the programs in `dir` with one of `extensions`, sorted by name.
+pub fn describe<P: AsRef<Path>>(dir: P, extensions: &[String]) -> Result<Vec<Info>> {
+  let mut infos = Vec::new();
+  for (name, path) in by_name(dir, extensions)? {
+    let size = fs::metadata(&path).with_context(|| format!("metadata {path:?}"))?.len();
+    let contents = fs::read_to_string(&path).with_context(|| format!("read {path:?}"))?;
+    let comment = contents
+      .lines()
+      .next()
+      .and_then(|line| line.trim().strip_prefix("//"))
+      .map(|comment| comment.trim().to_string());
+
+    infos.push(Info { name, size, comment });
+  }
+
+  Ok(infos)
+}
+
+#[cfg(test)]
+mod tests {
+  use tempfile::TempDir;
+
+  use super::*;
+
+  #[test]
+  fn args_files_give_an_invocation_per_line() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("fib.hvm"), "").unwrap();
+    fs::write(dir.path().join("fib.args"), "20\n\n30 --flag\n").unwrap();
+    fs::write(dir.path().join("sum_rec.hvm"), "").unwrap();
+
+    let programs = by_name(dir.path(), &["hvm".to_string()]).unwrap();
+    assert_eq!(programs.keys().collect::<Vec<_>>(), ["fib", "sum_rec"]);
+
+    let fib = invocations("fib", "", &programs["fib"]).unwrap();
+    assert_eq!(
+      fib.iter().map(|i| i.name.as_str()).collect::<Vec<_>>(),
+      ["fib 20", "fib 30 --flag"]
+    );
+    assert_eq!(fib[1].args, ["30", "--flag"]);
+
+    let sum_rec = invocations("sum_rec", "", &programs["sum_rec"]).unwrap();
+    assert_eq!(sum_rec[0].name, "sum_rec");
+    assert!(sum_rec[0].args.is_empty());
+  }
+
+  #[test]
+  fn only_files_with_program_extensions_are_programs() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("fib.hvm"), "").unwrap();
+    fs::write(dir.path().join("sort.hvml"), "").unwrap();
+    fs::write(dir.path().join("README.md"), "").unwrap();
+    fs::write(dir.path().join(".gitignore"), "").unwrap();
+    fs::create_dir(dir.path().join("lib.hvm")).unwrap();
+
+    let extensions = EXTENSIONS.iter().map(|ext| ext.to_string()).collect::<Vec<_>>();
+    let programs = by_name(dir.path(), &extensions).unwrap();
+    assert_eq!(programs.keys().collect::<Vec<_>>(), ["fib", "sort"]);
+
+    let programs = by_name(dir.path(), &[".hvml".to_string()]).unwrap();
+    assert_eq!(programs.keys().collect::<Vec<_>>(), ["sort"]);
+  }
+
+  #[test]
+  fn subdirectories_are_categories() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir_all(dir.path().join("sorting")).unwrap();
+    fs::create_dir_all(dir.path().join("numeric/float")).unwrap();
+    fs::create_dir_all(dir.path().join(".git")).unwrap();
+    fs::write(dir.path().join("fib.hvm"), "").unwrap();
+    fs::write(dir.path().join("sorting/bitonic.hvm"), "").unwrap();
+    fs::write(dir.path().join("numeric/float/sqrt.hvm"), "").unwrap();
+    fs::write(dir.path().join(".git/hidden.hvm"), "").unwrap();
+
+    let programs = by_name(dir.path(), &["hvm".to_string()]).unwrap();
+    let categories = (programs.iter())
+      .map(|(name, path)| (name.as_str(), category(dir.path(), path)))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      categories,
+      [
+        ("bitonic", "sorting".to_string()),
+        ("fib", String::new()),
+        ("sqrt", "numeric/float".to_string())
+      ]
+    );
+
+    fs::write(dir.path().join("numeric/fib.hvm"), "").unwrap();
+    assert!(by_name(dir.path(), &["hvm".to_string()]).is_err());
+  }
+}
@@ -0,0 +1,113 @@
+use std::{
+  fmt::Write,
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+use crate::{log::Verbosity, stats::Mode};
+
+/// Number of cells that must have finished before the remaining time is
+/// estimated.
+const MIN_FINISHED_FOR_ETA: usize = 3;
+
+/// Progress through the cells of a benchmark, i.e. the runs of each program
+/// in each mode of each revision. Reported as plain lines on stderr, so that
+/// it reads fine when redirected, unless quiet.
+pub struct Progress {
+  started_at: Instant,
+  verbosity: Verbosity,
+  counts: Mutex<Counts>,
+}
+
+struct Counts {
+  total: usize,
+  started: usize,
+  finished: usize,
+}
+
+impl Progress {
+  pub fn new(total: usize, verbosity: Verbosity) -> Self {
+    Self {
+      started_at: Instant::now(),
+      verbosity,
+      counts: Mutex::new(Counts {
+        total,
+        started: 0,
+        finished: 0,
+      }),
+    }
+  }
+
+  /// Reports that the cell of `program` in `mode` of `rev` started, with an
+  /// estimate of the time left once enough cells have finished.
+  pub fn start(&self, rev: &str, program: &str, mode: Mode) {
+    let mut counts = self.counts.lock().unwrap();
+    counts.started += 1;
+
+    let mut line = format!(
+      "[{}/{}] revision {rev:?}, program {program:?}, mode {mode}",
+      counts.started, counts.total
+    );
+    if let Some(left) = counts.left(self.started_at.elapsed()) {
+      write!(line, ", about {} left", format_duration(left)).unwrap();
+    }
+
+    self.verbosity.info(format_args!("{line}"));
+  }
+
+  /// Records that a started cell finished.
+  pub fn finish(&self) {
+    self.counts.lock().unwrap().finished += 1;
+  }
+
+  /// Records that `cells` cells won't run, e.g. because the results of an
+  /// identical revision are reused.
+  pub fn skip(&self, cells: usize) {
+    let mut counts = self.counts.lock().unwrap();
+    counts.total = counts.total.saturating_sub(cells);
+  }
+}
+
+impl Counts {
+  /// Estimates the time left from the average time a cell took so far.
+  fn left(&self, elapsed: Duration) -> Option<Duration> {
+    if self.finished < MIN_FINISHED_FOR_ETA {
+      return None;
+    }
+
+    let remaining = self.total.saturating_sub(self.finished);
+    Some(elapsed.mul_f64(remaining as f64 / self.finished as f64))
+  }
+}
+
+/// Formats `duration` to the second, e.g. `1h05m`, `2m30s`, or `45s`.
+fn format_duration(duration: Duration) -> String {
+  let secs = duration.as_secs();
+  match secs {
+    3600.. => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    60.. => format!("{}m{:02}s", secs / 60, secs % 60),
+    _ => format!("{secs}s"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn time_left_is_estimated_from_finished_cells() {
+    let mut counts = Counts {
+      total: 20,
+      started: 3,
+      finished: 2,
+    };
+    assert_eq!(counts.left(Duration::from_secs(10)), None);
+
+    counts.finished = 4;
+    assert_eq!(counts.left(Duration::from_secs(10)), Some(Duration::from_secs(40)));
+
+    assert_eq!(format_duration(Duration::from_secs(45)), "45s");
+    assert_eq!(format_duration(Duration::from_secs(150)), "2m30s");
+    assert_eq!(format_duration(Duration::from_secs(3900)), "1h05m");
+  }
+}
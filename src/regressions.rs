@@ -0,0 +1,95 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::Result;
+
+use crate::stats::{self, Aggregate, Mode, Stats, Timing};
+
+/// Returns a description of every program and mode in which `revision` is
+/// slower than `baseline` by more than `threshold` percent, with repeated runs
+/// combined by `aggregate` and rounded to `noise_floor`, so that differences
+/// below it don't count. Timing out where the baseline didn't counts as
+/// slower, while failed runs have no timing and are not checked.
+pub fn regressions(
+  stats: &BTreeMap<String, Stats>,
+  baseline: &str,
+  revision: &str,
+  threshold: f64,
+  aggregate: Aggregate,
+  noise_floor: Option<Duration>,
+) -> Result<Vec<String>> {
+  let (Some(baseline_stats), Some(revision_stats)) = (stats.get(baseline), stats.get(revision)) else {
+    anyhow::bail!("no results to compare {revision:?} with {baseline:?}");
+  };
+
+  let round = |seconds: f64| match noise_floor {
+    Some(floor) => stats::round_seconds(seconds, floor),
+    None => seconds,
+  };
+
+  let mut regressions = Vec::new();
+
+  for (program, program_stats) in &revision_stats.programs {
+    let Some(baseline_program) = baseline_stats.programs.get(program) else {
+      continue;
+    };
+
+    for mode in Mode::ALL {
+      let (Some(Ok(baseline_timing)), Some(Ok(timing))) = (
+        baseline_program.timing(mode, aggregate),
+        program_stats.timing(mode, aggregate),
+      ) else {
+        continue;
+      };
+
+      let regressed = match (&baseline_timing, &timing) {
+        (Timing::Completed { .. }, Timing::Timeout | Timing::CompileTimeout) => true,
+        (Timing::Completed { reported: base, .. }, Timing::Completed { reported, .. }) => {
+          let (base, seconds) = (round(base.seconds), round(reported.seconds));
+          base > 0.0 && (seconds - base) / base * 100.0 > threshold
+        }
+        _ => false,
+      };
+
+      if regressed {
+        regressions.push(format!(
+          "{revision} {program} {mode} took {timing}, {baseline} took {baseline_timing}"
+        ));
+      }
+    }
+  }
+
+  Ok(regressions)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::stats::{Program, Reported};
+
+  fn stats(seconds: f64) -> Stats {
+    let timing = Timing::Completed {
+      reported: Reported::parse(&format!("{seconds}s")).unwrap(),
+      wall: Duration::from_secs_f64(seconds),
+      peak_memory: None,
+      compile_time: None,
+      counters: None,
+      throttled: false,
+    };
+    let mut program = Program::default();
+    *program.mode_mut(Mode::InterpretedC) = Some(Ok(vec![timing]));
+
+    let mut stats = Stats::default();
+    stats.programs.insert("sum_rec".to_string(), program);
+    stats
+  }
+
+  #[test]
+  fn differences_below_the_noise_floor_are_not_regressions() {
+    let stats = BTreeMap::from([("main".to_string(), stats(0.101)), ("local".to_string(), stats(0.119))]);
+    let regressions = |noise_floor| regressions(&stats, "main", "local", 10.0, Aggregate::Median, noise_floor).unwrap();
+
+    assert_eq!(regressions(None).len(), 1);
+    // both round to 0.1s
+    assert!(regressions(Some(Duration::from_millis(100))).is_empty());
+  }
+}
@@ -1,121 +1,1216 @@
-use std::{io::Write, path::Path, process::Command, time::Duration};
+use std::{
+  ffi::{OsStr, OsString},
+  fmt, fs,
+  path::{Path, PathBuf},
+  process::Command,
+  time::{Duration, Instant},
+};
 
 use anyhow::{Context, Result};
-use tempfile::{NamedTempFile, TempDir};
+use tempfile::TempDir;
 
 use crate::{
-  ext::{CommandExt, NamedTempFileExt},
-  stats::Timing,
+  ext::{Captured, CommandExt, ExitError},
+  log::Verbosity,
+  stats::{Counters, Mode, Reported, Timing},
 };
 
 const TIME_PREFIX: &str = "- TIME: ";
+const ITRS_PREFIX: &str = "- ITRS: ";
+const MIPS_PREFIX: &str = "- MIPS: ";
+/// GNU time, which reports the peak memory of the command it runs.
+const TIME_BIN: &str = "/usr/bin/time";
+/// Counts the hardware events of the command it runs.
+const PERF_BIN: &str = "perf";
+/// Events counted by `perf stat`, see [`Counters`].
+const PERF_EVENTS: &str = "cycles,instructions,cache-misses";
+/// Runs a command pinned to a set of CPUs, replacing itself with it.
+const TASKSET_BIN: &str = "taskset";
+/// Prefix of the line `time` is told to print with the peak memory, in KiB,
+/// worded like the one of `time -v`.
+const PEAK_MEMORY_PREFIX: &str = "Maximum resident set size (kbytes): ";
+/// Stderr of an `hvm` binary that doesn't know a mode, e.g. an old revision
+/// without `run-cu`, contains one of these.
+const UNSUPPORTED_MARKERS: [&str; 3] = ["unrecognized subcommand", "invalid subcommand", "wasn't expected"];
+/// Queries whether each GPU is slowed down to cool off, by the hardware or by
+/// the driver, with a `Active` or `Not Active` column for each.
+const THROTTLE_QUERY: &str =
+  "--query-gpu=clocks_throttle_reasons.hw_thermal_slowdown,clocks_throttle_reasons.sw_thermal_slowdown";
+/// Number of lines of `perf report` shown by [`profile`].
+const PROFILE_SUMMARY_LINES: usize = 20;
+
+pub const C_COMPILER: &str = "gcc";
+pub const C_FLAGS: &str = "-lm -O2";
+pub const CUDA_COMPILER: &str = "nvcc";
+pub const CUDA_FLAGS: &str = "-w -O3";
+/// Directory the generated files of failed runs are kept in, with
+/// [`Options::keep_temp`].
+pub const KEEP_TEMP_DIR: &str = "hvm-bench-failures";
+
+/// Why a run failed, so that failures can be told apart in the results.
+#[derive(Debug)]
+pub enum RunError {
+  /// The revision failed to build, so the program wasn't run.
+  Build(anyhow::Error),
+  /// Generating or compiling the program failed.
+  Compile(anyhow::Error),
+  /// The program exited with a non-zero status.
+  Crash(anyhow::Error),
+  /// The program finished, but its timing couldn't be parsed.
+  Parse(anyhow::Error),
+  /// Anything else, e.g. the program couldn't be started.
+  Other(anyhow::Error),
+}
+
+impl RunError {
+  /// Returns the short label shown in place of a timing.
+  pub fn label(&self) -> &'static str {
+    match self {
+      RunError::Build(_) => "build-err",
+      RunError::Compile(_) => "compile-err",
+      RunError::Crash(_) => "crash",
+      RunError::Parse(_) => "parse-err",
+      RunError::Other(_) => "error",
+    }
+  }
+
+  /// Returns an error with the kind of `label` and the message `message`,
+  /// e.g. to read back a saved error.
+  pub fn from_label(label: &str, message: String) -> Self {
+    let err = anyhow::anyhow!(message);
+    match label {
+      "build-err" => RunError::Build(err),
+      "compile-err" => RunError::Compile(err),
+      "crash" => RunError::Crash(err),
+      "parse-err" => RunError::Parse(err),
+      _ => RunError::Other(err),
+    }
+  }
+
+  /// Returns a copy of the error, keeping only its message.
+  pub fn to_owned_message(&self) -> Self {
+    Self::from_label(self.label(), format!("{self}"))
+  }
+
+  fn context<C>(self, context: C) -> Self
+  where
+    C: fmt::Display + Send + Sync + 'static,
+  {
+    match self {
+      RunError::Build(err) => RunError::Build(err.context(context)),
+      RunError::Compile(err) => RunError::Compile(err.context(context)),
+      RunError::Crash(err) => RunError::Crash(err.context(context)),
+      RunError::Parse(err) => RunError::Parse(err.context(context)),
+      RunError::Other(err) => RunError::Other(err.context(context)),
+    }
+  }
+}
 
-/// Returns the timing line of an `hvm` run.
-fn parse_stdout(stdout: &str) -> Result<Timing> {
-  for line in stdout.lines() {
-    if let Some(timing) = line.strip_prefix(TIME_PREFIX) {
-      return Ok(timing.to_string());
+impl fmt::Display for RunError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let (RunError::Build(err)
+    | RunError::Compile(err)
+    | RunError::Crash(err)
+    | RunError::Parse(err)
+    | RunError::Other(err)) = self;
+
+    write!(f, "{err:#}")
+  }
+}
+
+impl std::error::Error for RunError {}
+
+/// Settings shared by every command that builds or runs hvm.
+#[derive(Clone)]
+pub struct Options {
+  /// Run commands inside a container instead of on the host.
+  pub docker: Option<Docker>,
+  /// Compiler for the code generated by the compiled C mode.
+  pub c_compiler: Compiler,
+  /// Compiler for the code generated by the compiled CUDA mode.
+  pub cuda_compiler: Compiler,
+  /// Measure the peak memory of runs, by running them under `time`.
+  pub measure_memory: bool,
+  /// CPUs to pin measured runs to, as `taskset -c` takes them, e.g. `2,3`
+  /// or `0-3`. Only Linux supports pinning; elsewhere runs aren't pinned.
+  pub cpu_affinity: Option<String>,
+  /// Count the cycles, instructions and cache misses of measured runs, by
+  /// running them under `perf stat`. Only Linux has perf; elsewhere runs
+  /// aren't counted.
+  pub perf: bool,
+  /// How much to report on stderr. When verbose, the stderr of every run is
+  /// written through to ours. The stderr of a failed run is part of its error
+  /// either way.
+  pub verbosity: Verbosity,
+  /// Environment variables set for every measured run, e.g.
+  /// `CUDA_VISIBLE_DEVICES`.
+  pub env: Vec<(String, String)>,
+  /// Directory to copy the generated source and compiled binary of a failed
+  /// run of a compiled mode to, instead of deleting them, e.g. to reproduce
+  /// a codegen bug by hand.
+  pub keep_temp: Option<PathBuf>,
+  /// Ask `nvidia-smi` before and after each run of a CUDA mode whether the
+  /// GPU is thermally throttled, marking the run as throttled if it is.
+  pub detect_throttling: bool,
+}
+
+impl Default for Options {
+  fn default() -> Self {
+    Self {
+      docker: None,
+      c_compiler: Compiler::new(C_COMPILER, &[C_FLAGS]),
+      cuda_compiler: Compiler::new(CUDA_COMPILER, &[CUDA_FLAGS]),
+      measure_memory: false,
+      cpu_affinity: None,
+      perf: false,
+      verbosity: Verbosity::default(),
+      env: Vec::new(),
+      keep_temp: None,
+      detect_throttling: false,
+    }
+  }
+}
+
+/// A compiler and the flags it's invoked with.
+#[derive(Clone)]
+pub struct Compiler {
+  pub program: String,
+  pub flags: Vec<String>,
+}
+
+impl Compiler {
+  /// Returns a compiler invoked with `flags`, each of which may hold several
+  /// space-separated flags.
+  pub fn new<S: AsRef<str>>(program: &str, flags: &[S]) -> Self {
+    Self {
+      program: program.to_string(),
+      flags: flags
+        .iter()
+        .flat_map(|flags| flags.as_ref().split_whitespace())
+        .map(str::to_string)
+        .collect(),
     }
   }
+}
 
-  anyhow::bail!("no line with {TIME_PREFIX:?} found")
+/// A docker image to run commands in.
+#[derive(Clone)]
+pub struct Docker {
+  pub image: String,
+  /// Extra arguments to `docker run`, e.g. `--gpus=all`.
+  pub args: Vec<String>,
+  /// Host directories mounted at the same path in the container. Commands
+  /// must only refer to paths under these.
+  pub mounts: Vec<PathBuf>,
+  /// Directory relative paths are resolved against, normally our own.
+  pub workdir: PathBuf,
 }
 
-/// Executes `hvm_bin mode program`, parsing hvm's timing output. an interpreted
-/// mode, without an additional C compilation step.
-fn interpreted<P, Q>(hvm_bin: P, mode: &str, program: Q, timeout: Duration) -> Result<Timing>
+impl Options {
+  /// Returns a command running `program`.
+  pub fn command<S: AsRef<OsStr>>(&self, program: S) -> Command {
+    match &self.docker {
+      Some(docker) => docker.command(&docker.workdir, program, &[]),
+      None => Command::new(program),
+    }
+  }
+
+  /// Returns a command running `program` with the environment variables of
+  /// measured runs.
+  fn env_command<S: AsRef<OsStr>>(&self, program: S) -> Command {
+    match &self.docker {
+      Some(docker) => docker.command(&docker.workdir, program, &self.env),
+      None => {
+        let mut command = Command::new(program);
+        command.envs(self.env.iter().map(|(key, value)| (key, value)));
+
+        command
+      }
+    }
+  }
+
+  /// Returns a command running `program` as a measured run, under `taskset`
+  /// if it's pinned to CPUs, under `time` if memory is measured and under
+  /// `perf stat` if it's counted.
+  fn run_command<S: AsRef<OsStr>>(&self, program: S) -> Command {
+    let mut wrappers = Vec::<OsString>::new();
+    if let Some(cpus) = self.cpu_affinity.as_ref().filter(|_| cfg!(target_os = "linux")) {
+      wrappers.extend([TASKSET_BIN.into(), "-c".into(), cpus.into()]);
+    }
+    if self.measure_memory {
+      wrappers.extend([TIME_BIN.into(), "-f".into(), format!("{PEAK_MEMORY_PREFIX}%M").into()]);
+    }
+    if self.perf && cfg!(target_os = "linux") {
+      wrappers.extend([PERF_BIN, "stat", "-x,", "-e", PERF_EVENTS, "--"].map(OsString::from));
+    }
+
+    let Some((wrapper, args)) = wrappers.split_first() else {
+      return self.env_command(program);
+    };
+    let mut command = self.env_command(wrapper);
+    command.args(args).arg(program);
+
+    command
+  }
+
+  /// Returns whether `perf stat` can count the events of a run, which needs
+  /// perf to be installed and the kernel to let us count, see
+  /// `kernel.perf_event_paranoid`.
+  pub fn perf_available(&self) -> bool {
+    cfg!(target_os = "linux")
+      && (self.command(PERF_BIN))
+        .args(["stat", "-x,", "-e", PERF_EVENTS, "--", "true"])
+        .output()
+        .is_ok_and(|output| output.status.success())
+  }
+
+  /// Returns a command running `program` in `dir`.
+  pub fn command_in<P: AsRef<Path>, S: AsRef<OsStr>>(&self, dir: P, program: S) -> Command {
+    match &self.docker {
+      Some(docker) => docker.command(&docker.workdir.join(dir), program, &[]),
+      None => {
+        let mut command = Command::new(program);
+        command.current_dir(dir);
+
+        command
+      }
+    }
+  }
+}
+
+impl Docker {
+  /// Returns a command running `program` in `dir` of a new container, with
+  /// the environment variables `env`.
+  fn command<S: AsRef<OsStr>>(&self, dir: &Path, program: S, env: &[(String, String)]) -> Command {
+    let mut command = Command::new("docker");
+    command.args(["run", "--rm", "--init"]);
+
+    // docker rejects mounting the same path twice, and nested ones are already
+    // visible through their parent
+    for mount in &self.mounts {
+      if !self
+        .mounts
+        .iter()
+        .any(|other| other != mount && mount.starts_with(other))
+      {
+        command.arg("--volume").arg(format!("{0}:{0}", mount.display()));
+      }
+    }
+
+    for (key, value) in env {
+      command.arg("--env").arg(format!("{key}={value}"));
+    }
+
+    command
+      .arg("--workdir")
+      .arg(dir)
+      .args(&self.args)
+      .arg(&self.image)
+      .arg(program);
+
+    command
+  }
+}
+
+/// Parses the timing line of an `hvm` run, along with the interactions and
+/// MIPS lines if it printed them. The other lines are the program's result.
+///
+/// Only lines that start with a prefix count, so that a result containing
+/// e.g. `- TIME:` isn't taken for the timing, and of several lines with the
+/// same prefix, only the first. Lines may end in `\r\n`, and the values may
+/// have whitespace around them.
+fn parse_stdout(stdout: &str) -> Result<Reported> {
+  let first = |prefix: &str| stdout.lines().find_map(|line| line.strip_prefix(prefix));
+
+  let Some(timing) = first(TIME_PREFIX) else {
+    anyhow::bail!("no line with {TIME_PREFIX:?} found");
+  };
+  let mut reported = Reported::parse(timing).with_context(|| format!("invalid time {timing:?}"))?;
+
+  if let Some(interactions) = first(ITRS_PREFIX) {
+    let parsed = interactions
+      .trim()
+      .parse()
+      .with_context(|| format!("invalid interactions {interactions:?}"))?;
+    reported.interactions = Some(parsed);
+  }
+  if let Some(mips) = first(MIPS_PREFIX) {
+    let parsed = mips.trim().parse().with_context(|| format!("invalid mips {mips:?}"))?;
+    reported.mips = Some(parsed);
+  }
+
+  let output = stdout
+    .lines()
+    .filter(|line| {
+      ![TIME_PREFIX, ITRS_PREFIX, MIPS_PREFIX]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+    })
+    .collect::<Vec<_>>()
+    .join("\n");
+  reported.output = Some(output);
+
+  Ok(reported)
+}
+
+/// Returns the timing line of an `hvm` run, falling back to stderr for
+/// versions that print it there.
+fn parse_output(output: &Captured) -> Result<Reported> {
+  parse_stdout(&output.stdout).or_else(|_| parse_stdout(&output.stderr).context("stdout and stderr"))
+}
+
+/// Returns the peak memory in bytes reported by `time`, if it ran.
+fn parse_peak_memory(stderr: &str) -> Option<u64> {
+  let kib = stderr
+    .lines()
+    .find_map(|line| line.trim().strip_prefix(PEAK_MEMORY_PREFIX))?;
+
+  kib.trim().parse::<u64>().ok().map(|kib| kib * 1024)
+}
+
+/// Returns the counters `perf stat -x,` printed, if it ran. Its lines are
+/// `value,unit,event,...`, with a value like `<not supported>` for events it
+/// couldn't count.
+fn parse_counters(stderr: &str) -> Option<Counters> {
+  let mut counters = None;
+  for line in stderr.lines() {
+    let [value, _, event, ..] = line.trim().split(',').collect::<Vec<_>>()[..] else {
+      continue;
+    };
+    // user-space only counts are suffixed, e.g. `cycles:u`
+    let counter: fn(&mut Counters) -> &mut Option<u64> = match event.split(':').next() {
+      Some("cycles") => |counters| &mut counters.cycles,
+      Some("instructions") => |counters| &mut counters.instructions,
+      Some("cache-misses") => |counters| &mut counters.cache_misses,
+      _ => continue,
+    };
+    *counter(counters.get_or_insert_with(Counters::default)) = value.parse().ok();
+  }
+
+  counters
+}
+
+/// Returns whether `err` was caused by an `hvm` binary not supporting the mode
+/// it was invoked with.
+fn is_unsupported(err: &anyhow::Error) -> bool {
+  err
+    .chain()
+    .filter_map(|err| err.downcast_ref::<ExitError>())
+    .any(|err| UNSUPPORTED_MARKERS.iter().any(|marker| err.stderr.contains(marker)))
+}
+
+/// Executes `hvm_bin mode program args...`, parsing hvm's timing output. an
+/// interpreted mode, without an additional C compilation step.
+fn interpreted<P, Q>(
+  hvm_bin: P,
+  mode: &str,
+  program: Q,
+  args: &[String],
+  timeout: Duration,
+  options: &Options,
+) -> Result<Timing, RunError>
 where
   P: AsRef<Path>,
   Q: AsRef<Path>,
 {
-  let Some(stdout) = Command::new(hvm_bin.as_ref())
-    .arg(mode)
-    .arg(program.as_ref())
-    .status_stdout_timeout(timeout)?
-  else {
-    return Ok("timeout".to_string());
-  };
+  run_timed(
+    options
+      .run_command(hvm_bin.as_ref())
+      .arg(mode)
+      .arg(program.as_ref())
+      .args(args),
+    timeout,
+    options,
+  )
+}
+
+/// Runs `command`, parsing hvm's timing output and measuring the wall-clock
+/// time of the run.
+fn run_timed(command: &mut Command, timeout: Duration, options: &Options) -> Result<Timing, RunError> {
+  let start = Instant::now();
+  let output = command.status_output_timeout(timeout);
+  let wall = start.elapsed();
+
+  if options.verbosity.is_verbose() {
+    let stderr = match &output {
+      Ok(Some(output)) => Some(&output.stderr),
+      Ok(None) => None,
+      Err(err) => err.downcast_ref::<ExitError>().map(|err| &err.stderr),
+    };
+    if let Some(stderr) = stderr {
+      eprint!("{stderr}");
+    }
+  }
 
-  parse_stdout(&stdout).context("parse")
+  match output {
+    Ok(Some(output)) => Ok(Timing::Completed {
+      reported: parse_output(&output).context("parse").map_err(RunError::Parse)?,
+      wall,
+      peak_memory: parse_peak_memory(&output.stderr),
+      compile_time: None,
+      counters: parse_counters(&output.stderr),
+      throttled: false,
+    }),
+    Ok(None) => Ok(Timing::Timeout),
+    Err(err) if is_unsupported(&err) => Ok(Timing::Unsupported),
+    Err(err) if err.chain().any(|err| err.is::<ExitError>()) => Err(RunError::Crash(err)),
+    Err(err) => Err(RunError::Other(err)),
+  }
 }
 
-pub fn interpreted_c<P, Q>(hvm_bin: P, program: Q, timeout: Duration) -> Result<Timing>
+pub fn interpreted_c<P, Q>(
+  hvm_bin: P,
+  program: Q,
+  args: &[String],
+  timeout: Duration,
+  options: &Options,
+) -> Result<Timing, RunError>
 where
   P: AsRef<Path>,
   Q: AsRef<Path>,
 {
-  interpreted(hvm_bin, "run-c", program, timeout)
+  interpreted(hvm_bin, "run-c", program, args, timeout, options)
 }
 
-pub fn interpreted_cuda<P, Q>(hvm_bin: P, program: Q, timeout: Duration) -> Result<Timing>
+pub fn interpreted_cuda<P, Q>(
+  hvm_bin: P,
+  program: Q,
+  args: &[String],
+  timeout: Duration,
+  options: &Options,
+) -> Result<Timing, RunError>
 where
   P: AsRef<Path>,
   Q: AsRef<Path>,
 {
-  interpreted(hvm_bin, "run-cu", program, timeout)
+  detect_throttling(options, || {
+    interpreted(hvm_bin, "run-cu", program, args, timeout, options)
+  })
+}
+
+/// Runs `run`, marking its timing as throttled if the GPU is thermally
+/// throttled before or after it, with [`Options::detect_throttling`].
+fn detect_throttling<F>(options: &Options, run: F) -> Result<Timing, RunError>
+where
+  F: FnOnce() -> Result<Timing, RunError>,
+{
+  if !options.detect_throttling {
+    return run();
+  }
+
+  let before = is_throttled(options);
+  let timing = run()?;
+
+  Ok(timing.with_throttled(before || is_throttled(options)))
+}
+
+/// Returns whether `nvidia-smi` reports any GPU as thermally throttled. If it
+/// can't be asked, the GPU isn't taken to be throttled.
+fn is_throttled(options: &Options) -> bool {
+  let output = options
+    .command("nvidia-smi")
+    .args([THROTTLE_QUERY, "--format=csv,noheader"])
+    .output();
+
+  match output {
+    Ok(output) if output.status.success() => parse_throttle_reasons(&String::from_utf8_lossy(&output.stdout)),
+    _ => false,
+  }
+}
+
+/// Returns whether any of the throttle reasons `nvidia-smi` printed for
+/// [`THROTTLE_QUERY`] is active.
+fn parse_throttle_reasons(stdout: &str) -> bool {
+  (stdout.lines())
+    .flat_map(|line| line.split(','))
+    .any(|reason| reason.trim() == "Active")
 }
 
-pub fn interpreted_rust<P, Q>(hvm_bin: P, program: Q, timeout: Duration) -> Result<Timing>
+pub fn interpreted_rust<P, Q>(
+  hvm_bin: P,
+  program: Q,
+  args: &[String],
+  timeout: Duration,
+  options: &Options,
+) -> Result<Timing, RunError>
 where
   P: AsRef<Path>,
   Q: AsRef<Path>,
 {
-  interpreted(hvm_bin, "run", program, timeout)
+  interpreted(hvm_bin, "run", program, args, timeout, options)
+}
+
+/// An hvm subcommand that generates a file to be compiled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Generator {
+  /// C, for `compiled-c`.
+  GenC,
+  /// CUDA, for `compiled-cuda`.
+  GenCu,
 }
 
-/// Generates a file to be compiled.
-fn generate_program<P, Q>(hvm_bin: P, mode: &str, program: Q) -> Result<String>
+impl Generator {
+  fn subcommand(self) -> &'static str {
+    match self {
+      Generator::GenC => "gen-c",
+      Generator::GenCu => "gen-cu",
+    }
+  }
+
+  fn extension(self) -> &'static str {
+    match self {
+      Generator::GenC => "c",
+      Generator::GenCu => "cu",
+    }
+  }
+
+  fn compiler(self, options: &Options) -> &Compiler {
+    match self {
+      Generator::GenC => &options.c_compiler,
+      Generator::GenCu => &options.cuda_compiler,
+    }
+  }
+}
+
+/// Generates a file to be compiled, returning its contents.
+pub fn generate_program<P, Q>(hvm_bin: P, generator: Generator, program: Q, options: &Options) -> Result<String>
 where
   P: AsRef<Path>,
   Q: AsRef<Path>,
 {
-  let output = Command::new(hvm_bin.as_ref())
-    .arg(mode)
+  let output = options
+    .command(hvm_bin.as_ref())
+    .arg(generator.subcommand())
     .arg(program.as_ref())
     .status_stdout();
 
   output
 }
 
-fn compile_and_run(compiler: &str, file: &Path, args: &[&str], timeout: Duration) -> Result<Timing> {
-  let bin_dir = TempDir::with_prefix("hvm-bench-compile-").context("tempdir")?;
-  let binary = bin_dir.path().join("bin");
+/// A compiled program, to run with [`run_binary`] as often as needed. The
+/// binary and the code it was compiled from are deleted when this is dropped.
+pub struct Compiled {
+  dir: TempDir,
+  generator: Generator,
+  /// The program the code was generated from.
+  program: PathBuf,
+  /// How long the compiler took.
+  pub time: Duration,
+}
+
+impl Compiled {
+  pub fn path(&self) -> PathBuf {
+    self.dir.path().join("bin")
+  }
 
-  Command::new(compiler)
-    .arg(file)
-    .args(args)
+  /// Returns the generated code the binary was compiled from.
+  fn source(&self) -> PathBuf {
+    self.dir.path().join(format!("source.{}", self.generator.extension()))
+  }
+}
+
+/// Generates the code for `program` with `generator` and compiles it. Returns
+/// `Ok(Err(timing))` with the timing that stands for every run of it instead
+/// if it can't be run: [`Timing::Unsupported`] if hvm can't generate the
+/// code, or [`Timing::CompileTimeout`] if compiling takes longer than
+/// `timeout`.
+fn compile(
+  hvm_bin: &Path,
+  generator: Generator,
+  program: &Path,
+  timeout: Duration,
+  options: &Options,
+) -> Result<Result<Compiled, Timing>, RunError> {
+  let code = match generate_program(hvm_bin, generator, program, options) {
+    Ok(code) => code,
+    Err(err) if is_unsupported(&err) => return Ok(Err(Timing::Unsupported)),
+    Err(err) => return Err(RunError::Compile(err.context("generate program"))),
+  };
+
+  let mut compiled = Compiled {
+    dir: TempDir::with_prefix("hvm-bench-compile-")
+      .context("tempdir")
+      .map_err(RunError::Other)?,
+    generator,
+    program: program.to_path_buf(),
+    time: Duration::ZERO,
+  };
+  fs::write(compiled.source(), code)
+    .context("write")
+    .map_err(RunError::Other)?;
+
+  let compiler = generator.compiler(options);
+  let start = Instant::now();
+  let output = options
+    .command(&compiler.program)
+    .arg(compiled.source())
+    .args(&compiler.flags)
     .arg("-o")
-    .arg(&binary)
-    .check_success()
-    .context("compile")?;
+    .arg(compiled.path())
+    .status_output_timeout(timeout);
+  compiled.time = start.elapsed();
 
-  let Some(stdout) = Command::new(binary).status_stdout_timeout(timeout)? else {
-    return Ok("timeout".to_string());
+  match output {
+    Ok(Some(_)) => Ok(Ok(compiled)),
+    Ok(None) => Ok(Err(Timing::CompileTimeout)),
+    Err(err) => Err(keep_temp(
+      RunError::Compile(err.context("compile")),
+      &compiled,
+      false,
+      options,
+    )),
+  }
+}
+
+/// Runs a binary compiled by [`compile_c`] or [`compile_cuda`] with `args`,
+/// parsing its timing output, to which the time it took to compile is added.
+pub fn run_binary(
+  compiled: &Compiled,
+  args: &[String],
+  timeout: Duration,
+  options: &Options,
+) -> Result<Timing, RunError> {
+  let run = || run_timed(options.run_command(compiled.path()).args(args), timeout, options);
+  let timing = match compiled.generator {
+    Generator::GenC => run(),
+    Generator::GenCu => detect_throttling(options, run),
   };
+  let timing = timing.map_err(|err| keep_temp(err.context("run"), compiled, true, options))?;
+
+  Ok(timing.with_compile_time(compiled.time))
+}
 
-  parse_stdout(&stdout).context("parse")
+/// Generates C code for `program` and compiles it, without running it, see
+/// [`compile`].
+pub fn compile_c<P, Q>(
+  hvm_bin: P,
+  program: Q,
+  timeout: Duration,
+  options: &Options,
+) -> Result<Result<Compiled, Timing>, RunError>
+where
+  P: AsRef<Path>,
+  Q: AsRef<Path>,
+{
+  compile(hvm_bin.as_ref(), Generator::GenC, program.as_ref(), timeout, options).map_err(|err| err.context("compile c"))
 }
 
-pub fn compiled_c<P, Q>(hvm_bin: P, program: Q, timeout: Duration) -> Result<Timing>
+/// Generates CUDA code for `program` and compiles it, without running it, see
+/// [`compile`].
+pub fn compile_cuda<P, Q>(
+  hvm_bin: P,
+  program: Q,
+  timeout: Duration,
+  options: &Options,
+) -> Result<Result<Compiled, Timing>, RunError>
 where
   P: AsRef<Path>,
   Q: AsRef<Path>,
 {
-  let mut c_file = NamedTempFile::with_suffix(".c")?;
-  let c_code = generate_program(hvm_bin, "gen-c", program).context("generate program")?;
-  c_file.write_all(c_code.as_bytes()).context("write")?;
+  compile(hvm_bin.as_ref(), Generator::GenCu, program.as_ref(), timeout, options)
+    .map_err(|err| err.context("compile cuda"))
+}
+
+/// With [`Options::keep_temp`], keeps the generated source of a failed run of
+/// `compiled`, and its binary if `with_binary`, noting where in `err`.
+fn keep_temp(err: RunError, compiled: &Compiled, with_binary: bool, options: &Options) -> RunError {
+  let Some(keep_dir) = &options.keep_temp else {
+    return err;
+  };
+
+  match keep_files(keep_dir, compiled, with_binary) {
+    Ok(dir) => err.context(format!("generated files kept in {dir:?}")),
+    Err(keep_err) => err.context(format!("couldn't keep the generated files: {keep_err:#}")),
+  }
+}
+
+/// Copies the source of `compiled`, and its binary if `with_binary`, named
+/// after its program, to a new directory in `keep_dir`, returning its path.
+fn keep_files(keep_dir: &Path, compiled: &Compiled, with_binary: bool) -> Result<PathBuf> {
+  fs::create_dir_all(keep_dir).with_context(|| format!("create {keep_dir:?}"))?;
+  let name = (compiled.program.file_stem())
+    .context("program has no name")?
+    .to_string_lossy();
+  // a new directory per run, as the same program fails for several revisions
+  let dir = TempDir::with_prefix_in(format!("{name}-"), keep_dir)
+    .context("create directory")?
+    .into_path();
+
+  let source_path = dir.join(format!("{name}.{}", compiled.generator.extension()));
+  fs::copy(compiled.source(), &source_path).with_context(|| format!("copy {source_path:?}"))?;
+  if with_binary {
+    let binary_path = dir.join(&*name);
+    fs::copy(compiled.path(), &binary_path).with_context(|| format!("copy {binary_path:?}"))?;
+  }
 
-  compile_and_run("gcc", c_file.path(), &["-lm", "-O2"], timeout).context("compile and run")
+  Ok(dir)
 }
 
-pub fn compiled_cuda<P, Q>(hvm_bin: P, program: Q, timeout: Duration) -> Result<Timing>
+/// Returns the commands a run of `program` in `mode` executes, as they'd be
+/// typed in a shell, e.g. for a dry run. Files that only exist while the run
+/// happens are named after the program.
+pub fn commands(hvm_bin: &Path, mode: Mode, program: &Path, args: &[String], options: &Options) -> Vec<String> {
+  let (generator, compiler) = match mode {
+    Mode::CompiledC => (Generator::GenC, &options.c_compiler),
+    Mode::CompiledCuda => (Generator::GenCu, &options.cuda_compiler),
+    Mode::InterpretedC | Mode::InterpretedCuda | Mode::InterpretedRust => {
+      let subcommand = match mode {
+        Mode::InterpretedC => "run-c",
+        Mode::InterpretedCuda => "run-cu",
+        _ => "run",
+      };
+      let mut run = options.run_command(hvm_bin);
+      run.arg(subcommand).arg(program).args(args);
+
+      return vec![shell(&run)];
+    }
+  };
+
+  let source = program.with_extension(generator.extension());
+  let binary = program.with_extension("");
+
+  let mut generate_command = options.command(hvm_bin);
+  generate_command.arg(generator.subcommand()).arg(program);
+  let mut compile = options.command(&compiler.program);
+  compile.arg(&source).args(&compiler.flags).arg("-o").arg(&binary);
+  let mut run = options.run_command(&binary);
+  run.args(args);
+
+  vec![
+    format!("{} > {}", shell(&generate_command), source.display()),
+    shell(&compile),
+    shell(&run),
+  ]
+}
+
+/// Formats `command` as it'd be typed in a shell, quoting the arguments that
+/// need it.
+fn shell(command: &Command) -> String {
+  let quote = |arg: &OsStr| {
+    let arg = arg.to_string_lossy();
+    if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || "'\"$\\".contains(c)) {
+      format!("'{}'", arg.replace('\'', "'\\''"))
+    } else {
+      arg.into_owned()
+    }
+  };
+
+  std::iter::once(command.get_program())
+    .chain(command.get_args())
+    .map(quote)
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// Runs `program` in `mode` under `perf record`, writing the profile to
+/// `output`. Returns the top of `perf report`, i.e. the hottest symbols.
+/// Compiling for a compiled mode, and the profiled run itself, fail if they
+/// take longer than `timeout`; the run is killed with everything it spawned.
+pub fn profile<P, Q>(
+  hvm_bin: P,
+  mode: Mode,
+  program: Q,
+  output: &Path,
+  timeout: Duration,
+  options: &Options,
+) -> Result<String>
 where
   P: AsRef<Path>,
   Q: AsRef<Path>,
 {
-  let mut cu_file = NamedTempFile::with_suffix(".cu")?;
-  let cu_code = generate_program(hvm_bin, "gen-cu", program).context("generate program")?;
-  cu_file.write_all(cu_code.as_bytes()).context("write")?;
+  let mut command = options.command("perf");
+  command.arg("record").arg("-o").arg(output).arg("--");
+
+  // kept alive until the profiled run finishes
+  let compiled = match mode {
+    Mode::CompiledC => Some(compile_c(&hvm_bin, &program, timeout, options)?),
+    Mode::CompiledCuda => Some(compile_cuda(&hvm_bin, &program, timeout, options)?),
+    _ => None,
+  };
+  let compiled = match compiled {
+    Some(Ok(compiled)) => Some(compiled),
+    Some(Err(timing)) => anyhow::bail!("can't profile, compiling gave {timing}"),
+    None => None,
+  };
+
+  match (&compiled, mode) {
+    (Some(compiled), _) => command.arg(compiled.path()),
+    (None, Mode::InterpretedC) => command.arg(hvm_bin.as_ref()).arg("run-c").arg(program.as_ref()),
+    (None, Mode::InterpretedCuda) => command.arg(hvm_bin.as_ref()).arg("run-cu").arg(program.as_ref()),
+    (None, _) => command.arg(hvm_bin.as_ref()).arg("run").arg(program.as_ref()),
+  };
+
+  command
+    .status_stdout_timeout(timeout)
+    .context("perf record")?
+    .with_context(|| format!("perf record timed out after {timeout:?}"))?;
+
+  let report = options
+    .command("perf")
+    .args(["report", "--stdio", "--sort", "symbol", "-i"])
+    .arg(output)
+    .status_stdout()
+    .context("perf report")?;
+
+  let summary = report
+    .lines()
+    .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+    .take(PROFILE_SUMMARY_LINES)
+    .collect::<Vec<_>>()
+    .join("\n");
+
+  Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    fs,
+    os::unix::{fs::PermissionsExt, process::ExitStatusExt},
+    process::ExitStatus,
+  };
+
+  use super::*;
+
+  fn captured(stdout: &str, stderr: &str) -> Captured {
+    Captured {
+      stdout: stdout.to_string(),
+      stderr: stderr.to_string(),
+    }
+  }
+
+  /// Writes an executable `name` into `dir` running `contents`.
+  fn script(dir: &Path, name: &str, contents: &str) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path
+  }
+
+  #[test]
+  fn timing_on_stdout() {
+    let output = captured("Result: 0\n- ITRS: 10\n- TIME: 0.52s\n", "");
+
+    assert_eq!(parse_output(&output).unwrap().raw, "0.52s");
+  }
+
+  #[test]
+  fn timing_on_stderr() {
+    let output = captured("Result: 0\n", "- ITRS: 10\n- TIME: 0.52s\n");
+
+    assert_eq!(parse_output(&output).unwrap().raw, "0.52s");
+  }
+
+  #[test]
+  fn timing_on_stdout_is_preferred() {
+    let output = captured("- TIME: 0.52s\n", "- TIME: 1.00s\n");
+
+    assert_eq!(parse_output(&output).unwrap().raw, "0.52s");
+  }
+
+  #[test]
+  fn no_timing() {
+    let output = captured("Result: 0\n", "warning: something\n");
+
+    assert!(parse_output(&output).is_err());
+  }
+
+  #[test]
+  fn failures_are_told_apart() {
+    let run = |script: &str| {
+      run_timed(
+        Command::new("sh").args(["-c", script]),
+        Duration::from_secs(10),
+        &Options::default(),
+      )
+    };
+
+    assert!(matches!(run("echo '- TIME: 1s'"), Ok(Timing::Completed { .. })));
+    assert!(matches!(run("exit 1"), Err(RunError::Crash(_))));
+    assert!(matches!(run("echo done"), Err(RunError::Parse(_))));
+  }
 
-  compile_and_run("nvcc", cu_file.path(), &["-w", "-O3"], timeout).context("compile and run")
+  #[test]
+  fn timeout_kills_spawned_processes() {
+    let dir = TempDir::new().unwrap();
+    let pid_file = dir.path().join("pid");
+    let script = format!("sleep 30 & echo $! > {}; wait", pid_file.display());
+
+    let timing = run_timed(
+      Command::new("sh").args(["-c", &script]),
+      Duration::from_millis(500),
+      &Options::default(),
+    );
+    assert!(matches!(timing, Ok(Timing::Timeout)));
+
+    // gone, or a zombie waiting to be reaped by init, once the signal lands
+    let pid = fs::read_to_string(&pid_file).unwrap();
+    let dead = || {
+      let stat = fs::read_to_string(format!("/proc/{}/stat", pid.trim())).unwrap_or_default();
+      stat.is_empty() || stat.contains(") Z ")
+    };
+    let start = Instant::now();
+    while !dead() && start.elapsed() < Duration::from_secs(5) {
+      std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(dead());
+  }
+
+  #[test]
+  fn crash_error_has_stderr() {
+    let err = run_timed(
+      Command::new("sh").args(["-c", "echo 'out of memory' >&2; exit 1"]),
+      Duration::from_secs(10),
+      &Options::default(),
+    )
+    .unwrap_err();
+
+    assert!(err.to_string().contains("out of memory"), "{err}");
+  }
+
+  #[test]
+  fn runs_see_the_environment() {
+    let options = Options {
+      env: vec![("SECONDS_TAKEN".to_string(), "1.5".to_string())],
+      ..Options::default()
+    };
+    let timing = run_timed(
+      options
+        .run_command("sh")
+        .args(["-c", "echo \"- TIME: ${SECONDS_TAKEN}s\""]),
+      Duration::from_secs(10),
+      &options,
+    )
+    .unwrap();
+
+    assert_eq!(timing.seconds(), Some(1.5));
+  }
+
+  #[test]
+  fn compile_timeouts_are_told_apart() {
+    let dir = TempDir::new().unwrap();
+    let hvm = script(dir.path(), "hvm", "#!/bin/sh\necho 'int main() {}'\n");
+    let compiler = script(dir.path(), "cc", "#!/bin/sh\nsleep 10\n");
+
+    let options = Options {
+      c_compiler: Compiler::new(&compiler.to_string_lossy(), &[] as &[&str]),
+      ..Options::default()
+    };
+    let compiled = compile_c(&hvm, "main.hvm", Duration::from_millis(200), &options).unwrap();
+
+    assert!(matches!(compiled, Err(Timing::CompileTimeout)));
+  }
+
+  #[test]
+  fn generated_code_is_hvm_stdout() {
+    let dir = TempDir::new().unwrap();
+    let hvm = script(dir.path(), "hvm", "#!/bin/sh\necho \"// $1 $2\"\n");
+
+    let code = generate_program(&hvm, Generator::GenCu, "fib.hvm", &Options::default()).unwrap();
+
+    assert_eq!(code, "// gen-cu fib.hvm\n");
+  }
+
+  #[test]
+  fn failed_runs_keep_their_files() {
+    let dir = TempDir::new().unwrap();
+    let hvm = script(dir.path(), "hvm", "#!/bin/sh\necho 'int main() {}'\n");
+    let compiler = script(
+      dir.path(),
+      "cc",
+      "#!/bin/sh\nprintf '#!/bin/sh\\nexit 1\\n' > \"$3\"\nchmod +x \"$3\"\n",
+    );
+
+    let options = Options {
+      c_compiler: Compiler::new(&compiler.to_string_lossy(), &[] as &[&str]),
+      keep_temp: Some(dir.path().join("kept")),
+      ..Options::default()
+    };
+    let Ok(Ok(compiled)) = compile_c(&hvm, "fib.hvm", Duration::from_secs(10), &options) else {
+      panic!("fib didn't compile");
+    };
+    let err = run_binary(&compiled, &[], Duration::from_secs(10), &options).unwrap_err();
+
+    let kept = fs::read_dir(dir.path().join("kept"))
+      .unwrap()
+      .next()
+      .unwrap()
+      .unwrap()
+      .path();
+    assert!(err.to_string().contains(&format!("{kept:?}")), "{err}");
+    assert_eq!(fs::read_to_string(kept.join("fib.c")).unwrap(), "int main() {}\n");
+    assert!(kept.join("fib").exists());
+  }
+
+  #[test]
+  fn dry_run_commands() {
+    let program = Path::new("programs/fib.hvm");
+    let args = ["20".to_string()];
+    let options = Options::default();
+
+    assert_eq!(
+      commands(Path::new("hvm"), Mode::InterpretedC, program, &args, &options),
+      ["hvm run-c programs/fib.hvm 20"]
+    );
+    assert_eq!(
+      commands(Path::new("hvm"), Mode::CompiledC, program, &args, &options),
+      [
+        "hvm gen-c programs/fib.hvm > programs/fib.c",
+        "gcc programs/fib.c -lm -O2 -o programs/fib",
+        "programs/fib 20"
+      ]
+    );
+  }
+
+  #[test]
+  #[cfg(target_os = "linux")]
+  fn pinned_runs() {
+    let program = Path::new("programs/fib.hvm");
+    let options = Options {
+      cpu_affinity: Some("2,3".to_string()),
+      measure_memory: true,
+      ..Options::default()
+    };
+
+    assert_eq!(
+      commands(Path::new("hvm"), Mode::InterpretedC, program, &[], &options),
+      ["taskset -c 2,3 /usr/bin/time -f 'Maximum resident set size (kbytes): %M' hvm run-c programs/fib.hvm"]
+    );
+  }
+
+  #[test]
+  fn unsupported_mode() {
+    let err = anyhow::Error::from(ExitError {
+      status: ExitStatus::from_raw(2 << 8),
+      stderr: "error: unrecognized subcommand 'run-cu'\n".to_string(),
+    })
+    .context("generate program");
+
+    assert!(is_unsupported(&err));
+  }
+
+  #[test]
+  fn crash_is_not_unsupported() {
+    let err = anyhow::Error::from(ExitError {
+      status: ExitStatus::from_raw(139),
+      stderr: String::new(),
+    });
+
+    assert!(!is_unsupported(&err));
+  }
+
+  #[test]
+  fn malformed_stdout() {
+    // (stdout, timing, interactions, result)
+    let fixtures = [
+      // hvm 2.0
+      (
+        "Result: 5000050000\n- ITRS: 2600042\n- TIME: 0.03s\n- MIPS: 86.67\n",
+        "0.03s",
+        Some(2600042),
+        "Result: 5000050000",
+      ),
+      // windows line endings
+      (
+        "Result: 0\r\n- ITRS: 10\r\n- TIME: 0.52s\r\n",
+        "0.52s",
+        Some(10),
+        "Result: 0",
+      ),
+      (
+        "- TIME: 1.00s\n- TIME: 2.00s\n- ITRS: 1\n- ITRS: 2\n",
+        "1.00s",
+        Some(1),
+        "",
+      ),
+      ("- TIME:   0.50s  \t\n", "0.50s", None, ""),
+      (
+        "Result: - TIME: 9.00s\n- TIME: 1.00s\n",
+        "1.00s",
+        None,
+        "Result: - TIME: 9.00s",
+      ),
+    ];
+
+    for (stdout, timing, interactions, result) in fixtures {
+      let reported = parse_stdout(stdout).unwrap();
+
+      assert_eq!(reported.raw, timing, "{stdout:?}");
+      assert_eq!(reported.interactions, interactions, "{stdout:?}");
+      assert_eq!(reported.output.as_deref(), Some(result), "{stdout:?}");
+    }
+
+    assert!(parse_stdout("  - TIME: 1.00s\n").is_err());
+    assert!(parse_stdout("Took - TIME: 1.00s\n").is_err());
+  }
+
+  #[test]
+  fn printed_timeouts_are_not_timeouts() {
+    let output = captured("timeout\n- TIME: 0.10s\n", "");
+    let reported = parse_output(&output).unwrap();
+    assert_eq!(reported.output.as_deref(), Some("timeout"));
+
+    // only the harness decides that a run timed out, see `Timing::Timeout`
+    assert!(parse_output(&captured("- TIME: timeout\n", "")).is_err());
+  }
+
+  #[test]
+  fn timing_units() {
+    for (stdout, seconds) in [
+      ("- TIME: 3.21s", 3.21),
+      ("- TIME: 950ms", 0.95),
+      ("- TIME: 1.2us", 1.2e-6),
+    ] {
+      let reported = parse_stdout(stdout).unwrap();
+
+      assert!((reported.seconds - seconds).abs() < 1e-12, "{stdout:?}");
+      assert_eq!(format!("{TIME_PREFIX}{}", reported.raw), stdout);
+    }
+  }
+
+  #[test]
+  fn interactions_and_mips() {
+    let reported = parse_stdout("Result: 0\n- ITRS: 1234\n- TIME: 0.50s\n- MIPS: 2.47\n").unwrap();
+
+    assert_eq!(reported.interactions, Some(1234));
+    assert_eq!(reported.mips, Some(2.47));
+    assert_eq!(reported.output.as_deref(), Some("Result: 0"));
+
+    let reported = parse_stdout("- TIME: 0.50s\n").unwrap();
+
+    assert_eq!((reported.interactions, reported.mips), (None, None));
+  }
+
+  #[test]
+  fn peak_memory() {
+    let stderr = "\tCommand being timed: \"hvm run sum_rec.hvm\"\n\tMaximum resident set size (kbytes): 2048\n";
+
+    assert_eq!(parse_peak_memory(stderr), Some(2 << 20));
+    assert_eq!(parse_peak_memory("- TIME: 0.50s\n"), None);
+  }
+
+  #[test]
+  fn throttle_reasons() {
+    assert!(!parse_throttle_reasons("Not Active, Not Active\n"));
+    assert!(parse_throttle_reasons("Not Active, Not Active\nNot Active, Active\n"));
+    assert!(!parse_throttle_reasons(""));
+  }
+
+  #[test]
+  fn perf_counters() {
+    let stderr = "- TIME: 0.50s\n1520334,,cycles:u,1005530,100.00,,\n2881104,,instructions:u,1005530,100.00,1.90,insn per cycle\n<not supported>,,cache-misses:u,0,100.00,,\n";
+    let counters = Counters {
+      cycles: Some(1520334),
+      instructions: Some(2881104),
+      cache_misses: None,
+    };
+    assert_eq!(parse_counters(stderr), Some(counters));
+
+    assert_eq!(parse_counters("- TIME: 0.50s\nResult: 1,2,3\n"), None);
+  }
+
+  #[test]
+  fn invalid_timing() {
+    assert!(parse_stdout("- TIME: soon").is_err());
+  }
 }
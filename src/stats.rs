@@ -1,44 +1,772 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, fmt, time::Duration};
 
 use anyhow::Result;
+use serde::{ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
 
-/// The time reported by `hvmc`, unparsed.
-pub type Timing = String;
+use crate::run::RunError;
+
+/// Serializes a duration as fractional seconds.
+fn serialize_seconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+  serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// Serializes an optional duration as fractional seconds.
+fn serialize_optional_seconds<S: Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+  match duration {
+    Some(duration) => serialize_seconds(duration, serializer),
+    None => serializer.serialize_none(),
+  }
+}
+
+/// Deserializes an optional duration from fractional seconds.
+fn deserialize_optional_seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+  Option::<f64>::deserialize(deserializer)?
+    .map(|seconds| Duration::try_from_secs_f64(seconds).map_err(serde::de::Error::custom))
+    .transpose()
+}
+
+/// Deserializes a duration from fractional seconds.
+fn deserialize_seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+  let seconds = f64::deserialize(deserializer)?;
+
+  Duration::try_from_secs_f64(seconds).map_err(serde::de::Error::custom)
+}
+
+/// A time reported by `hvmc`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Reported {
+  /// The reported time, normalized to seconds.
+  pub seconds: f64,
+  /// The reported time as printed, e.g. `3.21s`.
+  pub raw: String,
+  /// The reported number of interactions, which unlike the time doesn't
+  /// depend on the machine.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub interactions: Option<u64>,
+  /// The reported millions of interactions per second.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub mips: Option<f64>,
+  /// What the program printed besides the statistics, i.e. its result.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub output: Option<String>,
+}
+
+impl Reported {
+  pub fn parse(raw: &str) -> Option<Reported> {
+    Some(Reported {
+      seconds: parse_seconds(raw)?,
+      raw: raw.trim().to_string(),
+      interactions: None,
+      mips: None,
+      output: None,
+    })
+  }
+}
+
+/// The outcome of a run that didn't fail.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Timing {
+  /// The run finished, taking `wall` of wall-clock time including process
+  /// startup.
+  Completed {
+    reported: Reported,
+    #[serde(serialize_with = "serialize_seconds", deserialize_with = "deserialize_seconds")]
+    wall: Duration,
+    /// Peak resident set size in bytes, if memory was measured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    peak_memory: Option<u64>,
+    /// Time the compiler took, for the compiled modes.
+    #[serde(
+      default,
+      skip_serializing_if = "Option::is_none",
+      serialize_with = "serialize_optional_seconds",
+      deserialize_with = "deserialize_optional_seconds"
+    )]
+    compile_time: Option<Duration>,
+    /// Hardware counters of the run, if it was counted with `perf stat`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    counters: Option<Counters>,
+    /// Whether the GPU was slowed down to cool off before or after the run,
+    /// see [`Options::detect_throttling`](crate::run::Options::detect_throttling).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    throttled: bool,
+  },
+  /// The run didn't finish before the timeout.
+  Timeout,
+  /// The compiler didn't finish before the timeout, so the program didn't run.
+  CompileTimeout,
+  /// The `hvm` revision doesn't support the mode.
+  Unsupported,
+  /// The mode wasn't attempted because the machine can't run it.
+  Skipped,
+}
+
+impl Timing {
+  /// Returns the reported seconds of a completed run.
+  pub fn seconds(&self) -> Option<f64> {
+    match self {
+      Timing::Completed { reported, .. } => Some(reported.seconds),
+      Timing::Timeout | Timing::CompileTimeout | Timing::Unsupported | Timing::Skipped => None,
+    }
+  }
+
+  /// Returns the seconds the run is ranked by against others: the reported
+  /// seconds if it completed, or infinity if it timed out, since it took
+  /// longer than any run that completed. Runs that didn't happen, i.e.
+  /// unsupported or skipped ones, aren't ranked.
+  pub fn rank_seconds(&self) -> Option<f64> {
+    match self {
+      Timing::Timeout | Timing::CompileTimeout => Some(f64::INFINITY),
+      _ => self.seconds(),
+    }
+  }
+
+  /// Returns the timing with the compile time `time`, if the run completed.
+  pub fn with_compile_time(mut self, time: Duration) -> Self {
+    if let Timing::Completed { compile_time, .. } = &mut self {
+      *compile_time = Some(time);
+    }
+
+    self
+  }
+
+  /// Returns the timing marked as throttled if `throttled`, and the run
+  /// completed.
+  pub fn with_throttled(mut self, throttled: bool) -> Self {
+    if let Timing::Completed { throttled: marked, .. } = &mut self {
+      *marked |= throttled;
+    }
+
+    self
+  }
+
+  /// Returns whether the run completed while the GPU was throttled.
+  pub fn is_throttled(&self) -> bool {
+    matches!(self, Timing::Completed { throttled: true, .. })
+  }
+}
+
+/// Hardware events counted during a run by `perf stat`, see
+/// [`Options::perf`](crate::run::Options::perf). Events the CPU or kernel
+/// doesn't count, e.g. in some virtual machines, are `None`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Counters {
+  pub cycles: Option<u64>,
+  pub instructions: Option<u64>,
+  pub cache_misses: Option<u64>,
+}
+
+impl fmt::Display for Timing {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Timing::Completed { reported, .. } => f.write_str(&reported.raw),
+      Timing::Timeout => f.write_str("timeout"),
+      Timing::CompileTimeout => f.write_str("compile-timeout"),
+      Timing::Unsupported => f.write_str("unsupported"),
+      Timing::Skipped => f.write_str("n/a"),
+    }
+  }
+}
+
+/// Duration suffixes understood by [`parse_seconds`], and their value in
+/// seconds. Longer suffixes come first so that `ms` is not mistaken for `s`.
+const UNITS: [(&str, f64); 5] = [("ns", 1e-9), ("us", 1e-6), ("µs", 1e-6), ("ms", 1e-3), ("s", 1.0)];
+
+/// Parses a duration like `3.21s`, `950ms` or `1.2us` into seconds. A number
+/// without a suffix is taken to be in seconds.
+pub fn parse_seconds(duration: &str) -> Option<f64> {
+  let duration = duration.trim();
+
+  for (suffix, scale) in UNITS {
+    if let Some(value) = duration.strip_suffix(suffix) {
+      return value.trim_end().parse::<f64>().ok().map(|value| value * scale);
+    }
+  }
+
+  duration.parse().ok()
+}
+
+/// Rounds `seconds` to the nearest multiple of `floor`.
+pub fn round_seconds(seconds: f64, floor: Duration) -> f64 {
+  let floor = floor.as_secs_f64();
+  if floor == 0.0 {
+    return seconds;
+  }
+
+  (seconds / floor).round() * floor
+}
+
+/// How the timings of repeated runs are combined into one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Aggregate {
+  Mean,
+  #[default]
+  Median,
+  Min,
+  Max,
+}
+
+/// Combines `values` with `aggregate`, or returns `None` if there are none.
+pub fn aggregate(values: &[f64], aggregate: Aggregate) -> Option<f64> {
+  if values.is_empty() {
+    return None;
+  }
+
+  let mut sorted = values.to_vec();
+  sorted.sort_by(f64::total_cmp);
+  let middle = sorted.len() / 2;
+
+  let value = match aggregate {
+    Aggregate::Mean => sorted.iter().sum::<f64>() / sorted.len() as f64,
+    Aggregate::Median if sorted.len().is_multiple_of(2) => (sorted[middle - 1] + sorted[middle]) / 2.0,
+    Aggregate::Median => sorted[middle],
+    Aggregate::Min => sorted[0],
+    Aggregate::Max => sorted[sorted.len() - 1],
+  };
+
+  Some(value)
+}
+
+/// Returns the sample standard deviation of `values` and their coefficient of
+/// variation, i.e. the standard deviation relative to the mean, or `None` if
+/// there are fewer than two.
+pub fn variation(values: &[f64]) -> Option<(f64, f64)> {
+  if values.len() < 2 {
+    return None;
+  }
+
+  let mean = values.iter().sum::<f64>() / values.len() as f64;
+  let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+  let std_dev = variance.sqrt();
+
+  let coefficient = if mean == 0.0 { 0.0 } else { std_dev / mean };
+
+  Some((std_dev, coefficient))
+}
+
+/// Returns the interval the mean of the population `values` were sampled
+/// from lies in with about 95% confidence, i.e. the mean of `values` give or
+/// take two standard errors, or `None` if there are fewer than two.
+pub fn confidence_interval(values: &[f64]) -> Option<(f64, f64)> {
+  let (std_dev, _) = variation(values)?;
+  let mean = values.iter().sum::<f64>() / values.len() as f64;
+  let margin = 2.0 * std_dev / (values.len() as f64).sqrt();
+
+  Some((mean - margin, mean + margin))
+}
+
+/// Combines the timings of repeated runs of the same program and mode. A
+/// single run is returned as is. Otherwise, the cell is a timeout if most runs
+/// timed out, and the completed runs are aggregated if not.
+pub fn aggregate_timings(timings: &[Timing], aggregate: Aggregate) -> Timing {
+  if let [timing] = timings {
+    return timing.clone();
+  }
+
+  if let Some(timing) = timings
+    .iter()
+    .find(|timing| matches!(timing, Timing::Unsupported | Timing::Skipped))
+  {
+    return timing.clone();
+  }
+
+  let completed = timings
+    .iter()
+    .filter_map(|timing| match timing {
+      Timing::Completed {
+        reported,
+        wall,
+        peak_memory,
+        compile_time,
+        counters,
+        throttled,
+      } => Some((reported, wall, peak_memory, compile_time, counters, throttled)),
+      Timing::Timeout | Timing::CompileTimeout | Timing::Unsupported | Timing::Skipped => None,
+    })
+    .collect::<Vec<_>>();
+  let seconds = completed
+    .iter()
+    .map(|(reported, ..)| reported.seconds)
+    .collect::<Vec<_>>();
+  let walls = (completed.iter())
+    .map(|(_, wall, ..)| wall.as_secs_f64())
+    .collect::<Vec<_>>();
+  // only aggregated if every completed run has them
+  let interactions = (completed.iter())
+    .map(|(reported, ..)| reported.interactions.map(|interactions| interactions as f64))
+    .collect::<Option<Vec<_>>>();
+  let mips = (completed.iter())
+    .map(|(reported, ..)| reported.mips)
+    .collect::<Option<Vec<_>>>();
+  let peak_memories = (completed.iter())
+    .map(|(_, _, peak_memory, ..)| peak_memory.map(|bytes| bytes as f64))
+    .collect::<Option<Vec<_>>>();
+  let compile_times = (completed.iter())
+    .map(|(.., compile_time, _, _)| compile_time.map(|time| time.as_secs_f64()))
+    .collect::<Option<Vec<_>>>();
+
+  let counter = |counter: fn(&Counters) -> Option<u64>| {
+    let values = (completed.iter())
+      .map(|(.., counters, _)| counters.as_ref().and_then(counter).map(|value| value as f64))
+      .collect::<Option<Vec<_>>>()?;
+    self::aggregate(&values, aggregate).map(|value| value.round() as u64)
+  };
+  let counters = (completed.iter().all(|(.., counters, _)| counters.is_some())).then(|| Counters {
+    cycles: counter(|counters| counters.cycles),
+    instructions: counter(|counters| counters.instructions),
+    cache_misses: counter(|counters| counters.cache_misses),
+  });
+
+  if seconds.len() * 2 < timings.len() {
+    if timings.iter().any(|timing| matches!(timing, Timing::CompileTimeout)) {
+      return Timing::CompileTimeout;
+    }
+
+    return Timing::Timeout;
+  }
+
+  let (Some(seconds), Some(wall)) = (self::aggregate(&seconds, aggregate), self::aggregate(&walls, aggregate)) else {
+    return Timing::Timeout;
+  };
+
+  Timing::Completed {
+    reported: Reported {
+      seconds,
+      raw: format!("{seconds:.3}s"),
+      interactions: interactions
+        .and_then(|interactions| self::aggregate(&interactions, aggregate))
+        .map(|interactions| interactions.round() as u64),
+      mips: mips.and_then(|mips| self::aggregate(&mips, aggregate)),
+      output: completed.iter().find_map(|(reported, ..)| reported.output.clone()),
+    },
+    wall: Duration::from_secs_f64(wall),
+    peak_memory: peak_memories
+      .and_then(|peak_memories| self::aggregate(&peak_memories, aggregate))
+      .map(|bytes| bytes.round() as u64),
+    compile_time: compile_times
+      .and_then(|compile_times| self::aggregate(&compile_times, aggregate))
+      .map(Duration::from_secs_f64),
+    counters,
+    throttled: completed.iter().any(|(.., throttled)| **throttled),
+  }
+}
+
+/// A way of running an hvm program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+  CompiledC,
+  CompiledCuda,
+  InterpretedC,
+  InterpretedCuda,
+  InterpretedRust,
+}
+
+impl Mode {
+  pub const ALL: [Mode; 5] = [
+    Mode::CompiledC,
+    Mode::CompiledCuda,
+    Mode::InterpretedC,
+    Mode::InterpretedCuda,
+    Mode::InterpretedRust,
+  ];
+
+  pub fn is_compiled(self) -> bool {
+    matches!(self, Mode::CompiledC | Mode::CompiledCuda)
+  }
+
+  pub fn is_cuda(self) -> bool {
+    matches!(self, Mode::CompiledCuda | Mode::InterpretedCuda)
+  }
+
+  /// The runtime a program runs on in this mode, e.g. `cuda`.
+  pub fn runtime(self) -> &'static str {
+    match self {
+      Mode::CompiledC | Mode::InterpretedC => "c",
+      Mode::CompiledCuda | Mode::InterpretedCuda => "cuda",
+      Mode::InterpretedRust => "rust",
+    }
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Mode::CompiledC => "compiled-c",
+      Mode::CompiledCuda => "compiled-cuda",
+      Mode::InterpretedC => "interpreted-c",
+      Mode::InterpretedCuda => "interpreted-cuda",
+      Mode::InterpretedRust => "interpreted-rust",
+    }
+  }
+}
+
+impl fmt::Display for Mode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.name())
+  }
+}
+
+/// An advisory about the reliability of the results, collected while
+/// benchmarking and shown after them.
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Warning {
+  /// The system was already busy when benchmarking started.
+  HighLoad { load: f64, cpus: usize },
+  /// `nvcc` or a GPU is missing, so the CUDA modes are skipped.
+  CudaUnavailable,
+  /// A remote revision failed to build, so it isn't benchmarked.
+  BuildFailed { rev: String, error: String },
+  /// A compiler needed by the compiled modes could not be run.
+  MissingCompiler { compiler: String },
+  /// A run finished close to the timeout, so it may time out on a slower
+  /// revision or a noisier run.
+  NearTimeout {
+    rev: String,
+    program: String,
+    mode: Mode,
+    seconds: f64,
+  },
+  /// The result `program` printed in `mode` in `revs` differs from the one it
+  /// printed in `reference`.
+  OutputMismatch {
+    program: String,
+    mode: Mode,
+    reference: String,
+    revs: Vec<String>,
+  },
+  /// The wall-clock time of a run differs a lot from the time hvm reported,
+  /// e.g. because of startup overhead or a misreported timer.
+  WallClockMismatch {
+    rev: String,
+    program: String,
+    mode: Mode,
+    reported: f64,
+    wall: f64,
+  },
+  /// The binary of a revision reported a different version than the one in
+  /// the revision's `Cargo.toml`, so it may be stale, e.g. a build that
+  /// didn't replace an older binary.
+  VersionMismatch {
+    rev: String,
+    expected: String,
+    actual: String,
+  },
+  /// `perf stat` could not count, so runs aren't counted.
+  PerfUnavailable,
+  /// The GPU was thermally throttled around `runs` runs of `program` in
+  /// `mode`, so its timings are likely slower than they'd otherwise be.
+  Throttled {
+    rev: String,
+    program: String,
+    mode: Mode,
+    runs: usize,
+  },
+  /// The runs of `program` in `mode` varied a lot, with a coefficient of
+  /// variation of `coefficient`, so its timings are unreliable.
+  HighVariance {
+    rev: String,
+    program: String,
+    mode: Mode,
+    coefficient: f64,
+  },
+}
+
+impl fmt::Display for Warning {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Warning::HighLoad { load, cpus } => write!(f, "load average {load:.2} is high for {cpus} cpus"),
+      Warning::BuildFailed { rev, error } => write!(f, "{rev} failed to build and was not benchmarked: {error}"),
+      Warning::CudaUnavailable => write!(f, "cuda is not available, cuda modes are skipped"),
+      Warning::MissingCompiler { compiler } => write!(f, "{compiler} is not available, compiled modes will fail"),
+      Warning::NearTimeout {
+        rev,
+        program,
+        mode,
+        seconds,
+      } => write!(f, "{rev} {program} {mode} took {seconds:.3}s, close to the timeout"),
+      Warning::OutputMismatch {
+        program,
+        mode,
+        reference,
+        revs,
+      } => write!(
+        f,
+        "{program} {mode} printed a different result in {} than in {reference}",
+        revs.join(", ")
+      ),
+      Warning::WallClockMismatch {
+        rev,
+        program,
+        mode,
+        reported,
+        wall,
+      } => write!(
+        f,
+        "{rev} {program} {mode} reported {reported:.3}s but took {wall:.3}s of wall-clock time"
+      ),
+      Warning::VersionMismatch { rev, expected, actual } => write!(
+        f,
+        "{rev}'s binary reports version {actual} but its Cargo.toml has {expected}, so it may be stale"
+      ),
+      Warning::PerfUnavailable => write!(
+        f,
+        "perf stat could not count events, runs are not counted; is perf installed and kernel.perf_event_paranoid low enough?"
+      ),
+      Warning::Throttled {
+        rev,
+        program,
+        mode,
+        runs,
+      } => write!(
+        f,
+        "the gpu was thermally throttled during {runs} {} of {rev} {program} {mode}, so it may be slower",
+        if *runs == 1 { "run" } else { "runs" }
+      ),
+      Warning::HighVariance {
+        rev,
+        program,
+        mode,
+        coefficient,
+      } => write!(
+        f,
+        "{rev} {program} {mode} varied by ±{:.1}% across runs, so its timings are unreliable",
+        coefficient * 100.0
+      ),
+    }
+  }
+}
+
+/// Given the result each revision printed, in order, returns the first of them
+/// and the revisions whose result differs from it, or `None` if all agree.
+pub fn output_mismatch<'a, 'b, I>(outputs: I) -> Option<(&'a str, Vec<&'a str>)>
+where
+  I: IntoIterator<Item = (&'a str, &'b str)>,
+{
+  let mut outputs = outputs.into_iter();
+  let (reference, reference_output) = outputs.next()?;
+  let mismatched = outputs
+    .filter(|(_, output)| *output != reference_output)
+    .map(|(rev, _)| rev)
+    .collect::<Vec<_>>();
+
+  (!mismatched.is_empty()).then_some((reference, mismatched))
+}
+
+/// Returns the geometric mean of `values`, or `None` if there are none.
+pub fn geomean<I: IntoIterator<Item = f64>>(values: I) -> Option<f64> {
+  let (count, log_sum) = values
+    .into_iter()
+    .fold((0, 0.0), |(count, log_sum), value| (count + 1, log_sum + value.ln()));
+
+  (count > 0).then(|| (log_sum / count as f64).exp())
+}
 
 /// Statistics for various programs, meant to represent the overall benchmarking
 /// results for a single revision.
-#[derive(Default)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct Stats {
+  /// When the revision was committed, in seconds since the epoch, or `None`
+  /// for a local repo.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub committed_at: Option<u64>,
+  /// The version the revision's binary reported with `--version`, if it did.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub version: Option<String>,
+  /// The category of each program in a subdirectory of the programs
+  /// directory, e.g. `sorting`. Programs at the top have none.
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub categories: BTreeMap<String, String>,
+  /// Results of each program, keyed by name. Not flattened into the other
+  /// fields, as a program may be named like one of them, e.g. `version`.
   pub programs: BTreeMap<String, Program>,
 }
 
+/// The timings of every run of a program in a mode, or why they stopped.
+pub type Runs = Result<Vec<Timing>, RunError>;
+
 /// Runtime statistics for a single hvm program, across all interpreted and
-/// compiled runtimes.
+/// compiled runtimes. Each mode holds the timings of all of its runs, or is
+/// `None` if it wasn't selected.
+#[derive(Default)]
 pub struct Program {
-  pub compiled_c: Result<Timing>,
-  pub compiled_cuda: Result<Timing>,
-  pub interpreted_c: Result<Timing>,
-  pub interpreted_cuda: Result<Timing>,
-  pub interpreted_rust: Result<Timing>,
+  pub compiled_c: Option<Runs>,
+  pub compiled_cuda: Option<Runs>,
+  pub interpreted_c: Option<Runs>,
+  pub interpreted_cuda: Option<Runs>,
+  pub interpreted_rust: Option<Runs>,
+}
+
+impl Clone for Program {
+  /// Errors are cloned by their message, dropping their source chain.
+  fn clone(&self) -> Self {
+    fn clone_result(result: &Option<Runs>) -> Option<Runs> {
+      match result.as_ref()? {
+        Ok(timings) => Some(Ok(timings.clone())),
+        Err(err) => Some(Err(err.to_owned_message())),
+      }
+    }
+
+    Self {
+      compiled_c: clone_result(&self.compiled_c),
+      compiled_cuda: clone_result(&self.compiled_cuda),
+      interpreted_c: clone_result(&self.interpreted_c),
+      interpreted_cuda: clone_result(&self.interpreted_cuda),
+      interpreted_rust: clone_result(&self.interpreted_rust),
+    }
+  }
+}
+
+/// Serialized as a map from each selected mode to either `{"runs": [...]}` or
+/// `{"error": "...", "failure": "crash"}`, where `failure` is the label of the
+/// [`RunError`].
+impl Serialize for Program {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    enum Cell<'a> {
+      Runs { runs: &'a [Timing] },
+      Error { error: String, failure: &'static str },
+    }
+
+    let mut map = serializer.serialize_map(None)?;
+    for mode in Mode::ALL {
+      let cell = match self.mode(mode) {
+        Some(Ok(timings)) => Cell::Runs { runs: timings },
+        Some(Err(err)) => Cell::Error {
+          error: err.to_string(),
+          failure: err.label(),
+        },
+        None => continue,
+      };
+
+      map.serialize_entry(&mode, &cell)?;
+    }
+
+    map.end()
+  }
+}
+
+/// Deserialized from the map written by its [`Serialize`] impl. Errors keep only
+/// their message.
+impl<'de> Deserialize<'de> for Program {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Cell {
+      Runs {
+        runs: Vec<Timing>,
+      },
+      Error {
+        error: String,
+        #[serde(default)]
+        failure: String,
+      },
+    }
+
+    let mut program = Program::default();
+    for (mode, cell) in BTreeMap::<Mode, Cell>::deserialize(deserializer)? {
+      *program.mode_mut(mode) = Some(match cell {
+        Cell::Runs { runs } => Ok(runs),
+        Cell::Error { error, failure } => Err(RunError::from_label(&failure, error)),
+      });
+    }
+
+    Ok(program)
+  }
 }
 
 impl Program {
-  pub fn compiled(&self, runtime: &str) -> &Result<Timing> {
-    match runtime {
-      "c" => &self.compiled_c,
-      "cuda" => &self.compiled_cuda,
+  pub fn mode(&self, mode: Mode) -> Option<&Runs> {
+    match mode {
+      Mode::CompiledC => self.compiled_c.as_ref(),
+      Mode::CompiledCuda => self.compiled_cuda.as_ref(),
+      Mode::InterpretedC => self.interpreted_c.as_ref(),
+      Mode::InterpretedCuda => self.interpreted_cuda.as_ref(),
+      Mode::InterpretedRust => self.interpreted_rust.as_ref(),
+    }
+  }
 
-      _ => panic!("unexpected runtime: {runtime}"),
+  pub fn mode_mut(&mut self, mode: Mode) -> &mut Option<Runs> {
+    match mode {
+      Mode::CompiledC => &mut self.compiled_c,
+      Mode::CompiledCuda => &mut self.compiled_cuda,
+      Mode::InterpretedC => &mut self.interpreted_c,
+      Mode::InterpretedCuda => &mut self.interpreted_cuda,
+      Mode::InterpretedRust => &mut self.interpreted_rust,
     }
   }
 
-  pub fn interpreted(&self, runtime: &str) -> &Result<Timing> {
-    match runtime {
-      "c" => &self.interpreted_c,
-      "cuda" => &self.interpreted_cuda,
-      "rust" => &self.interpreted_rust,
+  /// Returns the timing of `mode`, aggregated over its runs, or `None` if it
+  /// wasn't selected.
+  pub fn timing(&self, mode: Mode, aggregate: Aggregate) -> Option<Result<Timing, &RunError>> {
+    let timings = self.mode(mode)?.as_ref();
+
+    Some(timings.map(|timings| aggregate_timings(timings, aggregate)))
+  }
+
+  /// Returns the result printed by the first completed run in `mode`.
+  pub fn output(&self, mode: Mode) -> Option<&str> {
+    let timings = self.mode(mode)?.as_ref().ok()?;
 
-      _ => panic!("unexpected runtime: {runtime}"),
+    timings.iter().find_map(|timing| match timing {
+      Timing::Completed { reported, .. } => reported.output.as_deref(),
+      _ => None,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn completed(seconds: f64) -> Timing {
+    Timing::Completed {
+      reported: Reported::parse(&format!("{seconds}s")).unwrap(),
+      wall: Duration::from_secs_f64(seconds),
+      peak_memory: None,
+      compile_time: None,
+      counters: None,
+      throttled: false,
     }
   }
+
+  #[test]
+  fn values_are_aggregated() {
+    let odd = [3.0, 1.0, 2.0];
+    let even = [4.0, 1.0, 3.0, 2.0];
+
+    assert_eq!(aggregate(&odd, Aggregate::Mean), Some(2.0));
+    assert_eq!(aggregate(&odd, Aggregate::Median), Some(2.0));
+    assert_eq!(aggregate(&odd, Aggregate::Min), Some(1.0));
+    assert_eq!(aggregate(&odd, Aggregate::Max), Some(3.0));
+
+    assert_eq!(aggregate(&even, Aggregate::Mean), Some(2.5));
+    assert_eq!(aggregate(&even, Aggregate::Median), Some(2.5));
+    assert_eq!(aggregate(&even, Aggregate::Min), Some(1.0));
+    assert_eq!(aggregate(&even, Aggregate::Max), Some(4.0));
+
+    assert_eq!(aggregate(&[], Aggregate::Median), None);
+  }
+
+  #[test]
+  fn timings_with_some_timeouts_are_aggregated() {
+    let seconds = |timings: &[Timing], aggregate: Aggregate| aggregate_timings(timings, aggregate).seconds();
+
+    let timings = [completed(3.0), completed(1.0), completed(2.0)];
+    assert_eq!(seconds(&timings, Aggregate::Median), Some(2.0));
+    assert_eq!(seconds(&timings, Aggregate::Max), Some(3.0));
+
+    // the timed out runs are left out, as long as most runs completed
+    let timings = [completed(4.0), Timing::Timeout, completed(1.0), completed(3.0)];
+    assert_eq!(seconds(&timings, Aggregate::Median), Some(3.0));
+    assert_eq!(seconds(&timings, Aggregate::Mean), Some(8.0 / 3.0));
+    assert_eq!(seconds(&timings, Aggregate::Min), Some(1.0));
+
+    let timings = [Timing::Timeout, completed(1.0), Timing::Timeout];
+    assert!(matches!(
+      aggregate_timings(&timings, Aggregate::Median),
+      Timing::Timeout
+    ));
+  }
 }